@@ -34,8 +34,12 @@ pub fn empty_scene_benchmark(c: &mut Criterion) {
             ..Camera::default()
         },
     );
-    scene.load_assets(Path::new(""));
-    let empty_scene = scene.build_raytracing_scene();
+    scene
+        .load_assets(Path::new(""))
+        .expect("failed to load assets");
+    let empty_scene = scene
+        .build_raytracing_scene()
+        .expect("failed to build scene");
 
     let mut group = c.benchmark_group("Empty scene");
     for coordinates in &COORDINATES {
@@ -52,8 +56,12 @@ pub fn simple_scene_benchmark(c: &mut Criterion) {
     let scene_path = Path::new("scenes/benchmarks/simple.json");
     let scene_file = File::open(scene_path).expect("file not found");
     let mut scene: Scene = serde_json::from_reader(scene_file).expect("failed to parse scene");
-    scene.load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")));
-    let simple_scene = scene.build_raytracing_scene();
+    scene
+        .load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")))
+        .expect("failed to load assets");
+    let simple_scene = scene
+        .build_raytracing_scene()
+        .expect("failed to build scene");
 
     let mut group = c.benchmark_group("Simple scene");
     for coordinates in &COORDINATES {
@@ -70,8 +78,12 @@ pub fn complex_scene_benchmark(c: &mut Criterion) {
     let scene_path = Path::new("scenes/benchmarks/complex.json");
     let scene_file = File::open(scene_path).expect("file not found");
     let mut scene: Scene = serde_json::from_reader(scene_file).expect("failed to parse scene");
-    scene.load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")));
-    let complex_scene = scene.build_raytracing_scene();
+    scene
+        .load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")))
+        .expect("failed to load assets");
+    let complex_scene = scene
+        .build_raytracing_scene()
+        .expect("failed to build scene");
 
     let mut group = c.benchmark_group("Complex scene");
     for coordinates in &COORDINATES {
@@ -1,12 +1,15 @@
-use super::{Axis, Transform};
+use super::{AnimatedTransform, Axis, Transform};
 use crate::primitives::RaytracingObject;
 use crate::ray_intersection::{Intersectable, Intersection, Ray};
 use itertools::{Either, Itertools};
-use nalgebra::Point3;
+use nalgebra::{Point3, Vector3};
 use std::cmp::Ordering::{self, Equal};
 use std::f64::EPSILON;
 use std::fmt;
 
+// Number of intervals the motion of animated objects is split into when bounding it
+const MOTION_BOUNDS_STEPS: u32 = 8;
+
 fn build_bounding_volume(bounding_volumes: &[BoundingVolume]) -> BoundingVolume {
     if bounding_volumes.is_empty() {
         panic!("trying to build a bounding volume out of nothing");
@@ -71,6 +74,50 @@ impl BoundingVolume {
         BoundingVolume::from_bounds(min, max)
     }
 
+    // Bounds covering an object over its whole motion, merging the bounds at regular steps and padding them
+    // by how far a rotating corner strays from the chord between two steps
+    pub fn from_bounds_and_motion(
+        bounds_min: Point3<f64>,
+        bounds_max: Point3<f64>,
+        transform: &AnimatedTransform,
+    ) -> Self {
+        if !transform.is_animated() {
+            return BoundingVolume::from_bounds_and_transform(
+                bounds_min,
+                bounds_max,
+                transform.get_start(),
+            );
+        }
+
+        let step_angle = transform.get_rotation_angle() / f64::from(MOTION_BOUNDS_STEPS);
+        let bounding_volumes = (0..=MOTION_BOUNDS_STEPS)
+            .map(|step| {
+                let transform = transform.at(f64::from(step) / f64::from(MOTION_BOUNDS_STEPS));
+                let bounding_volume =
+                    BoundingVolume::from_bounds_and_transform(bounds_min, bounds_max, &transform);
+
+                let origin = transform.matrix() * Point3::origin();
+                let radius = (bounding_volume.bounds_max - bounding_volume.bounds_min)
+                    .abs()
+                    .magnitude()
+                    / 2.0
+                    + (bounding_volume.center - origin).magnitude();
+                let padding = Vector3::repeat(radius * (1.0 - (step_angle / 2.0).cos()));
+
+                BoundingVolume::from_bounds(
+                    bounding_volume.bounds_min - padding,
+                    bounding_volume.bounds_max + padding,
+                )
+            })
+            .collect::<Vec<BoundingVolume>>();
+
+        build_bounding_volume(&bounding_volumes)
+    }
+
+    pub fn get_bounds(&self) -> (Point3<f64>, Point3<f64>) {
+        (self.bounds_min, self.bounds_max)
+    }
+
     pub fn merge(a: &BoundingVolume, b: &BoundingVolume) -> BoundingVolume {
         let mut min = a.bounds_min;
         let mut max = a.bounds_max;
@@ -158,7 +205,7 @@ pub struct UnboundedObject(Box<dyn RaytracingObject>);
 impl Intersectable for UnboundedObject {
     fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection> {
         let object = &self.0;
-        let ray = &ray.transform(object.get_transform().inverse());
+        let ray = &ray.transform(object.get_transform_at(ray.time).inverse());
         object.intersect(ray, max_distance)
     }
 }
@@ -175,7 +222,7 @@ impl Intersectable for BoundedObject {
             return None;
         }
 
-        let ray = &ray.transform(self.object.get_transform().inverse());
+        let ray = &ray.transform(self.object.get_transform_at(ray.time).inverse());
         self.object.intersect(ray, max_distance)
     }
 }
@@ -249,6 +296,8 @@ pub struct KdTreeAccelerator {
     unbounded_objects: Vec<UnboundedObject>,
     bounded_objects: Vec<BoundedObject>,
     tree: KdTree,
+    // Bounds of all the objects, or `None` if there are no objects or some of them are unbounded
+    bounding_volume: Option<BoundingVolume>,
 }
 
 impl KdTreeAccelerator {
@@ -262,8 +311,8 @@ impl KdTreeAccelerator {
                     ObjectWithBounds::Bounded(object) => Either::Right(object),
                 });
 
-        let (tree, bounded_objects) = if bounded_objects.is_empty() {
-            (KdTree::Leaf(Vec::new()), bounded_objects)
+        let (tree, bounded_objects, bounding_volume) = if bounded_objects.is_empty() {
+            (KdTree::Leaf(Vec::new()), bounded_objects, None)
         } else {
             let indexes = (0..bounded_objects.len()).collect();
             let max_depth = (8.0 + 1.3 * (bounded_objects.len() as f64).log2()) as u8;
@@ -273,6 +322,7 @@ impl KdTreeAccelerator {
                 .iter()
                 .map(|object| object.bounding_volume)
                 .collect();
+            let bounding_volume = build_bounding_volume(&bounding_volumes);
 
             (
                 KdTree::build(
@@ -280,15 +330,17 @@ impl KdTreeAccelerator {
                     KdTreeConstructionOptions::default(),
                     max_depth,
                     max_bad_refines,
-                    build_bounding_volume(&bounding_volumes),
+                    bounding_volume,
                     indexes,
                 )
                 .unwrap_or_else(|| KdTree::Leaf(Vec::new())),
                 bounded_objects,
+                Some(bounding_volume),
             )
         };
 
         Self {
+            bounding_volume: bounding_volume.filter(|_| unbounded_objects.is_empty()),
             unbounded_objects,
             bounded_objects,
             tree,
@@ -299,11 +351,20 @@ impl KdTreeAccelerator {
         self.unbounded_objects.len() + self.bounded_objects.len()
     }
 
+    pub fn get_bounding_volume(&self) -> Option<BoundingVolume> {
+        self.bounding_volume
+    }
+
     pub fn raycast(&self, ray: &Ray) -> Option<Intersection> {
+        self.raycast_within(ray, None)
+    }
+
+    // Closest intersection no further than `max_distance` along the ray
+    pub fn raycast_within(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection<'_>> {
         self.unbounded_objects
             .iter()
-            .filter_map(|object| object.intersect(ray, None))
-            .chain(self.raycast_tree(&self.tree, ray, None))
+            .filter_map(|object| object.intersect(ray, max_distance))
+            .chain(self.raycast_tree(&self.tree, ray, max_distance))
             .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Equal))
     }
 
@@ -476,7 +537,8 @@ impl KdTree {
         let mut best_axis_and_split = None;
         let mut best_cost = f64::INFINITY;
 
-        for axis in Axis::iter(split_axis) {
+        for candidate_axis in Axis::iter(split_axis) {
+            let axis = usize::from(candidate_axis);
             split_candidates.clear();
             for &index in &indexes {
                 let object_bounds = objects[index].bounding_volume;
@@ -521,7 +583,7 @@ impl KdTree {
 
                     if cost < best_cost {
                         best_cost = cost;
-                        best_axis_and_split = Some((axis, index));
+                        best_axis_and_split = Some((candidate_axis, index));
                     }
                 }
 
@@ -571,7 +633,7 @@ impl KdTree {
         }
 
         let mut left_bound = bounding_volume.bounds_max;
-        left_bound[split_axis] = split_location;
+        left_bound[usize::from(split_axis)] = split_location;
         let left_bounding_volume =
             BoundingVolume::from_bounds(bounding_volume.bounds_min, left_bound);
         let left = Self::build(
@@ -584,7 +646,7 @@ impl KdTree {
         );
 
         let mut right_bound = bounding_volume.bounds_min;
-        right_bound[split_axis] = split_location;
+        right_bound[usize::from(split_axis)] = split_location;
         let right_bounding_volume =
             BoundingVolume::from_bounds(right_bound, bounding_volume.bounds_max);
         let right = Self::build(
@@ -598,7 +660,7 @@ impl KdTree {
 
         match (left, right) {
             (Some(left), Some(right)) => Some(Self::Node {
-                split_axis: split_axis.into(),
+                split_axis,
                 split_location,
                 bounding_volume,
                 left: Box::new(left),
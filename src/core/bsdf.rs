@@ -0,0 +1,307 @@
+use super::{Material, PhongMaterial, PhysicalMaterial, Texture};
+use crate::ray_intersection::Intersection;
+use crate::utils;
+use nalgebra::{Unit, Vector2, Vector3};
+use num_traits::identities::Zero;
+use rand::Rng;
+use std::collections::HashMap;
+use std::f64::consts::FRAC_1_PI;
+
+pub struct BsdfSample {
+    pub direction: Unit<Vector3<f64>>,
+    // BSDF value multiplied by the cosine term and divided by the pdf of the sampled direction
+    pub weight: Vector3<f64>,
+    // Probability density of the sampled direction, or the probability of choosing a specular lobe
+    pub pdf: f64,
+    pub is_specular: bool,
+    pub refractive_index: f64,
+}
+
+#[derive(Debug)]
+pub enum Bsdf {
+    Phong {
+        color: Vector3<f64>,
+        specular: Vector3<f64>,
+        shininess: f64,
+        reflectivity: f64,
+    },
+    Physical {
+        color: Vector3<f64>,
+        roughness: f64,
+        metalness: f64,
+        opacity: f64,
+        refractive_index: f64,
+    },
+}
+
+impl Bsdf {
+    // BSDF of the surface at an intersection whose data is computed
+    pub fn new(intersection: &Intersection<'_>, textures: &HashMap<String, Texture>) -> Self {
+        let color = intersection.get_material_color(textures);
+        match intersection.get_material() {
+            Material::Phong(material) => Self::from_phong(material, color),
+            Material::Physical(material) => {
+                Self::from_physical(material, intersection.get_uv(), color, textures)
+            }
+        }
+    }
+
+    fn from_phong(material: &PhongMaterial, color: Vector3<f64>) -> Self {
+        Bsdf::Phong {
+            color,
+            specular: material.specular,
+            shininess: material.shininess,
+            reflectivity: material.reflectivity.clamp(0.0, 1.0),
+        }
+    }
+
+    fn from_physical(
+        material: &PhysicalMaterial,
+        uv: Vector2<f64>,
+        color: Vector3<f64>,
+        textures: &HashMap<String, Texture>,
+    ) -> Self {
+        let (roughness, metalness) = material.get_roughness_metalness(uv, textures);
+        Bsdf::Physical {
+            color,
+            roughness: roughness.max(0.04),
+            metalness,
+            opacity: material.opacity.clamp(0.0, 1.0),
+            refractive_index: material.refractive_index,
+        }
+    }
+
+    pub fn albedo(&self) -> Vector3<f64> {
+        match self {
+            Bsdf::Phong { color, .. } | Bsdf::Physical { color, .. } => *color,
+        }
+    }
+
+    // Evaluate the non-specular part of the BSDF for light arriving from `light_dir` and leaving towards `view_dir`
+    pub fn evaluate(
+        &self,
+        normal: &Unit<Vector3<f64>>,
+        view_dir: &Unit<Vector3<f64>>,
+        light_dir: &Unit<Vector3<f64>>,
+    ) -> Vector3<f64> {
+        let n_dot_v = normal.dot(view_dir);
+        let n_dot_l = normal.dot(light_dir);
+        if n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+            return Vector3::zero();
+        }
+
+        match *self {
+            Bsdf::Phong {
+                color,
+                specular,
+                shininess,
+                reflectivity,
+            } => {
+                let half_vec = Unit::new_normalize(light_dir.into_inner() + view_dir.into_inner());
+                let n_dot_h = normal.dot(&half_vec).max(0.0);
+                let normalization = (shininess + 8.0) * FRAC_1_PI / 8.0;
+
+                (color * FRAC_1_PI + specular * normalization * n_dot_h.powf(shininess))
+                    * (1.0 - reflectivity)
+            }
+            Bsdf::Physical {
+                color,
+                roughness,
+                metalness,
+                opacity,
+                ..
+            } => {
+                let half_vec = Unit::new_normalize(light_dir.into_inner() + view_dir.into_inner());
+                let n_dot_h = normal.dot(&half_vec).max(0.0);
+                let v_dot_h = view_dir.dot(&half_vec).max(0.0);
+
+                let base_reflectivity = Vector3::repeat(0.04).lerp(&color, metalness);
+                let f = utils::fresnel(v_dot_h, base_reflectivity);
+                let ndf = utils::ndf(n_dot_h, roughness);
+                let g = utils::geometry_function(n_dot_v, n_dot_l, roughness);
+
+                let k_d = (Vector3::repeat(1.0) - f) * (1.0 - metalness);
+                let diffuse = FRAC_1_PI * k_d.component_mul(&color);
+                let specular = ndf * g * f / (4.0 * n_dot_v * n_dot_l);
+
+                (diffuse + specular) * opacity
+            }
+        }
+    }
+
+    // Probability density of sampling `light_dir` from the non-specular part of the BSDF
+    pub fn pdf(
+        &self,
+        normal: &Unit<Vector3<f64>>,
+        view_dir: &Unit<Vector3<f64>>,
+        light_dir: &Unit<Vector3<f64>>,
+    ) -> f64 {
+        let n_dot_v = normal.dot(view_dir);
+        let n_dot_l = normal.dot(light_dir);
+        if n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+            return 0.0;
+        }
+
+        match *self {
+            Bsdf::Phong { reflectivity, .. } => (1.0 - reflectivity) * n_dot_l * FRAC_1_PI,
+            Bsdf::Physical {
+                roughness, opacity, ..
+            } => {
+                let specular_probability = self.specular_probability(n_dot_v);
+
+                // Density of reflecting off a visible microfacet normal
+                let half_vec = Unit::new_normalize(light_dir.into_inner() + view_dir.into_inner());
+                let n_dot_h = normal.dot(&half_vec).max(0.0);
+                let specular_pdf = utils::smith_masking(n_dot_v, roughness)
+                    * utils::ndf(n_dot_h, roughness)
+                    / (4.0 * n_dot_v);
+                let diffuse_pdf = n_dot_l * FRAC_1_PI;
+
+                opacity
+                    * (specular_probability * specular_pdf
+                        + (1.0 - specular_probability) * diffuse_pdf)
+            }
+        }
+    }
+
+    // Probability of sampling the specular lobe of an opaque physical surface rather than the diffuse lobe
+    fn specular_probability(&self, n_dot_v: f64) -> f64 {
+        match *self {
+            Bsdf::Phong { .. } => 0.0,
+            Bsdf::Physical {
+                color, metalness, ..
+            } => {
+                let base_reflectivity = Vector3::repeat(0.04).lerp(&color, metalness);
+                let f = utils::fresnel(n_dot_v, base_reflectivity);
+                let specular_weight = f.mean();
+                let diffuse_weight = ((Vector3::repeat(1.0) - f) * (1.0 - metalness))
+                    .component_mul(&color)
+                    .mean();
+
+                if specular_weight + diffuse_weight > 0.0 {
+                    specular_weight / (specular_weight + diffuse_weight)
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+
+    fn sample_non_specular<R: Rng>(
+        &self,
+        normal: &Unit<Vector3<f64>>,
+        view_dir: &Unit<Vector3<f64>>,
+        refractive_index: f64,
+        rng: &mut R,
+    ) -> Option<BsdfSample> {
+        let n_dot_v = normal.dot(view_dir);
+        let sample_specular = rng.gen::<f64>() < self.specular_probability(n_dot_v);
+
+        let direction = if sample_specular {
+            let microfacet_normal = match *self {
+                Bsdf::Physical { roughness, .. } => {
+                    utils::ggx_sample_visible_microfacet(normal, view_dir, roughness, rng)
+                }
+                Bsdf::Phong { .. } => unreachable!(),
+            };
+            utils::reflect(&-view_dir.into_inner(), &microfacet_normal)
+        } else {
+            utils::cosine_sample_hemisphere(normal, rng)
+        };
+
+        let n_dot_l = normal.dot(&direction);
+        let pdf = self.pdf(normal, view_dir, &direction);
+        if n_dot_l <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction,
+            weight: self.evaluate(normal, view_dir, &direction) * n_dot_l / pdf,
+            pdf,
+            is_specular: false,
+            refractive_index,
+        })
+    }
+
+    // Sample an incoming light direction for light leaving towards `view_dir`
+    pub fn sample<R: Rng>(
+        &self,
+        normal: &Unit<Vector3<f64>>,
+        view_dir: &Unit<Vector3<f64>>,
+        incident_refractive_index: f64,
+        rng: &mut R,
+    ) -> Option<BsdfSample> {
+        match *self {
+            Bsdf::Phong {
+                color,
+                reflectivity,
+                ..
+            } => {
+                if rng.gen::<f64>() < reflectivity {
+                    Some(BsdfSample {
+                        direction: utils::reflect(&-view_dir.into_inner(), normal),
+                        weight: color,
+                        pdf: reflectivity,
+                        is_specular: true,
+                        refractive_index: incident_refractive_index,
+                    })
+                } else {
+                    self.sample_non_specular(normal, view_dir, incident_refractive_index, rng)
+                }
+            }
+            Bsdf::Physical {
+                opacity,
+                refractive_index,
+                ..
+            } => {
+                if rng.gen::<f64>() < opacity {
+                    return self.sample_non_specular(
+                        normal,
+                        view_dir,
+                        incident_refractive_index,
+                        rng,
+                    );
+                }
+
+                // Treat the transparent part as a smooth dielectric, choosing between reflection
+                // and refraction using the Fresnel reflectance
+                let incident = -view_dir.into_inner();
+                let exiting = (incident_refractive_index - refractive_index).abs() < f64::EPSILON;
+                let (eta, transmitted_refractive_index) = if exiting {
+                    (refractive_index, 1.0)
+                } else {
+                    (
+                        incident_refractive_index / refractive_index,
+                        refractive_index,
+                    )
+                };
+                let reflectance = utils::fresnel(
+                    normal.dot(view_dir).max(0.0),
+                    Vector3::repeat(((1.0 - eta) / (1.0 + eta)).powi(2)),
+                )
+                .mean();
+
+                let refraction_dir = utils::refract(&incident, normal, eta)
+                    .filter(|_| rng.gen::<f64>() >= reflectance);
+
+                Some(refraction_dir.map_or_else(
+                    || BsdfSample {
+                        direction: utils::reflect(&incident, normal),
+                        weight: Vector3::repeat(1.0),
+                        pdf: (1.0 - opacity) * reflectance,
+                        is_specular: true,
+                        refractive_index: incident_refractive_index,
+                    },
+                    |direction| BsdfSample {
+                        direction,
+                        weight: Vector3::repeat(1.0),
+                        pdf: (1.0 - opacity) * (1.0 - reflectance),
+                        is_specular: true,
+                        refractive_index: transmitted_refractive_index,
+                    },
+                ))
+            }
+        }
+    }
+}
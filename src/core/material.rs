@@ -1,12 +1,15 @@
 use super::Texture;
+use crate::render::Validator;
+use crate::RaytraceError;
 use nalgebra::{Vector2, Vector3};
 use num_traits::identities::Zero;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use serde::de::{MapAccess, Visitor};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
 use std::path::Path;
 
-#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum MaterialSide {
     Both,
     Front,
@@ -19,7 +22,7 @@ impl Default for MaterialSide {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct PhongMaterial {
     pub side: MaterialSide,
@@ -47,6 +50,17 @@ impl Default for PhongMaterial {
 }
 
 impl PhongMaterial {
+    fn validate(&self, validator: &mut Validator) {
+        validator.check_color("color", &self.color);
+        validator.check_color("emissive", &self.emissive);
+        validator.check_color("specular", &self.specular);
+        validator.check_range("reflectivity", self.reflectivity, 0.0..=1.0);
+        validator.check_non_negative("shininess", self.shininess);
+        if let Some(texture_path) = &self.texture_path {
+            validator.check_file("texture", texture_path);
+        }
+    }
+
     pub fn get_color(&self, uv: Vector2<f64>, textures: &HashMap<String, Texture>) -> Vector3<f64> {
         self.texture_path
             .as_ref()
@@ -57,7 +71,7 @@ impl PhongMaterial {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct PhysicalMaterial {
     pub side: MaterialSide,
@@ -70,6 +84,8 @@ pub struct PhysicalMaterial {
     pub refractive_index: f64,
     #[serde(rename = "texture")]
     pub texture_path: Option<String>,
+    // Texture scaling the roughness by its green channel and the metalness by its blue channel
+    pub metallic_roughness_texture: Option<String>,
 }
 
 impl Default for PhysicalMaterial {
@@ -84,11 +100,28 @@ impl Default for PhysicalMaterial {
             metalness: 0.0,
             refractive_index: 1.0,
             texture_path: None,
+            metallic_roughness_texture: None,
         }
     }
 }
 
 impl PhysicalMaterial {
+    fn validate(&self, validator: &mut Validator) {
+        validator.check_color("color", &self.color);
+        validator.check_range("opacity", self.opacity, 0.0..=1.0);
+        validator.check_color("emissive", &self.emissive);
+        validator.check_non_negative("emissive_intensity", self.emissive_intensity);
+        validator.check_range("roughness", self.roughness, 0.0..=1.0);
+        validator.check_range("metalness", self.metalness, 0.0..=1.0);
+        validator.check_positive("refractive_index", self.refractive_index);
+        if let Some(texture_path) = &self.texture_path {
+            validator.check_file("texture", texture_path);
+        }
+        if let Some(texture_path) = &self.metallic_roughness_texture {
+            validator.check_file("metallic_roughness_texture", texture_path);
+        }
+    }
+
     pub fn get_color(&self, uv: Vector2<f64>, textures: &HashMap<String, Texture>) -> Vector3<f64> {
         self.texture_path
             .as_ref()
@@ -97,10 +130,28 @@ impl PhysicalMaterial {
                 self.color.component_mul(&texture.get_color(uv))
             })
     }
+
+    /// # Panics
+    ///
+    /// Will panic if the metallic-roughness texture is not loaded
+    pub fn get_roughness_metalness(
+        &self,
+        uv: Vector2<f64>,
+        textures: &HashMap<String, Texture>,
+    ) -> (f64, f64) {
+        self.metallic_roughness_texture.as_ref().map_or(
+            (self.roughness, self.metalness),
+            |texture_path| {
+                let texture = textures.get(texture_path).expect("texture not loaded");
+                let color = texture.get_color(uv);
+                (self.roughness * color.y, self.metalness * color.z)
+            },
+        )
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(tag = "type", rename_all(deserialize = "lowercase"))]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Material {
     Phong(PhongMaterial),
     Physical(PhysicalMaterial),
@@ -113,28 +164,59 @@ impl Default for Material {
 }
 
 impl Material {
-    /// # Panics
-    ///
-    /// Will panic if texture cannot be loaded
-    pub fn load_textures(&self, asset_base: &Path, textures: &mut HashMap<String, Texture>) {
-        let texture_path = match self {
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        match self {
+            Material::Phong(material) => material.validate(validator),
+            Material::Physical(material) => material.validate(validator),
+        }
+    }
+
+    pub fn get_texture_path(&self) -> Option<&String> {
+        match self {
             Material::Phong(material) => material.texture_path.as_ref(),
             Material::Physical(material) => material.texture_path.as_ref(),
+        }
+    }
+
+    // Paths of every texture the material samples
+    pub fn get_texture_paths(&self) -> impl Iterator<Item = &String> {
+        let metallic_roughness_texture = match self {
+            Material::Phong(_) => None,
+            Material::Physical(material) => material.metallic_roughness_texture.as_ref(),
         };
 
-        if let Some(texture_path) = texture_path {
+        self.get_texture_path()
+            .into_iter()
+            .chain(metallic_roughness_texture)
+    }
+
+    pub fn load_textures(
+        &self,
+        asset_base: &Path,
+        textures: &mut HashMap<String, Texture>,
+    ) -> Result<(), RaytraceError> {
+        for texture_path in self.get_texture_paths() {
             if !textures.contains_key(texture_path) {
                 let texture_path = texture_path.to_string();
                 let mut texture = Texture::new(&texture_path);
-                texture.load(asset_base).unwrap_or_else(|err| {
-                    panic!(
-                        "failed to load texture at path \"{}\": {}",
-                        texture_path, err
-                    );
-                });
+                texture
+                    .load(asset_base)
+                    .map_err(|source| RaytraceError::Texture {
+                        path: asset_base.join(&texture_path),
+                        source,
+                    })?;
                 textures.insert(texture_path, texture);
             }
         }
+
+        Ok(())
+    }
+
+    pub fn get_color(&self, uv: Vector2<f64>, textures: &HashMap<String, Texture>) -> Vector3<f64> {
+        match self {
+            Material::Phong(material) => material.get_color(uv, textures),
+            Material::Physical(material) => material.get_color(uv, textures),
+        }
     }
 
     pub fn side(&self) -> MaterialSide {
@@ -143,46 +225,122 @@ impl Material {
             Material::Physical(material) => material.side,
         }
     }
+
+    pub fn emissive(&self) -> Vector3<f64> {
+        match self {
+            Material::Phong(material) => material.emissive,
+            Material::Physical(material) => material.emissive,
+        }
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use serde_json::json;
+// Material of an object, written inline or as the name of one of the scene's materials
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MaterialRef {
+    Named(String),
+    Material(Material),
+}
+
+impl Default for MaterialRef {
+    fn default() -> Self {
+        MaterialRef::Material(Material::default())
+    }
+}
+
+impl From<Material> for MaterialRef {
+    fn from(material: Material) -> Self {
+        MaterialRef::Material(material)
+    }
+}
 
-    impl PartialEq for PhongMaterial {
-        fn eq(&self, other: &PhongMaterial) -> bool {
-            self.side == other.side
-                && self.color == other.color
-                && self.emissive == other.emissive
-                && self.specular == other.specular
-                && self.reflectivity == other.reflectivity
-                && self.shininess == other.shininess
+impl From<&str> for MaterialRef {
+    fn from(name: &str) -> Self {
+        MaterialRef::Named(name.to_string())
+    }
+}
+
+impl MaterialRef {
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        match self {
+            MaterialRef::Named(name) => {
+                if !validator.has_material(name) {
+                    validator.report(format!("material \"{name}\" is not defined"));
+                }
+            }
+            MaterialRef::Material(material) => material.validate(validator),
         }
     }
 
-    impl PartialEq for PhysicalMaterial {
-        fn eq(&self, other: &PhysicalMaterial) -> bool {
-            self.side == other.side
-                && self.color == other.color
-                && self.opacity == other.opacity
-                && self.emissive == other.emissive
-                && self.emissive_intensity == other.emissive_intensity
-                && self.roughness == other.roughness
-                && self.metalness == other.metalness
-                && self.refractive_index == other.refractive_index
+    // Material given inline or already resolved, or `None` for a name which is not resolved yet
+    pub fn get(&self) -> Option<&Material> {
+        match self {
+            MaterialRef::Named(_) => None,
+            MaterialRef::Material(material) => Some(material),
         }
     }
 
-    impl PartialEq for Material {
-        fn eq(&self, other: &Material) -> bool {
-            match (self, other) {
-                (Material::Phong(a), Material::Phong(b)) => a == b,
-                (Material::Physical(a), Material::Physical(b)) => a == b,
-                _ => false,
+    // Replace a name with a copy of the named material
+    pub fn resolve(&mut self, materials: &BTreeMap<String, Material>) -> Result<(), RaytraceError> {
+        if let MaterialRef::Named(name) = self {
+            let material = materials
+                .get(name)
+                .ok_or_else(|| RaytraceError::UnknownMaterial(name.clone()))?;
+            *self = MaterialRef::Material(material.clone());
+        }
+
+        Ok(())
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the material is a name which was not resolved
+    pub fn into_material(self) -> Material {
+        match self {
+            MaterialRef::Named(name) => panic!("material \"{name}\" is not resolved"),
+            MaterialRef::Material(material) => material,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MaterialRef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct MaterialRefVisitor;
+
+        impl<'de> Visitor<'de> for MaterialRefVisitor {
+            type Value = MaterialRef;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a material or the name of a material")
+            }
+
+            fn visit_str<E>(self, name: &str) -> Result<MaterialRef, E>
+            where
+                E: de::Error,
+            {
+                Ok(MaterialRef::Named(name.to_string()))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<MaterialRef, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                Material::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(MaterialRef::Material)
             }
         }
+
+        deserializer.deserialize_any(MaterialRefVisitor)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
 
     #[test]
     fn it_deserializes_defaults() {
@@ -222,4 +380,22 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn it_deserializes_material_references() {
+        assert_eq!(
+            serde_json::from_value::<MaterialRef>(json!("red")).unwrap(),
+            MaterialRef::Named("red".to_string())
+        );
+        assert_eq!(
+            serde_json::from_value::<MaterialRef>(json!({ "type": "physical" })).unwrap(),
+            MaterialRef::Material(Material::Physical(PhysicalMaterial::default()))
+        );
+
+        let mut material = MaterialRef::from("red");
+        let materials = BTreeMap::from([("red".to_string(), Material::default())]);
+        material.resolve(&materials).unwrap();
+        assert_eq!(material.get(), Some(&Material::default()));
+        assert!(MaterialRef::from("blue").resolve(&materials).is_err());
+    }
 }
@@ -1,12 +1,16 @@
 mod bounds;
+mod bsdf;
 mod material;
 mod texture;
 mod transform;
 
+use crate::RaytraceError;
+
 pub use bounds::{BoundedObject, BoundingVolume, KdTreeAccelerator, ObjectWithBounds};
-pub use material::{Material, MaterialSide, PhongMaterial, PhysicalMaterial};
+pub use bsdf::{Bsdf, BsdfSample};
+pub use material::{Material, MaterialRef, MaterialSide, PhongMaterial, PhysicalMaterial};
 pub use texture::Texture;
-pub use transform::{Transform, Transformed};
+pub use transform::{AnimatedTransform, Transform, Transformed};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Axis {
@@ -16,10 +20,12 @@ pub enum Axis {
 }
 
 impl Axis {
-    fn iter(initial_axis: Axis) -> impl Iterator<Item = usize> {
+    const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    fn iter(initial_axis: Axis) -> impl Iterator<Item = Axis> {
         let start: usize = initial_axis.into();
 
-        (start..(start + 3)).map(|a| a % 3)
+        (start..(start + 3)).map(|a| Axis::ALL[a % 3])
     }
 }
 
@@ -43,13 +49,15 @@ impl From<&Axis> for usize {
     }
 }
 
-impl From<usize> for Axis {
-    fn from(axis: usize) -> Self {
+impl TryFrom<usize> for Axis {
+    type Error = RaytraceError;
+
+    fn try_from(axis: usize) -> Result<Self, Self::Error> {
         match axis {
-            0 => Axis::X,
-            1 => Axis::Y,
-            2 => Axis::Z,
-            _ => panic!("{:?} is not a valid 3D axis", axis),
+            0 => Ok(Axis::X),
+            1 => Ok(Axis::Y),
+            2 => Ok(Axis::Z),
+            _ => Err(RaytraceError::InvalidAxis(axis)),
         }
     }
 }
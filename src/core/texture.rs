@@ -3,13 +3,15 @@ use image::RgbImage;
 use nalgebra::{Vector2, Vector3};
 use std::fmt;
 use std::path::Path;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Texture {
     texture_path: String,
     width: u32,
     height: u32,
-    texture: Option<RgbImage>,
+    // Shared between clones so scenes can reuse a loaded texture
+    texture: Option<Arc<RgbImage>>,
 }
 
 impl fmt::Debug for Texture {
@@ -32,18 +34,33 @@ impl Texture {
         }
     }
 
+    // Texture of an image which is already decoded, such as one embedded in a glTF file
+    pub fn from_image(texture_path: &str, image: RgbImage) -> Self {
+        Self {
+            texture_path: texture_path.to_string(),
+            width: image.width(),
+            height: image.height(),
+            texture: Some(Arc::new(image)),
+        }
+    }
+
     pub fn load(&mut self, asset_base: &Path) -> Result<(), image::ImageError> {
         assert!(self.texture.is_none());
 
         let texture = image::open(asset_base.join(self.texture_path.clone()))?.to_rgb8();
         self.width = texture.width();
         self.height = texture.height();
-        self.texture = Some(texture);
+        self.texture = Some(Arc::new(texture));
 
         Ok(())
     }
 
     pub fn get_color(&self, uv: Vector2<f64>) -> Vector3<f64> {
+        let texture = self.texture.as_ref().expect("texture not loaded");
+        // Images without pixels have no color to look up
+        if self.width == 0 || self.height == 0 {
+            return Vector3::zeros();
+        }
         let (w, h) = (self.width - 1, self.height - 1);
 
         let (x, y) = (uv.x % 1.0, uv.y % 1.0);
@@ -53,11 +70,7 @@ impl Texture {
         let (x, y) = (x * f64::from(w), (1.0 - y) * f64::from(h));
         let (x, y) = ((x as u32).clamp(0, w), (y as u32).clamp(0, h));
 
-        let pixel = self
-            .texture
-            .as_ref()
-            .expect("texture not loaded")
-            .get_pixel(x, y);
+        let pixel = texture.get_pixel(x, y);
         let channels = pixel.channels();
 
         let norm = f64::from(std::u8::MAX);
@@ -68,3 +81,15 @@ impl Texture {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_looks_up_colors_of_empty_images() {
+        let texture = Texture::from_image("empty.png", RgbImage::new(0, 0));
+
+        assert_eq!(texture.get_color(Vector2::new(0.5, 0.5)), Vector3::zeros());
+    }
+}
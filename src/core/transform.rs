@@ -1,8 +1,11 @@
 use auto_ops::impl_op_ex;
-use nalgebra::{Affine3, Matrix4, Point3, Rotation3, Translation3, Unit, Vector3};
+use nalgebra::{
+    Affine3, Matrix3, Matrix4, Point3, Rotation3, Translation3, Unit, UnitQuaternion, Vector3,
+};
 use once_cell::sync::OnceCell;
 use serde::de::{SeqAccess, Visitor};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::default::Default;
 use std::fmt;
 
@@ -11,13 +14,27 @@ pub trait Transformed {
     fn get_position(&self) -> Point3<f64> {
         self.get_transform().matrix() * Point3::origin()
     }
+
+    // Transform at `time` between the start (0) and the end (1) of the motion of objects which move while the
+    // shutter is open
+    fn get_transform_at(&self, _time: f64) -> Cow<'_, Transform> {
+        Cow::Borrowed(self.get_transform())
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone)]
 pub struct Transform {
     matrix: Affine3<f64>,
     inv_matrix: OnceCell<Affine3<f64>>,
     inv_transpose_matrix: OnceCell<Affine3<f64>>,
+    // Steps the transform was built from, kept to serialize it in the same list form
+    steps: Vec<SubTransform>,
+}
+
+impl PartialEq for Transform {
+    fn eq(&self, other: &Self) -> bool {
+        self.matrix == other.matrix
+    }
 }
 
 impl Default for Transform {
@@ -44,6 +61,7 @@ impl Transform {
             matrix,
             inv_matrix: OnceCell::new(),
             inv_transpose_matrix: OnceCell::new(),
+            steps: Vec::new(),
         }
     }
 
@@ -51,6 +69,16 @@ impl Transform {
         self.matrix
     }
 
+    // Whether the transform is finite and does not collapse space onto a plane, line or point
+    pub fn is_invertible(&self) -> bool {
+        let matrix = self.matrix.matrix();
+        matrix.iter().all(|value| value.is_finite())
+            && matrix
+                .fixed_slice::<3, 3>(0, 0)
+                .try_inverse()
+                .is_some_and(|inverse| inverse.iter().all(|value| value.is_finite()))
+    }
+
     pub fn inverse(&self) -> Affine3<f64> {
         *self.inv_matrix.get_or_init(|| self.matrix.inverse())
     }
@@ -63,36 +91,201 @@ impl Transform {
         })
     }
 
-    fn set_matrix(mut self, m: Affine3<f64>) -> Self {
-        self.matrix = m;
+    // Apply `step` after the steps the transform is already built from
+    fn then(mut self, step: SubTransform) -> Self {
+        let matrix = match &step {
+            SubTransform::Translate(translation) => Translation3::from(*translation) * self.matrix,
+            SubTransform::Rotate(axis, angle) => {
+                Rotation3::from_axis_angle(axis, angle.to_radians()) * self.matrix
+            }
+            SubTransform::Scale(scale) => {
+                Affine3::from_matrix_unchecked(Matrix4::new_nonuniform_scaling(scale)) * self.matrix
+            }
+            SubTransform::Matrix(rows) => {
+                Affine3::from_matrix_unchecked(Matrix4::from_fn(|row, column| rows[row][column]))
+                    * self.matrix
+            }
+        };
+
+        self.matrix = matrix;
         self.inv_matrix = OnceCell::new();
         self.inv_transpose_matrix = OnceCell::new();
+        self.steps.push(step);
         self
     }
 
     pub fn translate(self, translation: Vector3<f64>) -> Self {
-        let translated = Translation3::from(translation) * self.matrix;
-        self.set_matrix(translated)
+        self.then(SubTransform::Translate(translation))
     }
 
     pub fn rotate(self, axis: Unit<Vector3<f64>>, angle: f64) -> Self {
-        let rotated = Rotation3::from_axis_angle(&axis, angle.to_radians()) * self.matrix;
-        self.set_matrix(rotated)
+        self.then(SubTransform::Rotate(axis, angle))
     }
 
     pub fn scale(self, scale: Vector3<f64>) -> Self {
-        let scaled =
-            Affine3::from_matrix_unchecked(Matrix4::new_nonuniform_scaling(&scale)) * self.matrix;
-        self.set_matrix(scaled)
+        self.then(SubTransform::Scale(scale))
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all(deserialize = "lowercase"))]
+// Affine transform split into a translation, a rotation and a scale which can be interpolated separately
+#[derive(Clone, Debug, PartialEq)]
+struct DecomposedTransform {
+    translation: Vector3<f64>,
+    rotation: UnitQuaternion<f64>,
+    scale: Matrix3<f64>,
+}
+
+impl DecomposedTransform {
+    // Polar decomposition of the linear part into a rotation and a symmetric scale, using its singular values
+    fn new(transform: &Transform) -> Self {
+        let matrix = transform.matrix().to_homogeneous();
+        let translation = matrix.fixed_slice::<3, 1>(0, 3).into_owned();
+        let linear = matrix.fixed_slice::<3, 3>(0, 0).into_owned();
+
+        let svd = linear.svd(true, true);
+        let mut rotation = svd.u.unwrap() * svd.v_t.unwrap();
+        // Keep a proper rotation, leaving any reflection in the scale
+        if rotation.determinant() < 0.0 {
+            rotation = -rotation;
+        }
+        let scale = rotation.transpose() * linear;
+
+        Self {
+            translation,
+            rotation: UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(
+                rotation,
+            )),
+            scale,
+        }
+    }
+
+    fn interpolate(&self, other: &Self, t: f64) -> Transform {
+        let translation = self.translation.lerp(&other.translation, t);
+        let rotation = self
+            .rotation
+            .try_slerp(&other.rotation, t, f64::EPSILON)
+            .unwrap_or(if t < 0.5 {
+                self.rotation
+            } else {
+                other.rotation
+            });
+        let scale = self.scale * (1.0 - t) + other.scale * t;
+
+        let mut matrix = (rotation.to_rotation_matrix().matrix() * scale).to_homogeneous();
+        matrix.fixed_slice_mut::<3, 1>(0, 3).copy_from(&translation);
+
+        Transform::new(Affine3::from_matrix_unchecked(matrix))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct TransformMotion {
+    end: Transform,
+    decomposed_start: DecomposedTransform,
+    decomposed_end: DecomposedTransform,
+}
+
+// Transform of an object which may move between the start and the end of the shutter interval. Objects give their
+// transform at the end of the interval as `end_transform`, which defaults to the start transform.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimatedTransform {
+    start: Transform,
+    motion: Option<Box<TransformMotion>>,
+}
+
+impl From<Transform> for AnimatedTransform {
+    fn from(transform: Transform) -> Self {
+        Self::new(transform, None)
+    }
+}
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Option<Transform>) -> Self {
+        let motion = end.filter(|end| *end != start).map(|end| {
+            Box::new(TransformMotion {
+                decomposed_start: DecomposedTransform::new(&start),
+                decomposed_end: DecomposedTransform::new(&end),
+                end,
+            })
+        });
+
+        Self { start, motion }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.motion.is_some()
+    }
+
+    pub fn get_start(&self) -> &Transform {
+        &self.start
+    }
+
+    pub fn get_end(&self) -> &Transform {
+        self.motion
+            .as_ref()
+            .map_or(&self.start, |motion| &motion.end)
+    }
+
+    // Nest a child with its own start and end transforms inside this transform
+    #[must_use]
+    pub fn compose(&self, start: &Transform, end: Option<&Transform>) -> Self {
+        let end = if self.is_animated() || end.is_some() {
+            Some(self.get_end() * end.unwrap_or(start))
+        } else {
+            None
+        };
+
+        Self::new(&self.start * start, end)
+    }
+
+    pub fn at(&self, time: f64) -> Cow<'_, Transform> {
+        match &self.motion {
+            None => Cow::Borrowed(&self.start),
+            Some(_) if time <= 0.0 => Cow::Borrowed(&self.start),
+            Some(motion) if time >= 1.0 => Cow::Borrowed(&motion.end),
+            Some(motion) => Cow::Owned(
+                motion
+                    .decomposed_start
+                    .interpolate(&motion.decomposed_end, time),
+            ),
+        }
+    }
+
+    // Angle the transform rotates by over its motion
+    pub fn get_rotation_angle(&self) -> f64 {
+        self.motion.as_ref().map_or(0.0, |motion| {
+            motion
+                .decomposed_start
+                .rotation
+                .angle_to(&motion.decomposed_end.rotation)
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 enum SubTransform {
     Translate(Vector3<f64>),
     Rotate(Unit<Vector3<f64>>, f64),
     Scale(Vector3<f64>),
+    // Rows of an affine matrix, for transforms which are not built from the steps above
+    Matrix([[f64; 4]; 4]),
+}
+
+impl Serialize for Transform {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Transforms composed from other transforms only keep their matrix
+        if self.steps.is_empty() && self.matrix != Affine3::identity() {
+            let matrix = self.matrix.to_homogeneous();
+            let rows = [0, 1, 2, 3].map(|row| [0, 1, 2, 3].map(|column| matrix[(row, column)]));
+            return [SubTransform::Matrix(rows)].serialize(serializer);
+        }
+
+        self.steps.serialize(serializer)
+    }
 }
 
 impl<'de> Deserialize<'de> for Transform {
@@ -117,13 +310,7 @@ impl<'de> Deserialize<'de> for Transform {
                 loop {
                     let next: Option<SubTransform> = seq.next_element()?;
                     if let Some(next) = next {
-                        transform = match next {
-                            SubTransform::Translate(translation) => {
-                                transform.translate(translation)
-                            }
-                            SubTransform::Rotate(axis, angle) => transform.rotate(axis, angle),
-                            SubTransform::Scale(scale) => transform.scale(scale),
-                        };
+                        transform = transform.then(next);
                     } else {
                         break;
                     }
@@ -294,6 +481,47 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_interpolates_animated_transforms() {
+        let start = Transform::default()
+            .scale(Vector3::from([1.0, 2.0, 1.0]))
+            .translate(Vector3::from([1.0, 0.0, 0.0]));
+        let end = Transform::default()
+            .scale(Vector3::from([3.0, 2.0, 1.0]))
+            .rotate(Vector3::y_axis(), 90.0)
+            .translate(Vector3::from([1.0, 4.0, 0.0]));
+        let animated = AnimatedTransform::new(start.clone(), Some(end.clone()));
+        let halfway = Transform::default()
+            .scale(Vector3::from([2.0, 2.0, 1.0]))
+            .rotate(Vector3::y_axis(), 45.0)
+            .translate(Vector3::from([1.0, 2.0, 0.0]));
+
+        assert!(animated.is_animated());
+        assert_eq!(*animated.at(0.0), start);
+        assert_eq!(*animated.at(1.0), end);
+        assert!((animated.at(0.5).matrix().matrix() - halfway.matrix().matrix()).amax() < 1e-9);
+        assert!((animated.get_rotation_angle() - 90_f64.to_radians()).abs() < 1e-9);
+
+        let still = AnimatedTransform::new(start.clone(), Some(start.clone()));
+        assert!(!still.is_animated());
+
+        // Children follow the motion of their parent
+        let child = Transform::default().translate(Vector3::from([0.0, 0.0, 1.0]));
+        let nested = animated.compose(&child, None);
+        assert_eq!(*nested.at(0.0), &start * &child);
+        assert_eq!(*nested.at(1.0), &end * &child);
+    }
+
+    #[test]
+    fn it_checks_whether_transforms_are_invertible() {
+        let scale = |scale: [f64; 3]| Transform::default().scale(Vector3::from(scale));
+
+        assert!(scale([1e-6, 1e-6, 1e-6]).is_invertible());
+        assert!(!scale([1.0, 0.0, 1.0]).is_invertible());
+        assert!(!scale([1.0, 1e-320, 1.0]).is_invertible());
+        assert!(!scale([f64::INFINITY, 1.0, 1.0]).is_invertible());
+    }
+
     #[test]
     fn it_deserializes_identity() {
         let identity = Transform::default();
@@ -369,4 +597,34 @@ mod test {
             full_identity
         );
     }
+
+    #[test]
+    fn it_serializes_transforms() {
+        let full = Transform::default()
+            .rotate(Vector3::y_axis(), 50.0)
+            .scale(Vector3::from([3.0, 2.0, 1.0]))
+            .translate(Vector3::from([5.0, 2.0, 3.0]));
+
+        assert_eq!(
+            serde_json::to_value(Transform::default()).unwrap(),
+            json!([])
+        );
+        assert_eq!(
+            serde_json::to_value(&full).unwrap(),
+            json!([
+                { "rotate": [[0.0, 1.0, 0.0], 50.0] },
+                { "scale": [3.0, 2.0, 1.0] },
+                { "translate": [5.0, 2.0, 3.0] }
+            ])
+        );
+
+        // Composed transforms are written as their matrix
+        let composed = &full * &Transform::default().translate(Vector3::from([1.0, 0.0, 0.0]));
+        let value = serde_json::to_value(&composed).unwrap();
+        assert_eq!(value[0]["matrix"][3], json!([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(
+            serde_json::from_value::<Transform>(value).unwrap(),
+            composed
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use crate::render::Diagnostic;
+use image::ImageError;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum RaytraceError {
+    // Scene or included scene file which cannot be read
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // Scene description which is not valid JSON or does not match the scene format
+    Parse(serde_json::Error),
+    Texture {
+        path: PathBuf,
+        source: ImageError,
+    },
+    EnvironmentMap {
+        path: PathBuf,
+        source: ImageError,
+    },
+    Mesh {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    // PLY or STL file which cannot be read
+    MeshData {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Gltf {
+        path: PathBuf,
+        source: gltf::Error,
+    },
+    // Scene description with values which cannot be rendered
+    InvalidScene(Vec<Diagnostic>),
+    // Included scene file which is not a valid scene
+    Include {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    // Material name used by an object which is not in the scene's materials
+    UnknownMaterial(String),
+    // Prototype name used by an instance which is not in the scene's prototypes
+    UnknownPrototype(String),
+    // Prototype containing instances, which cannot be nested
+    NestedInstance(String),
+    // Texture used by a material which was not loaded before building the scene
+    MissingTexture(String),
+    // Scene which cannot be changed or loaded again once its assets are loaded
+    AssetsLoaded,
+    InvalidAxis(usize),
+    // Rendered image which cannot be written
+    Image(ImageError),
+    Window(minifb::Error),
+    Denoise(String),
+}
+
+impl fmt::Display for RaytraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaytraceError::Io { path, source } => {
+                write!(f, "failed to read \"{}\": {source}", path.display())
+            }
+            RaytraceError::Parse(err) => write!(f, "failed to parse scene: {err}"),
+            RaytraceError::Texture { path, source } => write!(
+                f,
+                "failed to load texture at path \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::EnvironmentMap { path, source } => write!(
+                f,
+                "failed to load environment map at path \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::Mesh { path, source } => write!(
+                f,
+                "failed to load object at path \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::MeshData { path, source } => write!(
+                f,
+                "failed to load mesh at path \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::Gltf { path, source } => write!(
+                f,
+                "failed to load glTF file at path \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::InvalidScene(diagnostics) => {
+                write!(f, "scene is invalid:")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {diagnostic}")?;
+                }
+
+                Ok(())
+            }
+            RaytraceError::Include { path, source } => write!(
+                f,
+                "failed to parse included scene \"{}\": {source}",
+                path.display()
+            ),
+            RaytraceError::UnknownMaterial(name) => write!(f, "material \"{name}\" is not defined"),
+            RaytraceError::UnknownPrototype(name) => {
+                write!(f, "prototype \"{name}\" is not defined")
+            }
+            RaytraceError::NestedInstance(name) => {
+                write!(
+                    f,
+                    "prototype \"{name}\" contains instances, which cannot be nested"
+                )
+            }
+            RaytraceError::MissingTexture(texture_path) => {
+                write!(f, "texture \"{texture_path}\" is not loaded")
+            }
+            RaytraceError::AssetsLoaded => write!(f, "scene assets are already loaded"),
+            RaytraceError::InvalidAxis(axis) => write!(f, "{axis} is not a valid 3D axis"),
+            RaytraceError::Image(err) => write!(f, "failed to write image: {err}"),
+            RaytraceError::Window(err) => write!(f, "failed to render to a window: {err}"),
+            RaytraceError::Denoise(message) => write!(f, "failed to denoise image: {message}"),
+        }
+    }
+}
+
+impl Error for RaytraceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RaytraceError::Io { source, .. } | RaytraceError::MeshData { source, .. } => {
+                Some(source)
+            }
+            RaytraceError::Parse(err) | RaytraceError::Include { source: err, .. } => Some(err),
+            RaytraceError::Texture { source, .. }
+            | RaytraceError::EnvironmentMap { source, .. } => Some(source),
+            RaytraceError::Mesh { source, .. } => Some(source),
+            RaytraceError::Gltf { source, .. } => Some(source),
+            RaytraceError::Image(err) => Some(err),
+            RaytraceError::Window(err) => Some(err),
+            RaytraceError::InvalidScene(_)
+            | RaytraceError::UnknownMaterial(_)
+            | RaytraceError::UnknownPrototype(_)
+            | RaytraceError::NestedInstance(_)
+            | RaytraceError::MissingTexture(_)
+            | RaytraceError::AssetsLoaded
+            | RaytraceError::InvalidAxis(_)
+            | RaytraceError::Denoise(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for RaytraceError {
+    fn from(err: serde_json::Error) -> Self {
+        RaytraceError::Parse(err)
+    }
+}
+
+impl From<ImageError> for RaytraceError {
+    fn from(err: ImageError) -> Self {
+        RaytraceError::Image(err)
+    }
+}
+
+impl From<minifb::Error> for RaytraceError {
+    fn from(err: minifb::Error) -> Self {
+        RaytraceError::Window(err)
+    }
+}
@@ -0,0 +1,43 @@
+mod path_tracing;
+mod sampler;
+mod whitted;
+
+use crate::ray_intersection::Ray;
+use crate::render::{CastStats, ColorData, RaytracingScene};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::marker::{Send, Sync};
+
+pub use path_tracing::PathTracingIntegrator;
+pub use sampler::Sampler;
+pub use whitted::WhittedIntegrator;
+
+// Computes the radiance arriving along a camera ray, counting every ray cast in `cast_stats`
+pub trait Integrator: Send + Sync + Debug {
+    fn radiance(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        sampler: &mut Sampler,
+        cast_stats: &mut CastStats,
+    ) -> ColorData;
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegratorType {
+    // Direct lighting with traced reflections/refractions, cosine sampled illumination rays and ambient occlusion
+    #[default]
+    Whitted,
+    // Unidirectional path tracing with next-event estimation and Russian roulette
+    Path,
+}
+
+impl From<IntegratorType> for Box<dyn Integrator> {
+    fn from(integrator_type: IntegratorType) -> Self {
+        match integrator_type {
+            IntegratorType::Whitted => Box::new(WhittedIntegrator),
+            IntegratorType::Path => Box::new(PathTracingIntegrator),
+        }
+    }
+}
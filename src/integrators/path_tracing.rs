@@ -0,0 +1,226 @@
+use super::{Integrator, Sampler};
+use crate::core::Bsdf;
+use crate::ray_intersection::{Intersection, Ray, RayType};
+use crate::render::{CastStats, ColorData, RaytracingScene, BIAS};
+use crate::utils;
+use nalgebra::{Unit, Vector3};
+use num_traits::identities::Zero;
+use rand::Rng;
+
+const RUSSIAN_ROULETTE_DEPTH: u8 = 3;
+
+#[derive(Debug)]
+pub struct PathTracingIntegrator;
+
+impl Integrator for PathTracingIntegrator {
+    fn radiance(
+        &self,
+        scene: &RaytracingScene,
+        camera_ray: &Ray,
+        sampler: &mut Sampler,
+        cast_stats: &mut CastStats,
+    ) -> ColorData {
+        let mut color_data = ColorData::black();
+        let mut radiance = Vector3::zero();
+        // Emission seen directly and light reaching the first hit, either sampled or hit after one bounce
+        let mut direct = Vector3::zero();
+        let mut throughput = Vector3::repeat(1.0);
+        // Density of the BSDF sample which produced the current ray, if it can be weighted against light sampling
+        let mut bsdf_pdf = None;
+
+        let mut ray = Ray {
+            ray_type: camera_ray.ray_type,
+            origin: camera_ray.origin,
+            direction: camera_ray.direction,
+            refractive_index: camera_ray.refractive_index,
+            time: camera_ray.time,
+        };
+
+        loop {
+            let depth = ray.get_depth();
+            if depth >= scene.render_options.max_depth {
+                break;
+            }
+
+            cast_stats.ray_count += 1;
+            let intersection = scene.raycast(&ray);
+
+            let max_distance = intersection
+                .as_ref()
+                .map_or(f64::INFINITY, |intersection| intersection.distance);
+            if let Some((light, light_intersection)) = scene.light_cast(&ray, max_distance) {
+                let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| {
+                    let direction = Unit::new_normalize(ray.direction);
+                    utils::power_heuristic(bsdf_pdf, light.pdf(&ray.origin, &direction))
+                });
+                let contribution = throughput.component_mul(&light_intersection.radiance) * weight;
+                radiance += contribution;
+                if depth <= 1 {
+                    direct += contribution;
+                }
+                break;
+            }
+
+            let Some(mut intersection) = intersection else {
+                if depth > 0 {
+                    let environment_radiance =
+                        Self::get_environment_radiance(scene, &ray, bsdf_pdf);
+                    let contribution = throughput.component_mul(&environment_radiance);
+                    radiance += contribution;
+                    if depth <= 1 {
+                        direct += contribution;
+                    }
+                } else {
+                    let background = scene.get_background(&Unit::new_normalize(ray.direction));
+                    radiance += background;
+                    direct += background;
+                }
+                break;
+            };
+            intersection.compute_data(&ray);
+
+            let hit_point = intersection.get_hit_point();
+            let normal = intersection.get_normal();
+            let view_dir = Unit::new_normalize(-ray.direction);
+
+            let material = intersection.get_material();
+            let bsdf = Bsdf::new(&intersection, scene.get_textures());
+
+            if depth == 0 {
+                color_data.albedo = bsdf.albedo();
+                color_data.normal = normal;
+            }
+
+            let emissive = material.emissive();
+            if !emissive.is_zero() {
+                let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| {
+                    let light_pdf = scene.emitter_pdf(&intersection, normal.dot(&view_dir).abs());
+                    utils::power_heuristic(bsdf_pdf, light_pdf)
+                });
+                let contribution = throughput.component_mul(&emissive) * weight;
+                radiance += contribution;
+                if depth <= 1 {
+                    direct += contribution;
+                }
+            }
+
+            let direct_lighting = Self::estimate_direct_lighting(
+                scene,
+                &intersection,
+                &ray,
+                &bsdf,
+                sampler,
+                cast_stats,
+            );
+            radiance += throughput.component_mul(&direct_lighting);
+            if depth == 0 {
+                direct += direct_lighting;
+            }
+
+            let Some(sample) = bsdf.sample(&normal, &view_dir, ray.refractive_index, sampler)
+            else {
+                break;
+            };
+            throughput.component_mul_assign(&sample.weight);
+            bsdf_pdf = (!sample.is_specular).then_some(sample.pdf);
+
+            if depth + 1 >= RUSSIAN_ROULETTE_DEPTH {
+                let survival_probability = throughput.max().min(1.0);
+                if sampler.gen::<f64>() >= survival_probability {
+                    break;
+                }
+                throughput /= survival_probability;
+            }
+
+            let direction = sample.direction.into_inner();
+            ray = Ray {
+                ray_type: RayType::Secondary(depth + 1),
+                origin: hit_point + direction * BIAS,
+                direction,
+                refractive_index: sample.refractive_index,
+                time: ray.time,
+            };
+        }
+
+        color_data.color = radiance;
+        color_data.direct = direct;
+        color_data
+    }
+}
+
+impl PathTracingIntegrator {
+    // Radiance from lights at infinity, weighted against the light sampling done at the previous hit
+    fn get_environment_radiance(
+        scene: &RaytracingScene,
+        ray: &Ray,
+        bsdf_pdf: Option<f64>,
+    ) -> Vector3<f64> {
+        let direction = Unit::new_normalize(ray.direction);
+
+        scene
+            .get_lights()
+            .iter()
+            .fold(Vector3::zero(), |acc, light| {
+                let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| {
+                    utils::power_heuristic(bsdf_pdf, light.pdf(&ray.origin, &direction))
+                });
+
+                acc + light.get_environment_radiance(&direction) * weight
+            })
+    }
+
+    fn estimate_direct_lighting(
+        scene: &RaytracingScene,
+        intersection: &Intersection<'_>,
+        ray: &Ray,
+        bsdf: &Bsdf,
+        sampler: &mut Sampler,
+        cast_stats: &mut CastStats,
+    ) -> Vector3<f64> {
+        let hit_point = intersection.get_hit_point();
+        let normal = intersection.get_normal();
+        let view_dir = Unit::new_normalize(-ray.direction);
+        let mut radiance = Vector3::zero();
+
+        for light in scene.get_lights() {
+            let Some(light_sample) = light.sample(&hit_point, sampler) else {
+                continue;
+            };
+
+            let light_dir = light_sample.direction;
+            let n_dot_l = normal.dot(&light_dir);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+
+            let f = bsdf.evaluate(&normal, &view_dir, &light_dir);
+            if f.is_zero() {
+                continue;
+            }
+
+            let shadow_ray = Ray {
+                ray_type: RayType::Shadow,
+                origin: hit_point + light_dir.into_inner() * BIAS,
+                direction: light_dir.into_inner(),
+                refractive_index: 1.0,
+                time: ray.time,
+            };
+
+            cast_stats.ray_count += 1;
+            if scene.shadow_cast(&shadow_ray, light_sample.distance) {
+                continue;
+            }
+
+            let weight = if light_sample.is_delta {
+                1.0
+            } else {
+                utils::power_heuristic(light_sample.pdf, bsdf.pdf(&normal, &view_dir, &light_dir))
+            };
+
+            radiance +=
+                f.component_mul(&light_sample.radiance) * n_dot_l * weight / light_sample.pdf;
+        }
+
+        radiance
+    }
+}
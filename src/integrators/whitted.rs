@@ -0,0 +1,593 @@
+use super::{Integrator, Sampler};
+use crate::core::{Material, PhongMaterial, PhysicalMaterial};
+use crate::lights::{Light, LightSample};
+use crate::ray_intersection::{Intersection, Ray, RayType};
+use crate::render::{CastStats, ColorData, RaytracingScene, BIAS};
+use crate::utils;
+use nalgebra::{Unit, Vector3};
+use num_traits::identities::Zero;
+use std::f64::consts::FRAC_1_PI;
+
+#[derive(Debug)]
+pub struct WhittedIntegrator;
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        sampler: &mut Sampler,
+        cast_stats: &mut CastStats,
+    ) -> ColorData {
+        let (color_data, stats) = self.get_color(scene, ray, sampler);
+        *cast_stats += stats;
+
+        color_data
+    }
+}
+
+impl WhittedIntegrator {
+    // Light arriving from a single light sample
+    fn get_light_color(light_sample: &LightSample) -> Vector3<f64> {
+        light_sample.radiance / light_sample.pdf
+    }
+
+    fn compute_global_illumination(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        intersection: &Intersection,
+        sampler: &mut Sampler,
+    ) -> (Vector3<f64>, f64, CastStats) {
+        let mut cast_stats = CastStats::zero();
+        let depth = ray.get_depth();
+        let d = 4_u16.pow(depth.into());
+        let illumination_rays = (scene.render_options.max_illumination_rays / d).max(1);
+
+        let mut incoming_emissive = Vector3::zero();
+        let mut ambient_occlusion = 0;
+        for _ in 0..illumination_rays {
+            let direction =
+                utils::cosine_sample_hemisphere(&intersection.get_normal(), sampler).into_inner();
+
+            let illumination_ray = Ray {
+                ray_type: RayType::Secondary(depth + 1),
+                origin: intersection.get_hit_point() + direction * BIAS,
+                direction,
+                refractive_index: 1.0,
+                time: ray.time,
+            };
+            let (emissive, illumination_stats, occluded) =
+                self.get_illumination(scene, &illumination_ray, false, sampler);
+            cast_stats += illumination_stats;
+            incoming_emissive += emissive;
+
+            if !occluded {
+                ambient_occlusion += 1;
+            }
+        }
+
+        (
+            incoming_emissive / f64::from(illumination_rays),
+            f64::from(ambient_occlusion) / f64::from(illumination_rays),
+            cast_stats,
+        )
+    }
+
+    // Emission arriving at the eye through reflections off the surface
+    fn get_emissive_phong(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        intersection: &Intersection,
+        material: &PhongMaterial,
+        sampler: &mut Sampler,
+    ) -> (Vector3<f64>, CastStats) {
+        let mut cast_stats = CastStats::zero();
+        let depth = ray.get_depth();
+        let hit_point = intersection.get_hit_point();
+
+        let normal = intersection.get_normal();
+
+        let material_color = intersection.get_material_color(scene.get_textures());
+
+        let mut emissive = Vector3::zero();
+
+        if material.reflectivity > 0.0 {
+            let reflection_dir = utils::reflect(&ray.direction, &normal).into_inner();
+            let reflection_ray = Ray {
+                ray_type: RayType::Secondary(depth + 1),
+                origin: hit_point + reflection_dir * BIAS,
+                direction: reflection_dir,
+                refractive_index: 1.0,
+                time: ray.time,
+            };
+            let (incoming_emissive, stats, _) =
+                self.get_illumination(scene, &reflection_ray, true, sampler);
+            cast_stats += stats;
+
+            emissive += incoming_emissive.component_mul(&material_color) * material.reflectivity;
+        }
+
+        (emissive, cast_stats)
+    }
+
+    fn get_color_phong(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        intersection: &Intersection,
+        material: &PhongMaterial,
+        sampler: &mut Sampler,
+    ) -> (ColorData, CastStats) {
+        let mut cast_stats = CastStats::zero();
+        let depth = ray.get_depth();
+        let hit_point = intersection.get_hit_point();
+
+        let normal = intersection.get_normal();
+
+        let material_color = intersection.get_material_color(scene.get_textures());
+
+        let reflection = if material.reflectivity > 0.0 {
+            let reflection_dir = utils::reflect(&ray.direction, &normal).into_inner();
+            let reflection_ray = Ray {
+                ray_type: RayType::Secondary(depth + 1),
+                origin: hit_point + reflection_dir * BIAS,
+                direction: reflection_dir,
+                refractive_index: 1.0,
+                time: ray.time,
+            };
+            let (mut color_data, stats) = self.get_color(scene, &reflection_ray, sampler);
+            color_data.color.component_mul_assign(&material_color);
+            cast_stats += stats;
+
+            Some(color_data)
+        } else {
+            None
+        };
+
+        let mut ambient_light = Vector3::zero();
+        let mut irradiance = Vector3::zero();
+        if material.reflectivity < 1.0 {
+            for light in scene.get_lights() {
+                match light {
+                    Light::Ambient(light) => {
+                        ambient_light += light.get_color().component_mul(&material_color);
+                    }
+                    light => {
+                        let Some(light_sample) = light.sample(&hit_point, sampler) else {
+                            continue;
+                        };
+                        let light_dir = light_sample.direction.into_inner();
+                        let light_distance = light_sample.distance;
+
+                        let n_dot_l = normal.dot(&light_dir);
+                        if n_dot_l > 0.0 {
+                            let shadow_ray = Ray {
+                                ray_type: RayType::Shadow,
+                                origin: hit_point + light_dir * BIAS,
+                                direction: light_dir,
+                                refractive_index: 1.0,
+                                time: ray.time,
+                            };
+
+                            cast_stats.ray_count += 1;
+                            if !scene.shadow_cast(&shadow_ray, light_distance) {
+                                let light_color = Self::get_light_color(&light_sample);
+                                irradiance += light_color.component_mul(&material_color) * n_dot_l;
+
+                                let half_vec = Unit::new_normalize(light_dir - ray.direction);
+                                let n_dot_h = normal.dot(&half_vec);
+                                if n_dot_h > 0.0 {
+                                    irradiance += light_color.component_mul(&material.specular)
+                                        * n_dot_h.powf(material.shininess);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let (incoming_emissive, ambient_occlusion, illumination_stats) =
+            self.compute_global_illumination(scene, ray, intersection, sampler);
+        cast_stats += illumination_stats;
+
+        let mut color_data = ColorData::new(
+            material.emissive
+                + (ambient_light + irradiance + incoming_emissive.component_mul(&material_color))
+                    * ambient_occlusion,
+            material_color,
+            normal,
+        );
+        color_data.direct = color_data.color;
+
+        if let Some(reflection) = reflection {
+            color_data.color = color_data
+                .color
+                .lerp(&reflection.color, material.reflectivity);
+            color_data.direct *= 1.0 - material.reflectivity;
+        }
+
+        (color_data, cast_stats)
+    }
+
+    // Reflect off a microfacet normal sampled from the visible GGX normals, returning the reflected direction
+    // with the masking weight of the outgoing ray
+    fn sample_glossy_reflection(
+        normal: &Unit<Vector3<f64>>,
+        view_dir: &Unit<Vector3<f64>>,
+        roughness: f64,
+        sampler: &mut Sampler,
+    ) -> Option<(Vector3<f64>, f64)> {
+        let microfacet_normal =
+            utils::ggx_sample_visible_microfacet(normal, view_dir, roughness, sampler);
+        let direction = utils::reflect(&-view_dir.into_inner(), &microfacet_normal);
+
+        let n_dot_l = normal.dot(&direction);
+        if n_dot_l <= 0.0 {
+            return None;
+        }
+
+        Some((
+            direction.into_inner(),
+            utils::smith_masking(n_dot_l, roughness),
+        ))
+    }
+
+    // Emission arriving at the eye through reflections off and refractions through the surface
+    fn get_emissive_physical(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        intersection: &Intersection,
+        material: &PhysicalMaterial,
+        sampler: &mut Sampler,
+    ) -> (Vector3<f64>, CastStats) {
+        let mut cast_stats = CastStats::zero();
+        let depth = ray.get_depth();
+        let hit_point = intersection.get_hit_point();
+
+        let normal = intersection.get_normal();
+
+        let uv = intersection.get_uv();
+        let material_color = intersection.get_material_color(scene.get_textures());
+        let (roughness, _) = material.get_roughness_metalness(uv, scene.get_textures());
+
+        let reflected_emissive = if scene.render_options.max_reflected_rays > 0 {
+            let d = 8_u16.pow(depth.into());
+            let reflected_rays = (scene.render_options.max_reflected_rays / d).max(1);
+
+            let view_dir = Unit::new_normalize(-ray.direction);
+
+            let mut emissive = (0..reflected_rays).fold(Vector3::zero(), |mut acc, _| {
+                let Some((direction, weight)) =
+                    Self::sample_glossy_reflection(&normal, &view_dir, roughness, sampler)
+                else {
+                    return acc;
+                };
+                let reflection_ray = Ray {
+                    ray_type: RayType::Secondary(depth + 1),
+                    origin: hit_point + direction * BIAS,
+                    direction,
+                    refractive_index: 1.0,
+                    time: ray.time,
+                };
+                let (incoming_emissive, stats, _) =
+                    self.get_illumination(scene, &reflection_ray, true, sampler);
+                cast_stats += stats;
+
+                acc += incoming_emissive * weight;
+                acc
+            });
+            emissive /= f64::from(reflected_rays);
+            emissive.component_mul_assign(&material_color);
+
+            Some(emissive)
+        } else {
+            None
+        };
+
+        let refracted_emissive = if material.opacity < 1.0 {
+            let eta = ray.refractive_index / material.refractive_index;
+            utils::refract(&ray.direction, &normal, eta).map(|refraction_dir| {
+                let refraction_dir = refraction_dir.into_inner();
+                let refraction_ray = Ray {
+                    ray_type: RayType::Secondary(depth + 1),
+                    origin: hit_point + refraction_dir * BIAS,
+                    direction: refraction_dir,
+                    refractive_index: material.refractive_index,
+                    time: ray.time,
+                };
+                let (passthrough_emissive, stats, _) =
+                    self.get_illumination(scene, &refraction_ray, true, sampler);
+                cast_stats += stats;
+
+                passthrough_emissive
+            })
+        } else {
+            None
+        };
+
+        let mut emissive = Vector3::zero();
+
+        if let Some(reflected_emissive) = reflected_emissive {
+            emissive += reflected_emissive;
+        }
+
+        if let Some(refracted_emissive) = refracted_emissive {
+            emissive += refracted_emissive * (1.0 - material.opacity);
+        }
+
+        (emissive, cast_stats)
+    }
+
+    fn get_color_physical(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        intersection: &Intersection,
+        material: &PhysicalMaterial,
+        sampler: &mut Sampler,
+    ) -> (ColorData, CastStats) {
+        let mut cast_stats = CastStats::zero();
+        let depth = ray.get_depth();
+        let hit_point = intersection.get_hit_point();
+
+        let normal = intersection.get_normal();
+        let view_dir = Unit::new_normalize(-ray.direction);
+        let n_dot_v = normal.dot(&view_dir).max(0.0);
+
+        let uv = intersection.get_uv();
+        let material_color = intersection.get_material_color(scene.get_textures());
+        let (material_roughness, metalness) =
+            material.get_roughness_metalness(uv, scene.get_textures());
+
+        let roughness = material_roughness.max(0.04);
+        let base_reflectivity = Vector3::repeat(0.04).lerp(&material_color, metalness);
+        let f = utils::fresnel(n_dot_v, base_reflectivity);
+        let k_s = f;
+        let k_d = (Vector3::repeat(1.0) - k_s) * (1.0 - metalness);
+
+        let reflection = if scene.render_options.max_reflected_rays > 0 {
+            let d = 8_u16.pow(depth.into());
+            let reflected_rays = (scene.render_options.max_reflected_rays / d).max(1);
+
+            let mut reflection = (0..reflected_rays).fold(ColorData::zero(), |mut acc, _| {
+                let Some((direction, weight)) =
+                    Self::sample_glossy_reflection(&normal, &view_dir, material_roughness, sampler)
+                else {
+                    return acc;
+                };
+                let reflection_ray = Ray {
+                    ray_type: RayType::Secondary(depth + 1),
+                    origin: hit_point + direction * BIAS,
+                    direction,
+                    refractive_index: 1.0,
+                    time: ray.time,
+                };
+                let (color_data, stats) = self.get_color(scene, &reflection_ray, sampler);
+                cast_stats += stats;
+
+                acc.color += color_data.color * weight;
+                acc
+            });
+            reflection.color /= f64::from(reflected_rays);
+
+            Some(reflection)
+        } else {
+            None
+        };
+
+        let refraction = if material.opacity < 1.0 {
+            let eta = ray.refractive_index / material.refractive_index;
+            utils::refract(&ray.direction, &normal, eta).map(|refraction_dir| {
+                let refraction_dir = refraction_dir.into_inner();
+                let refraction_ray = Ray {
+                    ray_type: RayType::Secondary(depth + 1),
+                    origin: hit_point + refraction_dir * BIAS,
+                    direction: refraction_dir,
+                    refractive_index: material.refractive_index,
+                    time: ray.time,
+                };
+                let (mut refraction, stats) = self.get_color(scene, &refraction_ray, sampler);
+                cast_stats += stats;
+
+                refraction
+                    .color
+                    .component_mul_assign(&Vector3::repeat(1.0).lerp(&f, material.opacity));
+
+                refraction
+            })
+        } else {
+            None
+        };
+
+        let mut ambient_light = Vector3::zero();
+        let mut irradiance = Vector3::zero();
+        let diffuse = FRAC_1_PI * k_d.component_mul(&material_color);
+        for light in scene.get_lights() {
+            match light {
+                Light::Ambient(light) => {
+                    ambient_light += light.get_color().component_mul(&material_color);
+                }
+                light => {
+                    let Some(light_sample) = light.sample(&hit_point, sampler) else {
+                        continue;
+                    };
+                    let light_dir = light_sample.direction.into_inner();
+                    let light_distance = light_sample.distance;
+
+                    let n_dot_l = normal.dot(&light_dir);
+                    if n_dot_l > 0.0 {
+                        let shadow_ray = Ray {
+                            ray_type: RayType::Shadow,
+                            origin: hit_point + light_dir * BIAS,
+                            direction: light_dir,
+                            refractive_index: 1.0,
+                            time: ray.time,
+                        };
+
+                        cast_stats.ray_count += 1;
+                        if !scene.shadow_cast(&shadow_ray, light_distance) {
+                            let half_vec = Unit::new_normalize(light_dir - ray.direction);
+                            let n_dot_h = normal.dot(&half_vec).max(0.0);
+
+                            let light_color = Self::get_light_color(&light_sample);
+                            let radiance = light_color * n_dot_l;
+
+                            let ndf = utils::ndf(n_dot_h, roughness);
+                            let g = utils::geometry_function(n_dot_v, n_dot_l, roughness);
+
+                            // Reflection rays already reach lights which are not delta lights, so only delta
+                            // lights add a highlight here when the reflection is traced
+                            let diffuse_specular = if n_dot_v == 0.0
+                                || (reflection.is_some() && !light_sample.is_delta)
+                            {
+                                diffuse
+                            } else {
+                                let specular = ndf * g * f / (4.0 * n_dot_v * n_dot_l);
+                                diffuse + specular
+                            };
+
+                            irradiance += diffuse_specular.component_mul(&radiance) * n_dot_l;
+                        }
+                    }
+                }
+            }
+        }
+
+        let (incoming_emissive, ambient_occlusion, illumination_stats) =
+            self.compute_global_illumination(scene, ray, intersection, sampler);
+        cast_stats += illumination_stats;
+
+        let mut color_data = ColorData::new(
+            material.emissive
+                + (ambient_light + irradiance + incoming_emissive.component_mul(&diffuse))
+                    * ambient_occlusion,
+            material_color,
+            normal,
+        );
+        color_data.direct = color_data.color;
+
+        if let Some(reflection) = reflection {
+            color_data.color = Vector3::new(
+                utils::lerp(color_data.color.x, reflection.color.x, f.x),
+                utils::lerp(color_data.color.y, reflection.color.y, f.y),
+                utils::lerp(color_data.color.z, reflection.color.z, f.z),
+            );
+            color_data
+                .direct
+                .component_mul_assign(&(Vector3::repeat(1.0) - f));
+        }
+
+        if let Some(refraction) = refraction {
+            color_data.color = refraction.color.lerp(&color_data.color, material.opacity);
+            color_data.direct *= material.opacity;
+            color_data.normal = refraction.normal;
+        }
+
+        (color_data, cast_stats)
+    }
+
+    // Emission of primitives which are sampled as lights is skipped when `include_sampled_emission` is false, as
+    // it is already accounted for by direct lighting
+    #[allow(clippy::option_if_let_else)]
+    fn get_illumination(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        include_sampled_emission: bool,
+        sampler: &mut Sampler,
+    ) -> (Vector3<f64>, CastStats, bool) {
+        let mut cast_stats = CastStats::zero();
+
+        if ray.get_depth() >= scene.render_options.max_depth {
+            return (Vector3::zero(), cast_stats, false);
+        }
+
+        cast_stats.ray_count += 1;
+        if let Some(mut intersection) = scene.raycast(ray) {
+            intersection.compute_data(ray);
+
+            let material = intersection.get_material();
+            let (mut emissive, material_stats) = match material {
+                Material::Phong(material) => {
+                    self.get_emissive_phong(scene, ray, &intersection, material, sampler)
+                }
+                Material::Physical(material) => {
+                    self.get_emissive_physical(scene, ray, &intersection, material, sampler)
+                }
+            };
+            cast_stats += material_stats;
+
+            if include_sampled_emission || intersection.get_emitter_shape().is_none() {
+                emissive += material.emissive();
+            }
+
+            (
+                emissive,
+                cast_stats,
+                intersection.distance <= scene.render_options.max_occlusion_distance,
+            )
+        } else {
+            (Vector3::zero(), cast_stats, false)
+        }
+    }
+
+    #[allow(clippy::option_if_let_else)]
+    fn get_color(
+        &self,
+        scene: &RaytracingScene,
+        ray: &Ray,
+        sampler: &mut Sampler,
+    ) -> (ColorData, CastStats) {
+        let mut cast_stats = CastStats::zero();
+
+        if ray.get_depth() >= scene.render_options.max_depth {
+            return (ColorData::black(), cast_stats);
+        }
+
+        cast_stats.ray_count += 1;
+        let intersection = scene.raycast(ray);
+
+        let max_distance = intersection
+            .as_ref()
+            .map_or(f64::INFINITY, |intersection| intersection.distance);
+        if let Some((_, light_intersection)) = scene.light_cast(ray, max_distance) {
+            let radiance = light_intersection.radiance;
+            // Albedo stays a reflectance however bright the light is
+            let mut color_data = ColorData::new(
+                radiance,
+                radiance.map(|c| c.min(1.0)),
+                Unit::new_normalize(-ray.direction),
+            );
+            color_data.direct = radiance;
+
+            return (color_data, cast_stats);
+        }
+
+        if let Some(mut intersection) = intersection {
+            intersection.compute_data(ray);
+
+            let material = intersection.get_material();
+            let (color_data, material_stats) = match material {
+                Material::Phong(material) => {
+                    self.get_color_phong(scene, ray, &intersection, material, sampler)
+                }
+                Material::Physical(material) => {
+                    self.get_color_physical(scene, ray, &intersection, material, sampler)
+                }
+            };
+            cast_stats += material_stats;
+
+            (color_data, cast_stats)
+        } else {
+            let mut color_data = ColorData::black();
+            color_data.color = scene.get_background(&Unit::new_normalize(ray.direction));
+            color_data.direct = color_data.color;
+
+            (color_data, cast_stats)
+        }
+    }
+}
@@ -12,13 +12,34 @@
 )]
 
 mod core;
+mod error;
+mod integrators;
 mod lights;
 mod primitives;
 mod ray_intersection;
 mod render;
 mod utils;
 
-pub use crate::core::{Material, PhongMaterial, PhysicalMaterial, Transform};
-pub use crate::lights::{AmbientLight, Light, PointLight};
-pub use crate::primitives::{Cube, Group, Mesh, Object3D, Plane, Sphere, Triangle};
-pub use crate::render::{Camera, CastStats, RenderOptions, Scene};
+pub use crate::core::{
+    Bsdf, BsdfSample, Material, MaterialRef, PhongMaterial, PhysicalMaterial, Transform,
+};
+pub use crate::error::RaytraceError;
+pub use crate::integrators::{
+    Integrator, IntegratorType, PathTracingIntegrator, Sampler, WhittedIntegrator,
+};
+pub use crate::lights::{
+    AmbientLight, DirectionalLight, DiskLight, EmissiveLight, Emitter, EmitterShape, Environment,
+    EnvironmentMap, Light, LightIntersection, LightSample, PointLight, RectangleLight, Sky,
+    SphereLight, SpotLight,
+};
+pub use crate::primitives::{
+    Cube, Gltf, Group, HasMaterial, Instance, MaterialMode, Mesh, Object3D, Plane,
+    RaytracingObject, Sphere, Triangle, TriangleMesh,
+};
+pub use crate::ray_intersection::{Intersection, Ray, RayType};
+pub use crate::render::{
+    get_frame_path, save_image, save_passes, to_display_image, AssetCache, Camera, CastStats,
+    ColorData, Diagnostic, ExrPrecision, ObjectIdMap, ObjectIds, Projection, RaytracingScene,
+    RenderOptions, RenderPass, RenderPasses, Scene, SceneAnimation, SceneBuilder, StereoLayout,
+    ToneMapping,
+};
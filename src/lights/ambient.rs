@@ -1,8 +1,13 @@
+use super::LightSample;
+use crate::render::Validator;
+use crate::utils;
 use nalgebra::Vector3;
 use num_traits::identities::Zero;
-use serde::Deserialize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct AmbientLight {
     color: Vector3<f64>,
@@ -24,4 +29,23 @@ impl AmbientLight {
     pub fn get_color(&self) -> Vector3<f64> {
         self.color
     }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_color("color", &self.color);
+    }
+
+    // Ambient light is sampled as a uniform environment surrounding the scene
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> LightSample {
+        LightSample {
+            direction: utils::uniform_sample_sphere(rng),
+            distance: f64::INFINITY,
+            radiance: self.color,
+            pdf: self.pdf(),
+            is_delta: false,
+        }
+    }
+
+    pub fn pdf(&self) -> f64 {
+        0.25 / PI
+    }
 }
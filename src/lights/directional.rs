@@ -0,0 +1,109 @@
+use super::LightSample;
+use crate::render::Validator;
+use crate::utils;
+use nalgebra::{Unit, Vector3};
+use num_traits::identities::Zero;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+// Infinitely distant light shining along `direction`, optionally subtending a cone of `angular_diameter` degrees
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectionalLight {
+    direction: Unit<Vector3<f64>>,
+    color: Vector3<f64>,
+    irradiance: f64,
+    angular_diameter: f64,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: -Vector3::y_axis(),
+            color: Vector3::from([1.0; 3]),
+            irradiance: 1.0,
+            angular_diameter: 0.0,
+        }
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(
+        direction: Unit<Vector3<f64>>,
+        color: Vector3<f64>,
+        irradiance: f64,
+        angular_diameter: f64,
+    ) -> Self {
+        Self {
+            direction,
+            color,
+            irradiance,
+            angular_diameter,
+        }
+    }
+
+    fn get_cos_max_angle(&self) -> f64 {
+        (self.angular_diameter.to_radians() / 2.0).cos()
+    }
+
+    fn get_solid_angle(&self) -> f64 {
+        TAU * (1.0 - self.get_cos_max_angle())
+    }
+
+    fn is_delta(&self) -> bool {
+        self.get_solid_angle() <= 0.0
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_direction("direction", &self.direction);
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("irradiance", self.irradiance);
+        validator.check_range("angular_diameter", self.angular_diameter, 0.0..=180.0);
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> LightSample {
+        let light_dir = -self.direction;
+        let irradiance = self.color * self.irradiance;
+
+        if self.is_delta() {
+            return LightSample {
+                direction: light_dir,
+                distance: f64::INFINITY,
+                radiance: irradiance,
+                pdf: 1.0,
+                is_delta: true,
+            };
+        }
+
+        let solid_angle = self.get_solid_angle();
+        LightSample {
+            direction: utils::uniform_sample_spherical_cap(
+                &light_dir,
+                self.get_cos_max_angle(),
+                rng,
+            ),
+            distance: f64::INFINITY,
+            radiance: irradiance / solid_angle,
+            pdf: 1.0 / solid_angle,
+            is_delta: false,
+        }
+    }
+
+    pub fn pdf(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        if self.is_delta() || -self.direction.dot(direction) < self.get_cos_max_angle() {
+            0.0
+        } else {
+            1.0 / self.get_solid_angle()
+        }
+    }
+
+    // Radiance of the light disk, which is only visible with a non-zero angular diameter
+    pub fn get_environment_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        if self.pdf(direction) > 0.0 {
+            self.color * self.irradiance / self.get_solid_angle()
+        } else {
+            Vector3::zero()
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use super::planar::PlanarEmitter;
+use super::{LightIntersection, LightSample};
+use crate::core::{Transform, Transformed};
+use crate::render::Validator;
+use crate::utils;
+use nalgebra::{Point2, Point3, Unit, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+// Disk in the local xz-plane, centered on the origin and emitting towards +y
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskLight {
+    transform: Transform,
+    color: Vector3<f64>,
+    intensity: f64,
+    radius: f64,
+    two_sided: bool,
+}
+
+impl Default for DiskLight {
+    fn default() -> Self {
+        Self {
+            transform: Transform::default(),
+            color: Vector3::from([1.0; 3]),
+            intensity: 10.0,
+            radius: 1.0,
+            two_sided: false,
+        }
+    }
+}
+
+impl DiskLight {
+    pub fn new(
+        color: Vector3<f64>,
+        intensity: f64,
+        radius: f64,
+        two_sided: bool,
+        transform: Transform,
+    ) -> Self {
+        Self {
+            transform,
+            color,
+            intensity,
+            radius,
+            two_sided,
+        }
+    }
+
+    fn emitter(&self) -> PlanarEmitter<'_> {
+        PlanarEmitter::new(
+            &self.transform,
+            self.color * self.intensity,
+            PI * self.radius * self.radius,
+            self.two_sided,
+        )
+    }
+
+    fn contains(&self, local_point: Point2<f64>) -> bool {
+        local_point.coords.magnitude_squared() <= self.radius * self.radius
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transform("transform", &self.transform);
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("intensity", self.intensity);
+        validator.check_positive("radius", self.radius);
+    }
+
+    pub fn sample<R: Rng>(&self, point: &Point3<f64>, rng: &mut R) -> Option<LightSample> {
+        self.emitter()
+            .sample(point, || utils::concentric_sample_disk(rng) * self.radius)
+    }
+
+    pub fn pdf(&self, point: &Point3<f64>, direction: &Unit<Vector3<f64>>) -> f64 {
+        self.emitter()
+            .pdf(point, direction, |local_point| self.contains(local_point))
+    }
+
+    pub fn intersect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+    ) -> Option<LightIntersection> {
+        self.emitter()
+            .intersect(origin, direction, |local_point| self.contains(local_point))
+    }
+}
+
+impl Transformed for DiskLight {
+    fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+}
@@ -0,0 +1,149 @@
+use super::LightSample;
+use crate::core::MaterialSide;
+use crate::primitives::RaytracingObject;
+use crate::render::BIAS;
+use crate::utils;
+use nalgebra::{Point3, Unit, Vector3};
+use rand::Rng;
+use std::f64::consts::PI;
+
+// World space surface of a primitive which can be sampled uniformly by area
+#[derive(Debug)]
+pub enum EmitterShape {
+    Triangle([Point3<f64>; 3]),
+    // Non-uniformly scaled spheres are approximated by a sphere with the average scale
+    Sphere { center: Point3<f64>, radius: f64 },
+}
+
+impl EmitterShape {
+    fn get_area(&self) -> f64 {
+        match self {
+            EmitterShape::Triangle([a, b, c]) => (b - a).cross(&(c - a)).magnitude() / 2.0,
+            EmitterShape::Sphere { radius, .. } => 4.0 * PI * radius * radius,
+        }
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> (Point3<f64>, Unit<Vector3<f64>>) {
+        match self {
+            EmitterShape::Triangle([a, b, c]) => {
+                let r1 = rng.gen::<f64>().sqrt();
+                let r2 = rng.gen::<f64>();
+
+                let point = a + (b - a) * (r1 * (1.0 - r2)) + (c - a) * (r1 * r2);
+                (point, Unit::new_normalize((b - a).cross(&(c - a))))
+            }
+            EmitterShape::Sphere { center, radius } => {
+                let normal = utils::uniform_sample_sphere(rng);
+                (center + normal.into_inner() * *radius, normal)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Emitter {
+    shape: EmitterShape,
+    radiance: Vector3<f64>,
+    side: MaterialSide,
+}
+
+impl Emitter {
+    pub fn from_object(object: &dyn RaytracingObject) -> Option<Self> {
+        let material = object.get_material();
+        let radiance = material.emissive();
+        if utils::luminance(radiance) <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            shape: object.get_emitter_shape()?,
+            radiance,
+            side: material.side(),
+        })
+    }
+
+    fn get_power(&self) -> f64 {
+        utils::luminance(self.radiance) * self.shape.get_area()
+    }
+
+    // Cosine between the surface normal and a direction leaving the surface, if light is emitted that way
+    fn get_emitted_cosine(
+        &self,
+        normal: &Unit<Vector3<f64>>,
+        direction: &Vector3<f64>,
+    ) -> Option<f64> {
+        let cos_theta = normal.dot(direction);
+        let cos_theta = match self.side {
+            MaterialSide::Front => cos_theta,
+            MaterialSide::Back => -cos_theta,
+            MaterialSide::Both => cos_theta.abs(),
+        };
+
+        (cos_theta > 0.0).then_some(cos_theta)
+    }
+}
+
+// Emissive primitives sampled as a single light, choosing primitives in proportion to their emitted power
+#[derive(Debug)]
+pub struct EmissiveLight {
+    emitters: Vec<Emitter>,
+    cdf: Vec<f64>,
+    total_power: f64,
+}
+
+impl EmissiveLight {
+    pub fn new(emitters: Vec<Emitter>) -> Self {
+        let cdf: Vec<f64> = emitters
+            .iter()
+            .scan(0.0, |total, emitter| {
+                *total += emitter.get_power();
+                Some(*total)
+            })
+            .collect();
+        let total_power = cdf.last().copied().unwrap_or(0.0);
+
+        Self {
+            emitters,
+            cdf,
+            total_power,
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, point: &Point3<f64>, rng: &mut R) -> Option<LightSample> {
+        if self.total_power <= 0.0 {
+            return None;
+        }
+
+        let target = rng.gen::<f64>() * self.total_power;
+        let index = self
+            .cdf
+            .partition_point(|&power| power <= target)
+            .min(self.emitters.len() - 1);
+        let emitter = &self.emitters[index];
+
+        let (light_point, normal) = emitter.shape.sample(rng);
+        let light_dir = light_point - point;
+        let distance = light_dir.magnitude();
+        let light_dir = Unit::new_normalize(light_dir);
+        let cos_theta = emitter.get_emitted_cosine(&normal, &-light_dir.into_inner())?;
+
+        Some(LightSample {
+            direction: light_dir,
+            // Stop short of the emitting surface so that it does not shadow itself
+            distance: distance - BIAS,
+            radiance: emitter.radiance,
+            pdf: self.pdf(emitter.radiance, distance, cos_theta),
+            is_delta: false,
+        })
+    }
+
+    // Solid angle density of sampling a point emitting `radiance`, seen from `distance` away at an angle
+    // with cosine `cos_theta` to the emitting surface
+    pub fn pdf(&self, radiance: Vector3<f64>, distance: f64, cos_theta: f64) -> f64 {
+        if self.total_power <= 0.0 || cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        utils::luminance(radiance) / self.total_power * distance * distance / cos_theta
+    }
+}
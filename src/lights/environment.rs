@@ -0,0 +1,442 @@
+use super::{DirectionalLight, LightSample, Sky};
+use crate::render::{AssetCache, Validator};
+use crate::utils;
+use crate::RaytraceError;
+use image::codecs::hdr::HdrDecoder;
+use image::error::DecodingError;
+use image::{ImageError, ImageFormat, Rgb32FImage};
+use nalgebra::{Rotation3, Unit, Vector3};
+use num_traits::identities::Zero;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{PI, TAU};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+// Piecewise-constant distribution over [0, 1) built from non-negative function values
+struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_sum: f64,
+}
+
+impl Distribution1D {
+    fn new(func: Vec<f64>) -> Self {
+        let func_sum: f64 = func.iter().sum();
+        let cdf = func
+            .iter()
+            .scan(0.0, |total, value| {
+                *total += value;
+                Some(*total / func_sum)
+            })
+            .collect();
+
+        Self {
+            func,
+            cdf,
+            func_sum,
+        }
+    }
+
+    // Sample a bucket, returning its index and the offset within the bucket
+    fn sample(&self, u: f64) -> (usize, f64) {
+        let index = self
+            .cdf
+            .partition_point(|&cdf| cdf <= u)
+            .min(self.func.len() - 1);
+        let cdf_start = if index == 0 { 0.0 } else { self.cdf[index - 1] };
+        let offset = (u - cdf_start) / (self.cdf[index] - cdf_start);
+
+        (index, offset.clamp(0.0, 1.0))
+    }
+
+    fn pdf(&self, index: usize) -> f64 {
+        if self.func_sum == 0.0 {
+            return 0.0;
+        }
+
+        self.func[index] * self.func.len() as f64 / self.func_sum
+    }
+}
+
+// Equirectangular image which can be importance sampled by luminance, indexed by directions in its own frame
+pub(crate) struct EnvironmentImage {
+    image: Rgb32FImage,
+    marginal: Distribution1D,
+    conditionals: Vec<Distribution1D>,
+}
+
+impl fmt::Debug for EnvironmentImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EnvironmentImage {{ width: {}, height: {} }}",
+            self.image.width(),
+            self.image.height()
+        )
+    }
+}
+
+impl EnvironmentImage {
+    pub(super) fn new(image: Rgb32FImage) -> Self {
+        let (width, height) = image.dimensions();
+
+        // Weight each pixel by the solid angle it covers on the sphere
+        let conditionals: Vec<Distribution1D> = (0..height)
+            .map(|y| {
+                let sin_theta = (PI * (f64::from(y) + 0.5) / f64::from(height)).sin();
+                Distribution1D::new(
+                    (0..width)
+                        .map(|x| {
+                            let color = Vector3::from(image.get_pixel(x, y).0.map(f64::from));
+                            utils::luminance(color).max(0.0) * sin_theta
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        let marginal = Distribution1D::new(
+            conditionals
+                .iter()
+                .map(|conditional| conditional.func_sum)
+                .collect(),
+        );
+
+        Self {
+            image,
+            marginal,
+            conditionals,
+        }
+    }
+
+    fn get_pixel(&self, x: usize, y: usize) -> Vector3<f64> {
+        Vector3::from(self.image.get_pixel(x as u32, y as u32).0.map(f64::from))
+    }
+
+    fn get_pixel_coordinates(&self, uv: (f64, f64)) -> (usize, usize) {
+        let (width, height) = self.image.dimensions();
+        let x = ((uv.0 * f64::from(width)) as usize).min(width as usize - 1);
+        let y = ((uv.1 * f64::from(height)) as usize).min(height as usize - 1);
+
+        (x, y)
+    }
+
+    // Map a direction to image coordinates in [0, 1), with v = 0 at the top of the image
+    fn direction_to_uv(direction: &Unit<Vector3<f64>>) -> (f64, f64) {
+        let u = 0.5 + direction.x.atan2(-direction.z) / TAU;
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+        (u.rem_euclid(1.0), v)
+    }
+
+    pub(super) fn uv_to_direction(uv: (f64, f64)) -> Unit<Vector3<f64>> {
+        let phi = (uv.0 - 0.5) * TAU;
+        let theta = uv.1 * PI;
+
+        Unit::new_normalize(Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        ))
+    }
+
+    pub(super) fn get_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        let (x, y) = self.get_pixel_coordinates(Self::direction_to_uv(direction));
+        self.get_pixel(x, y)
+    }
+
+    // Importance sample a direction in proportion to the luminance of the image
+    pub(super) fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        if self.marginal.func_sum <= 0.0 {
+            return None;
+        }
+
+        let (y, v_offset) = self.marginal.sample(rng.gen());
+        let (x, u_offset) = self.conditionals[y].sample(rng.gen());
+
+        let (width, height) = self.image.dimensions();
+        let uv = (
+            (x as f64 + u_offset) / f64::from(width),
+            (y as f64 + v_offset) / f64::from(height),
+        );
+        let direction = Self::uv_to_direction(uv);
+        let pdf = self.pdf(&direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.get_pixel(x, y),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    pub(super) fn pdf(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        if self.marginal.func_sum <= 0.0 {
+            return 0.0;
+        }
+
+        let uv = Self::direction_to_uv(direction);
+        let sin_theta = (uv.1 * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let (x, y) = self.get_pixel_coordinates(uv);
+        let uv_pdf = self.marginal.pdf(y) * self.conditionals[y].pdf(x);
+
+        uv_pdf / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// Equirectangular image surrounding the scene, rotated about the y-axis by `rotation` degrees
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentMap {
+    file: String,
+    rotation: f64,
+    intensity: f64,
+
+    #[serde(skip)]
+    image: Option<Arc<EnvironmentImage>>,
+}
+
+impl Default for EnvironmentMap {
+    fn default() -> Self {
+        Self {
+            file: String::new(),
+            rotation: 0.0,
+            intensity: 1.0,
+            image: None,
+        }
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(file: &str, rotation: f64, intensity: f64) -> Self {
+        Self {
+            file: file.to_string(),
+            rotation,
+            intensity,
+            image: None,
+        }
+    }
+
+    fn read_image(path: &Path) -> Result<EnvironmentImage, ImageError> {
+        let is_hdr = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+
+        // The generic Radiance HDR decoder converts to 8-bit color, so read the float pixels directly
+        let image = if is_hdr {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+
+            Rgb32FImage::from_raw(
+                metadata.width,
+                metadata.height,
+                pixels.into_iter().flat_map(|pixel| pixel.0).collect(),
+            )
+            .ok_or_else(|| {
+                ImageError::Decoding(DecodingError::new(
+                    ImageFormat::Hdr.into(),
+                    "decoded HDR image does not match its dimensions",
+                ))
+            })?
+        } else {
+            image::open(path)?.into_rgb32f()
+        };
+
+        Ok(EnvironmentImage::new(image))
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the image is already loaded
+    pub fn load(&mut self, asset_base: &Path, assets: &mut AssetCache) -> Result<(), ImageError> {
+        assert!(self.image.is_none());
+
+        let path = asset_base.join(&self.file);
+        let image = assets.get_or_load_environment_image(&path, || Self::read_image(&path))?;
+        self.image = Some(image);
+
+        Ok(())
+    }
+
+    fn validate(&self, validator: &mut Validator) {
+        validator.check_file("file", &self.file);
+        validator.check_finite("rotation", self.rotation);
+        validator.check_non_negative("intensity", self.intensity);
+    }
+
+    fn get_rotation(&self) -> Rotation3<f64> {
+        Rotation3::from_axis_angle(&Vector3::y_axis(), self.rotation.to_radians())
+    }
+
+    fn to_image_direction(&self, direction: &Unit<Vector3<f64>>) -> Unit<Vector3<f64>> {
+        self.get_rotation().inverse() * direction
+    }
+
+    pub fn get_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        self.image.as_ref().map_or_else(Vector3::zero, |image| {
+            image.get_radiance(&self.to_image_direction(direction)) * self.intensity
+        })
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        let mut sample = self.image.as_ref()?.sample(rng)?;
+        sample.direction = self.get_rotation() * sample.direction;
+        sample.radiance *= self.intensity;
+
+        Some(sample)
+    }
+
+    pub fn pdf(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        self.image
+            .as_ref()
+            .map_or(0.0, |image| image.pdf(&self.to_image_direction(direction)))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Environment {
+    Map(Box<EnvironmentMap>),
+    Sky(Box<Sky>),
+}
+
+impl From<EnvironmentMap> for Environment {
+    fn from(value: EnvironmentMap) -> Self {
+        Environment::Map(Box::new(value))
+    }
+}
+
+impl From<Sky> for Environment {
+    fn from(value: Sky) -> Self {
+        Environment::Sky(Box::new(value))
+    }
+}
+
+impl Environment {
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        match self {
+            Environment::Map(map) => map.validate(validator),
+            Environment::Sky(sky) => sky.validate(validator),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the environment is already loaded
+    pub fn load_assets(
+        &mut self,
+        asset_base: &Path,
+        assets: &mut AssetCache,
+    ) -> Result<(), RaytraceError> {
+        match self {
+            Environment::Map(map) => {
+                map.load(asset_base, assets)
+                    .map_err(|source| RaytraceError::EnvironmentMap {
+                        path: asset_base.join(&map.file),
+                        source,
+                    })?;
+            }
+            Environment::Sky(sky) => sky.load(),
+        }
+
+        Ok(())
+    }
+
+    // Sun which is lit consistently with the environment, if any
+    pub fn get_sun_light(&self) -> Option<DirectionalLight> {
+        match self {
+            Environment::Map(_) => None,
+            Environment::Sky(sky) => sky.get_sun_light(),
+        }
+    }
+
+    pub fn get_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        match self {
+            Environment::Map(map) => map.get_radiance(direction),
+            Environment::Sky(sky) => sky.get_radiance(direction),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        match self {
+            Environment::Map(map) => map.sample(rng),
+            Environment::Sky(sky) => sky.sample(rng),
+        }
+    }
+
+    pub fn pdf(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgb;
+    use more_asserts::assert_le;
+
+    const PRECISION: f64 = 1e-6;
+
+    #[test]
+    fn it_maps_directions_to_image_coordinates_and_back() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..1_000 {
+            let direction = utils::uniform_sample_sphere(&mut rng);
+            let mapped =
+                EnvironmentImage::uv_to_direction(EnvironmentImage::direction_to_uv(&direction));
+
+            assert_le!(
+                (mapped.into_inner() - direction.into_inner()).magnitude(),
+                PRECISION
+            );
+        }
+    }
+
+    #[test]
+    fn it_gives_black_rows_a_zero_pdf() {
+        let distribution = Distribution1D::new(vec![0.0; 4]);
+
+        assert_eq!(distribution.pdf(2), 0.0);
+    }
+
+    #[test]
+    fn it_samples_bright_regions_of_the_environment() {
+        let mut image = Rgb32FImage::new(16, 8);
+        image.put_pixel(4, 2, Rgb([100.0, 100.0, 100.0]));
+
+        let mut map = EnvironmentMap::new("", 90.0, 2.0);
+        map.image = Some(Arc::new(EnvironmentImage::new(image)));
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let sample = map.sample(&mut rng).unwrap();
+            let image_direction = map.to_image_direction(&sample.direction);
+            let bright_pixel = map
+                .image
+                .as_ref()
+                .unwrap()
+                .get_pixel_coordinates(EnvironmentImage::direction_to_uv(&image_direction));
+
+            assert_eq!(bright_pixel, (4, 2));
+            assert_eq!(sample.radiance, Vector3::repeat(200.0));
+            assert_eq!(map.get_radiance(&sample.direction), sample.radiance);
+            assert!((sample.pdf - map.pdf(&sample.direction)).abs() < PRECISION);
+        }
+    }
+}
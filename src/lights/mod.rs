@@ -1,15 +1,188 @@
 mod ambient;
+mod directional;
+mod disk;
+mod emissive;
+mod environment;
+mod planar;
 mod point;
+mod rectangle;
+mod sky;
+mod sphere;
+mod spot;
 
-use serde::Deserialize;
+use crate::render::Validator;
+use nalgebra::{Point3, Unit, Vector3};
+use num_traits::identities::Zero;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 pub use ambient::AmbientLight;
+pub use directional::DirectionalLight;
+pub use disk::DiskLight;
+pub use emissive::{EmissiveLight, Emitter, EmitterShape};
+pub(crate) use environment::EnvironmentImage;
+pub use environment::{Environment, EnvironmentMap};
 pub use point::PointLight;
+pub use rectangle::RectangleLight;
+pub use sky::Sky;
+pub use sphere::SphereLight;
+pub use spot::SpotLight;
 
-#[derive(Debug, Deserialize)]
+// Light arriving at a point from a sampled direction
+pub struct LightSample {
+    // Direction from the lit point towards the light
+    pub direction: Unit<Vector3<f64>>,
+    pub distance: f64,
+    // Incident radiance, or irradiance for delta lights
+    pub radiance: Vector3<f64>,
+    // Solid angle density of the sampled direction, one for delta lights
+    pub pdf: f64,
+    pub is_delta: bool,
+}
+
+// Emitting surface of an area light hit by a ray
+pub struct LightIntersection {
+    pub distance: f64,
+    // Radiance emitted back towards the ray origin
+    pub radiance: Vector3<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Light {
     Ambient(AmbientLight),
     Point(Box<PointLight>),
+    Directional(Box<DirectionalLight>),
+    Spot(Box<SpotLight>),
+    Rectangle(Box<RectangleLight>),
+    Disk(Box<DiskLight>),
+    Sphere(Box<SphereLight>),
+    // Built from the emissive primitives of a scene rather than deserialized
+    #[serde(skip)]
+    Emissive(Box<EmissiveLight>),
+    // Built from the environment of a scene rather than deserialized
+    #[serde(skip)]
+    Environment(Box<Environment>),
+}
+
+impl Light {
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        match self {
+            Light::Ambient(light) => light.validate(validator),
+            Light::Point(light) => light.validate(validator),
+            Light::Directional(light) => light.validate(validator),
+            Light::Spot(light) => light.validate(validator),
+            Light::Rectangle(light) => light.validate(validator),
+            Light::Disk(light) => light.validate(validator),
+            Light::Sphere(light) => light.validate(validator),
+            Light::Emissive(_) => {}
+            Light::Environment(environment) => environment.validate(validator),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, point: &Point3<f64>, rng: &mut R) -> Option<LightSample> {
+        match self {
+            Light::Ambient(light) => Some(light.sample(rng)),
+            Light::Point(light) => light.sample(point),
+            Light::Directional(light) => Some(light.sample(rng)),
+            Light::Spot(light) => light.sample(point),
+            Light::Rectangle(light) => light.sample(point, rng),
+            Light::Disk(light) => light.sample(point, rng),
+            Light::Sphere(light) => light.sample(point, rng),
+            Light::Emissive(light) => light.sample(point, rng),
+            Light::Environment(environment) => environment.sample(rng),
+        }
+    }
+
+    // Solid angle density of sampling `direction` from `point`, zero for delta lights
+    pub fn pdf(&self, point: &Point3<f64>, direction: &Unit<Vector3<f64>>) -> f64 {
+        match self {
+            Light::Ambient(light) => light.pdf(),
+            Light::Directional(light) => light.pdf(direction),
+            Light::Environment(environment) => environment.pdf(direction),
+            Light::Rectangle(light) => light.pdf(point, direction),
+            Light::Disk(light) => light.pdf(point, direction),
+            Light::Sphere(light) => light.pdf(point, direction),
+            // Delta lights cannot be hit, and emissive primitives are hit through the scene geometry (see
+            // `RaytracingScene::emitter_pdf`)
+            Light::Point(_) | Light::Spot(_) | Light::Emissive(_) => 0.0,
+        }
+    }
+
+    // Find where a ray hits the emitting surface of an area light
+    pub fn intersect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+    ) -> Option<LightIntersection> {
+        match self {
+            Light::Ambient(_)
+            | Light::Point(_)
+            | Light::Directional(_)
+            | Light::Spot(_)
+            | Light::Emissive(_)
+            | Light::Environment(_) => None,
+            Light::Rectangle(light) => light.intersect(origin, direction),
+            Light::Disk(light) => light.intersect(origin, direction),
+            Light::Sphere(light) => light.intersect(origin, direction),
+        }
+    }
+
+    // Radiance arriving along a ray which leaves the scene in `direction`
+    pub fn get_environment_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        match self {
+            Light::Ambient(light) => light.get_color(),
+            Light::Directional(light) => light.get_environment_radiance(direction),
+            Light::Environment(environment) => environment.get_radiance(direction),
+            Light::Point(_)
+            | Light::Spot(_)
+            | Light::Rectangle(_)
+            | Light::Disk(_)
+            | Light::Sphere(_)
+            | Light::Emissive(_) => Vector3::zero(),
+        }
+    }
+}
+
+impl From<AmbientLight> for Light {
+    fn from(value: AmbientLight) -> Self {
+        Light::Ambient(value)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(value: PointLight) -> Self {
+        Light::Point(Box::new(value))
+    }
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(value: DirectionalLight) -> Self {
+        Light::Directional(Box::new(value))
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(value: SpotLight) -> Self {
+        Light::Spot(Box::new(value))
+    }
+}
+
+impl From<RectangleLight> for Light {
+    fn from(value: RectangleLight) -> Self {
+        Light::Rectangle(Box::new(value))
+    }
+}
+
+impl From<DiskLight> for Light {
+    fn from(value: DiskLight) -> Self {
+        Light::Disk(Box::new(value))
+    }
+}
+
+impl From<SphereLight> for Light {
+    fn from(value: SphereLight) -> Self {
+        Light::Sphere(Box::new(value))
+    }
 }
@@ -0,0 +1,114 @@
+use super::{LightIntersection, LightSample};
+use crate::core::Transform;
+use nalgebra::{Point2, Point3, Unit, Vector3};
+
+// Emitting shape in the local xz-plane of a transform, emitting towards +y
+pub(super) struct PlanarEmitter<'a> {
+    transform: &'a Transform,
+    radiance: Vector3<f64>,
+    area: f64,
+    two_sided: bool,
+}
+
+impl<'a> PlanarEmitter<'a> {
+    // `local_area` is the area of the shape before the transform is applied
+    pub fn new(
+        transform: &'a Transform,
+        radiance: Vector3<f64>,
+        local_area: f64,
+        two_sided: bool,
+    ) -> Self {
+        let matrix = transform.matrix();
+        let scale = (matrix * Vector3::x())
+            .cross(&(matrix * Vector3::z()))
+            .magnitude();
+
+        Self {
+            transform,
+            radiance,
+            area: local_area * scale,
+            two_sided,
+        }
+    }
+
+    fn get_normal(&self) -> Unit<Vector3<f64>> {
+        Unit::new_normalize(self.transform.inverse_transpose() * Vector3::y())
+    }
+
+    // Cosine between the light normal and the direction leaving the light, if light is emitted that way
+    fn get_emitted_cosine(&self, direction: &Unit<Vector3<f64>>) -> Option<f64> {
+        let cos_theta = self.get_normal().dot(direction);
+        let cos_theta = if self.two_sided {
+            cos_theta.abs()
+        } else {
+            cos_theta
+        };
+
+        (cos_theta > 0.0).then_some(cos_theta)
+    }
+
+    // Sample the light at the xz-coordinates returned by `sample_local_point`
+    pub fn sample(
+        &self,
+        point: &Point3<f64>,
+        sample_local_point: impl FnOnce() -> Point2<f64>,
+    ) -> Option<LightSample> {
+        let local_point = sample_local_point();
+        let local_point = Point3::new(local_point.x, 0.0, local_point.y);
+
+        let light_dir = self.transform.matrix() * local_point - point;
+        let distance = light_dir.magnitude();
+        let light_dir = Unit::new_normalize(light_dir);
+        let cos_theta = self.get_emitted_cosine(&-light_dir)?;
+
+        Some(LightSample {
+            direction: light_dir,
+            distance,
+            radiance: self.radiance,
+            pdf: distance * distance / (cos_theta * self.area),
+            is_delta: false,
+        })
+    }
+
+    pub fn pdf(
+        &self,
+        point: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+        contains: impl FnOnce(Point2<f64>) -> bool,
+    ) -> f64 {
+        self.intersect(point, direction, contains)
+            .and_then(|intersection| {
+                let cos_theta = self.get_emitted_cosine(&-*direction)?;
+                Some(intersection.distance.powi(2) / (cos_theta * self.area))
+            })
+            .unwrap_or(0.0)
+    }
+
+    // Intersect the plane of the light, keeping hits whose xz-coordinates satisfy `contains`
+    pub fn intersect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+        contains: impl FnOnce(Point2<f64>) -> bool,
+    ) -> Option<LightIntersection> {
+        let inverse = self.transform.inverse();
+        let local_origin = inverse * origin;
+        let local_direction = inverse * direction.into_inner();
+        if local_direction.y == 0.0 {
+            return None;
+        }
+
+        let distance = -local_origin.y / local_direction.y;
+        let local_point = local_origin + local_direction * distance;
+        if distance <= 0.0 || !contains(Point2::new(local_point.x, local_point.z)) {
+            return None;
+        }
+
+        self.get_emitted_cosine(&-*direction)?;
+
+        Some(LightIntersection {
+            distance,
+            radiance: self.radiance,
+        })
+    }
+}
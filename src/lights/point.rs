@@ -1,8 +1,10 @@
+use super::LightSample;
 use crate::core::{Transform, Transformed};
-use nalgebra::Vector3;
-use serde::Deserialize;
+use crate::render::Validator;
+use nalgebra::{Point3, Unit, Vector3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct PointLight {
     transform: Transform,
@@ -30,7 +32,33 @@ impl PointLight {
     }
 
     pub fn get_color(&self, distance: f64) -> Vector3<f64> {
-        (self.intensity * self.color / distance.powi(2)).map(|c| c.clamp(0.0, 1.0))
+        self.get_irradiance(distance).map(|c| c.clamp(0.0, 1.0))
+    }
+
+    pub fn get_irradiance(&self, distance: f64) -> Vector3<f64> {
+        self.intensity * self.color / distance.powi(2)
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transform("transform", &self.transform);
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("intensity", self.intensity);
+    }
+
+    pub fn sample(&self, point: &Point3<f64>) -> Option<LightSample> {
+        let light_dir = self.get_position() - point;
+        let distance = light_dir.magnitude();
+        if distance == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: Unit::new_normalize(light_dir),
+            distance,
+            radiance: self.get_irradiance(distance),
+            pdf: 1.0,
+            is_delta: true,
+        })
     }
 }
 
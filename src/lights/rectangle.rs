@@ -0,0 +1,102 @@
+use super::planar::PlanarEmitter;
+use super::{LightIntersection, LightSample};
+use crate::core::{Transform, Transformed};
+use crate::render::Validator;
+use nalgebra::{Point2, Point3, Unit, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// Rectangle in the local xz-plane, centered on the origin and emitting towards +y
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RectangleLight {
+    transform: Transform,
+    color: Vector3<f64>,
+    intensity: f64,
+    width: f64,
+    height: f64,
+    two_sided: bool,
+}
+
+impl Default for RectangleLight {
+    fn default() -> Self {
+        Self {
+            transform: Transform::default(),
+            color: Vector3::from([1.0; 3]),
+            intensity: 10.0,
+            width: 1.0,
+            height: 1.0,
+            two_sided: false,
+        }
+    }
+}
+
+impl RectangleLight {
+    pub fn new(
+        color: Vector3<f64>,
+        intensity: f64,
+        width: f64,
+        height: f64,
+        two_sided: bool,
+        transform: Transform,
+    ) -> Self {
+        Self {
+            transform,
+            color,
+            intensity,
+            width,
+            height,
+            two_sided,
+        }
+    }
+
+    fn emitter(&self) -> PlanarEmitter<'_> {
+        PlanarEmitter::new(
+            &self.transform,
+            self.color * self.intensity,
+            self.width * self.height,
+            self.two_sided,
+        )
+    }
+
+    fn contains(&self, local_point: Point2<f64>) -> bool {
+        local_point.x.abs() <= self.width / 2.0 && local_point.y.abs() <= self.height / 2.0
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transform("transform", &self.transform);
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("intensity", self.intensity);
+        validator.check_positive("width", self.width);
+        validator.check_positive("height", self.height);
+    }
+
+    pub fn sample<R: Rng>(&self, point: &Point3<f64>, rng: &mut R) -> Option<LightSample> {
+        self.emitter().sample(point, || {
+            Point2::new(
+                (rng.gen::<f64>() - 0.5) * self.width,
+                (rng.gen::<f64>() - 0.5) * self.height,
+            )
+        })
+    }
+
+    pub fn pdf(&self, point: &Point3<f64>, direction: &Unit<Vector3<f64>>) -> f64 {
+        self.emitter()
+            .pdf(point, direction, |local_point| self.contains(local_point))
+    }
+
+    pub fn intersect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+    ) -> Option<LightIntersection> {
+        self.emitter()
+            .intersect(origin, direction, |local_point| self.contains(local_point))
+    }
+}
+
+impl Transformed for RectangleLight {
+    fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+}
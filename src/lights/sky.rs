@@ -0,0 +1,305 @@
+use super::environment::EnvironmentImage;
+use super::{DirectionalLight, LightSample};
+use crate::render::Validator;
+use image::{Rgb, Rgb32FImage};
+use nalgebra::{Matrix3, Matrix3x4, Unit, Vector3, Vector4};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+// Illuminance of the sun above the atmosphere in kilolux, the unit of the sky model's luminance
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+// Resolution of the tabulated sky used for importance sampling
+const TABLE_WIDTH: u32 = 256;
+const TABLE_HEIGHT: u32 = 128;
+
+#[rustfmt::skip]
+const XYZ_TO_LINEAR_SRGB: [f64; 9] = [
+    3.2406, -1.5372, -0.4986,
+    -0.9689, 1.8758, 0.0415,
+    0.0557, -0.2040, 1.0570,
+];
+
+#[rustfmt::skip]
+const ZENITH_X_COEFFICIENTS: [f64; 12] = [
+    0.00166, -0.00375, 0.00209, 0.0,
+    -0.02903, 0.06377, -0.03202, 0.00394,
+    0.11693, -0.21196, 0.06052, 0.25886,
+];
+#[rustfmt::skip]
+const ZENITH_Y_COEFFICIENTS: [f64; 12] = [
+    0.00275, -0.00610, 0.00317, 0.0,
+    -0.04214, 0.08970, -0.04153, 0.00516,
+    0.15346, -0.26756, 0.06670, 0.26688,
+];
+
+// Perez distribution coefficients for one channel of the sky
+struct PerezCoefficients([f64; 5]);
+
+impl PerezCoefficients {
+    fn new(turbidity: f64, slopes: [f64; 5], offsets: [f64; 5]) -> Self {
+        let mut coefficients = offsets;
+        for (coefficient, slope) in coefficients.iter_mut().zip(slopes) {
+            *coefficient += slope * turbidity;
+        }
+
+        Self(coefficients)
+    }
+
+    // Relative sky value at zenith angle `theta` and angle `gamma` from the sun
+    fn evaluate(&self, theta: f64, gamma: f64) -> f64 {
+        let [darkening, gradient, circumsolar, circumsolar_falloff, backscattering] = self.0;
+        let cos_theta = theta.cos().max(0.01);
+
+        (1.0 + darkening * (gradient / cos_theta).exp())
+            * (1.0
+                + circumsolar * (circumsolar_falloff * gamma).exp()
+                + backscattering * gamma.cos().powi(2))
+    }
+}
+
+// Chromaticity of the zenith as a polynomial in turbidity (rows) and the sun's zenith angle (columns)
+fn get_zenith_chromaticity(turbidity: f64, sun_theta: f64, coefficients: [f64; 12]) -> f64 {
+    let turbidity_powers = Vector3::new(turbidity * turbidity, turbidity, 1.0);
+    let sun_theta_powers = Vector4::new(sun_theta.powi(3), sun_theta.powi(2), sun_theta, 1.0);
+
+    turbidity_powers.dot(&(Matrix3x4::from_row_slice(&coefficients) * sun_theta_powers))
+}
+
+// Clear daylight sky following Preetham et al., "A Practical Analytic Model for Daylight", with the sun
+// `sun_elevation` degrees above the horizon and `sun_azimuth` degrees clockwise from -z towards +x
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sky {
+    sun_elevation: f64,
+    sun_azimuth: f64,
+    turbidity: f64,
+    intensity: f64,
+    sun: bool,
+
+    #[serde(skip)]
+    table: Option<EnvironmentImage>,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            intensity: 0.02,
+            sun: true,
+            table: None,
+        }
+    }
+}
+
+impl Sky {
+    pub fn new(
+        sun_elevation: f64,
+        sun_azimuth: f64,
+        turbidity: f64,
+        intensity: f64,
+        sun: bool,
+    ) -> Self {
+        Self {
+            sun_elevation,
+            sun_azimuth,
+            turbidity,
+            intensity,
+            sun,
+            table: None,
+        }
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_range("sun_elevation", self.sun_elevation, -90.0..=90.0);
+        validator.check_finite("sun_azimuth", self.sun_azimuth);
+        validator.check_positive("turbidity", self.turbidity);
+        validator.check_non_negative("intensity", self.intensity);
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the sky is already tabulated
+    pub fn load(&mut self) {
+        assert!(self.table.is_none());
+
+        let mut image = Rgb32FImage::new(TABLE_WIDTH, TABLE_HEIGHT);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let direction = EnvironmentImage::uv_to_direction((
+                (f64::from(x) + 0.5) / f64::from(TABLE_WIDTH),
+                (f64::from(y) + 0.5) / f64::from(TABLE_HEIGHT),
+            ));
+            *pixel = Rgb(self.get_radiance(&direction).map(|c| c as f32).into());
+        }
+        self.table = Some(EnvironmentImage::new(image));
+    }
+
+    // Direction from the scene towards the sun
+    pub fn get_sun_direction(&self) -> Unit<Vector3<f64>> {
+        let elevation = self.sun_elevation.to_radians();
+        let azimuth = self.sun_azimuth.to_radians();
+
+        Unit::new_normalize(Vector3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        ))
+    }
+
+    // Light from the sun disk after attenuation by the atmosphere, or `None` when the sun is disabled or set
+    pub fn get_sun_light(&self) -> Option<DirectionalLight> {
+        if !self.sun || self.sun_elevation <= 0.0 {
+            return None;
+        }
+
+        let transmittance = self.get_sun_transmittance();
+        let irradiance = SOLAR_ILLUMINANCE * self.intensity;
+
+        Some(DirectionalLight::new(
+            -self.get_sun_direction(),
+            transmittance,
+            irradiance,
+            SUN_ANGULAR_DIAMETER,
+        ))
+    }
+
+    // Rayleigh and aerosol extinction along the path of sunlight through the atmosphere, sampled at the
+    // wavelengths of red, green and blue light
+    fn get_sun_transmittance(&self) -> Vector3<f64> {
+        let zenith_degrees = 90.0 - self.sun_elevation;
+        let air_mass = 1.0
+            / (zenith_degrees.to_radians().cos() + 0.15 * (93.885 - zenith_degrees).powf(-1.253));
+        let beta = 0.046_08 * self.turbidity - 0.045_86;
+
+        Vector3::new(0.68, 0.55, 0.44).map(|wavelength: f64| {
+            let rayleigh = 0.008_735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+
+            (-air_mass * (rayleigh + aerosol)).exp()
+        })
+    }
+
+    pub fn get_radiance(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        if direction.y <= 0.0 {
+            return Vector3::zeros();
+        }
+
+        let turbidity = self.turbidity;
+        let sun_direction = self.get_sun_direction();
+        let sun_theta = sun_direction.y.clamp(0.0, 1.0).acos().min(FRAC_PI_2);
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let gamma = direction.dot(&sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance_coefficients = PerezCoefficients::new(
+            turbidity,
+            [0.1787, -0.3554, -0.0227, 0.1206, -0.0670],
+            [-1.4630, 0.4275, 5.3251, -2.5771, 0.3703],
+        );
+        let x_coefficients = PerezCoefficients::new(
+            turbidity,
+            [-0.0193, -0.0665, -0.0004, -0.0641, -0.0033],
+            [-0.2592, 0.0008, 0.2125, -0.8989, 0.0452],
+        );
+        let y_coefficients = PerezCoefficients::new(
+            turbidity,
+            [-0.0167, -0.0950, -0.0079, -0.0441, -0.0109],
+            [-0.2608, 0.0092, 0.2102, -1.6537, 0.0529],
+        );
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance =
+            (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+        let zenith_x = get_zenith_chromaticity(turbidity, sun_theta, ZENITH_X_COEFFICIENTS);
+        let zenith_y = get_zenith_chromaticity(turbidity, sun_theta, ZENITH_Y_COEFFICIENTS);
+
+        let relative = |coefficients: &PerezCoefficients| {
+            coefficients.evaluate(theta, gamma) / coefficients.evaluate(0.0, sun_theta)
+        };
+        let luminance = (zenith_luminance * relative(&luminance_coefficients)).max(0.0);
+        let x = zenith_x * relative(&x_coefficients);
+        let y = zenith_y * relative(&y_coefficients);
+
+        let xyz = Vector3::new(x / y, 1.0, (1.0 - x - y) / y) * luminance;
+        let rgb = Matrix3::from_row_slice(&XYZ_TO_LINEAR_SRGB) * xyz;
+
+        rgb.map(|c| c.max(0.0)) * self.intensity
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        let mut sample = self.table.as_ref()?.sample(rng)?;
+        sample.radiance = self.get_radiance(&sample.direction);
+
+        Some(sample)
+    }
+
+    pub fn pdf(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        self.table
+            .as_ref()
+            .map_or(0.0, |table| table.pdf(direction))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils;
+    use more_asserts::assert_gt;
+
+    #[test]
+    fn it_matches_the_sky_and_sun_directions() {
+        let sky = Sky::new(30.0, 90.0, 3.0, 1.0, true);
+        let sun_direction = sky.get_sun_direction();
+        let sun = sky.get_sun_light().unwrap();
+
+        assert!(
+            (sun_direction.into_inner() - Vector3::new(0.75f64.sqrt(), 0.5, 0.0)).magnitude()
+                < 1e-6
+        );
+        assert!(
+            (sun.sample(&mut rand::thread_rng())
+                .direction
+                .dot(&sun_direction)
+                - 1.0)
+                .abs()
+                < 1e-4
+        );
+
+        // The sky brightens towards the sun and the sun reddens as it sets
+        let away_from_sun = Unit::new_normalize(Vector3::new(-0.75f64.sqrt(), 0.5, 0.0));
+        assert_gt!(
+            utils::luminance(sky.get_radiance(&sun_direction)),
+            utils::luminance(sky.get_radiance(&away_from_sun))
+        );
+
+        let sunset = Sky::new(5.0, 90.0, 3.0, 1.0, true).get_sun_transmittance();
+        let transmittance = sky.get_sun_transmittance();
+        assert_gt!(transmittance.x / transmittance.z, 1.0);
+        assert_gt!(sunset.x / sunset.z, transmittance.x / transmittance.z);
+
+        assert!(Sky::new(-5.0, 90.0, 3.0, 1.0, true)
+            .get_sun_light()
+            .is_none());
+        assert!(Sky::new(30.0, 90.0, 3.0, 1.0, false)
+            .get_sun_light()
+            .is_none());
+    }
+
+    #[test]
+    fn it_samples_the_tabulated_sky() {
+        let mut sky = Sky::new(30.0, 0.0, 3.0, 1.0, true);
+        sky.load();
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let sample = sky.sample(&mut rng).unwrap();
+
+            assert_gt!(sample.direction.y, 0.0);
+            assert!((sample.pdf - sky.pdf(&sample.direction)).abs() < 1e-6);
+            assert_eq!(sample.radiance, sky.get_radiance(&sample.direction));
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use super::{LightIntersection, LightSample};
+use crate::core::{Transform, Transformed};
+use crate::render::Validator;
+use crate::utils;
+use nalgebra::{Point3, Unit, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::TAU;
+
+// Sphere centered on the transformed origin, emitting outwards
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SphereLight {
+    transform: Transform,
+    color: Vector3<f64>,
+    intensity: f64,
+    radius: f64,
+}
+
+impl Default for SphereLight {
+    fn default() -> Self {
+        Self {
+            transform: Transform::default(),
+            color: Vector3::from([1.0; 3]),
+            intensity: 10.0,
+            radius: 1.0,
+        }
+    }
+}
+
+impl SphereLight {
+    pub fn new(color: Vector3<f64>, intensity: f64, radius: f64, transform: Transform) -> Self {
+        Self {
+            transform,
+            color,
+            intensity,
+            radius,
+        }
+    }
+
+    // Cosine of the half angle of the cone of directions from `point` which hit the sphere
+    fn get_cos_max_angle(&self, point: &Point3<f64>) -> Option<f64> {
+        let distance_squared = (self.get_position() - point).magnitude_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transform("transform", &self.transform);
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("intensity", self.intensity);
+        validator.check_positive("radius", self.radius);
+    }
+
+    // Points inside the sphere are not lit, so the light is sampled by the solid angle it subtends
+    pub fn sample<R: Rng>(&self, point: &Point3<f64>, rng: &mut R) -> Option<LightSample> {
+        let cos_max_angle = self.get_cos_max_angle(point)?;
+        let center_dir = Unit::new_normalize(self.get_position() - point);
+        let light_dir = utils::uniform_sample_spherical_cap(&center_dir, cos_max_angle, rng);
+
+        // Grazing samples can numerically miss the sphere, so fall back on the closest approach
+        let distance = self.intersect(point, &light_dir).map_or_else(
+            || (self.get_position() - point).dot(&light_dir),
+            |intersection| intersection.distance,
+        );
+
+        Some(LightSample {
+            direction: light_dir,
+            distance,
+            radiance: self.color * self.intensity,
+            pdf: 1.0 / (TAU * (1.0 - cos_max_angle)),
+            is_delta: false,
+        })
+    }
+
+    pub fn pdf(&self, point: &Point3<f64>, direction: &Unit<Vector3<f64>>) -> f64 {
+        match self.get_cos_max_angle(point) {
+            Some(cos_max_angle) if self.intersect(point, direction).is_some() => {
+                1.0 / (TAU * (1.0 - cos_max_angle))
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn intersect(
+        &self,
+        origin: &Point3<f64>,
+        direction: &Unit<Vector3<f64>>,
+    ) -> Option<LightIntersection> {
+        let hypot = origin - self.get_position();
+        let b = 2.0 * hypot.dot(direction);
+        let c = hypot.magnitude_squared() - self.radius * self.radius;
+
+        let (t0, _) = utils::quadratic(1.0, b, c)?;
+        if t0 <= 0.0 {
+            return None;
+        }
+
+        Some(LightIntersection {
+            distance: t0,
+            radiance: self.color * self.intensity,
+        })
+    }
+}
+
+impl Transformed for SphereLight {
+    fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+}
@@ -0,0 +1,113 @@
+use super::LightSample;
+use crate::core::{Transform, Transformed};
+use crate::render::Validator;
+use crate::utils;
+use nalgebra::{Point3, Unit, Vector3};
+use serde::{Deserialize, Serialize};
+
+// Point light at the transformed origin, shining in a cone towards the transformed `target`. The angles are
+// half angles of the cones in degrees, with light fading smoothly from the inner cone to the outer cone.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotLight {
+    transform: Transform,
+    target: Point3<f64>,
+    color: Vector3<f64>,
+    intensity: f64,
+    inner_angle: f64,
+    outer_angle: f64,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            transform: Transform::default(),
+            target: Point3::from([0.0, -1.0, 0.0]),
+            color: Vector3::from([1.0; 3]),
+            intensity: 10.0,
+            inner_angle: 30.0,
+            outer_angle: 45.0,
+        }
+    }
+}
+
+impl SpotLight {
+    pub fn new(
+        color: Vector3<f64>,
+        intensity: f64,
+        target: Point3<f64>,
+        inner_angle: f64,
+        outer_angle: f64,
+        transform: Transform,
+    ) -> Self {
+        Self {
+            transform,
+            target,
+            color,
+            intensity,
+            inner_angle,
+            outer_angle,
+        }
+    }
+
+    fn get_direction(&self) -> Unit<Vector3<f64>> {
+        Unit::new_normalize(self.transform.matrix() * self.target - self.get_position())
+    }
+
+    fn get_falloff(&self, direction: &Unit<Vector3<f64>>) -> f64 {
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+        let cos_theta = self.get_direction().dot(direction);
+
+        if cos_inner <= cos_outer {
+            return if cos_theta >= cos_outer { 1.0 } else { 0.0 };
+        }
+
+        utils::smoothstep(cos_outer, cos_inner, cos_theta)
+    }
+
+    pub fn get_irradiance(&self, direction: &Unit<Vector3<f64>>, distance: f64) -> Vector3<f64> {
+        self.intensity * self.get_falloff(direction) * self.color / distance.powi(2)
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transform("transform", &self.transform);
+        validator.check(
+            "target",
+            self.transform.matrix() * self.target != self.get_position(),
+            "must differ from the light's position",
+        );
+        validator.check_color("color", &self.color);
+        validator.check_non_negative("intensity", self.intensity);
+        validator.check_range("inner_angle", self.inner_angle, 0.0..=180.0);
+        validator.check_range("outer_angle", self.outer_angle, 0.0..=180.0);
+    }
+
+    pub fn sample(&self, point: &Point3<f64>) -> Option<LightSample> {
+        let light_dir = self.get_position() - point;
+        let distance = light_dir.magnitude();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let light_dir = Unit::new_normalize(light_dir);
+        let irradiance = self.get_irradiance(&-light_dir, distance);
+        if irradiance.max() <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: light_dir,
+            distance,
+            radiance: irradiance,
+            pdf: 1.0,
+            is_delta: true,
+        })
+    }
+}
+
+impl Transformed for SpotLight {
+    fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+}
@@ -1,9 +1,13 @@
 #![deny(clippy::all)]
 
-use clap::{Arg, Command};
-use raytrace::Scene;
-use std::fs::File;
+use clap::{Arg, ArgMatches, Command};
+use raytrace::{
+    get_frame_path, AssetCache, ExrPrecision, RaytraceError, RenderPass, Scene, SceneAnimation,
+    ToneMapping,
+};
+use std::ops::RangeInclusive;
 use std::path::Path;
+use std::process;
 use std::time::{Duration, Instant};
 
 fn main() {
@@ -21,7 +25,47 @@ fn main() {
                 .short('o')
                 .long("output")
                 .takes_value(true)
-                .help("Output rendered image to file. If omitted, image is rendered to a window"),
+                .help("Output rendered image to file. .exr, .hdr and .pfm files keep linear high dynamic range color. If omitted, image is rendered to a window"),
+        )
+        .arg(
+            Arg::new("half")
+                .long("half")
+                .help("Write half float channels when outputting to an OpenEXR file"),
+        )
+        .arg(
+            Arg::new("exposure")
+                .long("exposure")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .help("Exposure adjustment in stops, overriding the scene's exposure"),
+        )
+        .arg(
+            Arg::new("tonemapping")
+                .long("tone-mapping")
+                .takes_value(true)
+                .possible_values(["none", "reinhard", "aces", "agx"])
+                .help("Tone mapping operator, overriding the scene's tone mapping"),
+        )
+        .arg(
+            Arg::new("passes")
+                .long("passes")
+                .takes_value(true)
+                .use_value_delimiter(true)
+                .possible_values(RenderPass::ALL.map(RenderPass::get_name))
+                .help("Comma separated render passes to output alongside the beauty pass, overriding the scene's passes. OpenEXR files hold every pass as channels of one image, other formats write each pass to <name>.<pass>.<extension>"),
+        )
+        .arg(
+            Arg::new("frames")
+                .long("frames")
+                .takes_value(true)
+                .requires("output")
+                .validator(|frames| parse_frames(frames).map(|_| ()))
+                .help("Render an image sequence of the frames <first>..<last> of a keyframed scene, numbering each output file by replacing a run of # in its name with the frame or otherwise as <name>.<frame>.<extension>"),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help("Check the scene for invalid values and missing asset files without rendering it"),
         )
         .arg(
             Arg::new("noprogress")
@@ -30,23 +74,144 @@ fn main() {
         )
         .get_matches();
 
+    if let Err(err) = run(&matches) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<(), RaytraceError> {
     let scene_path = Path::new(matches.value_of("scene").unwrap());
-    let scene_file = File::open(scene_path).expect("file not found");
+    let asset_base = scene_path.parent().unwrap_or_else(|| Path::new(""));
     let output_filename = matches.value_of("output");
     let use_progress = !matches.is_present("noprogress");
 
+    let animation = SceneAnimation::from_file(scene_path)?;
+    let mut assets = AssetCache::default();
+
+    if matches.is_present("check") {
+        let frames = matches
+            .value_of("frames")
+            .map_or(0..=0, |frames| parse_frames(frames).unwrap());
+        for frame in frames {
+            let mut scene = animation.get_scene(f64::from(frame))?;
+            apply_overrides(&mut scene, matches);
+            validate(&mut scene, asset_base)?;
+        }
+        println!("Scene is valid.");
+
+        return Ok(());
+    }
+
+    if let Some(frames) = matches.value_of("frames") {
+        let frames = parse_frames(frames).unwrap();
+        let output_path = Path::new(output_filename.unwrap());
+
+        for frame in frames {
+            println!("Rendering frame {}", frame);
+            let scene = animation.get_scene(f64::from(frame))?;
+            render(
+                scene,
+                matches,
+                asset_base,
+                &mut assets,
+                Some(&get_frame_path(output_path, frame)),
+                use_progress,
+            )?;
+        }
+
+        Ok(())
+    } else {
+        let scene = animation.get_scene(0.0)?;
+        render(
+            scene,
+            matches,
+            asset_base,
+            &mut assets,
+            output_filename.map(Path::new),
+            use_progress,
+        )
+    }
+}
+
+// Frames to render written as `<first>..<last>`, or a single frame
+fn parse_frames(frames: &str) -> Result<RangeInclusive<u32>, String> {
+    let parse_frame = |frame: &str| {
+        frame
+            .trim()
+            .parse::<u32>()
+            .map_err(|err| format!("invalid frame \"{}\": {}", frame, err))
+    };
+
+    match frames.split_once("..") {
+        Some((first, last)) => {
+            let (first, last) = (parse_frame(first)?, parse_frame(last)?);
+            if first > last {
+                return Err(format!("frame range {} ends before it starts", frames));
+            }
+
+            Ok(first..=last)
+        }
+        None => parse_frame(frames).map(|frame| frame..=frame),
+    }
+}
+
+// Check the scene, then check it again along with the scenes it includes once they are found
+fn validate(scene: &mut Scene, asset_base: &Path) -> Result<(), RaytraceError> {
+    let mut diagnostics = scene.validate(asset_base);
+    if diagnostics.is_empty() {
+        scene.resolve_includes(asset_base)?;
+        diagnostics = scene.validate(asset_base);
+    }
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(RaytraceError::InvalidScene(diagnostics))
+    }
+}
+
+fn apply_overrides(scene: &mut Scene, matches: &ArgMatches) {
+    if matches.is_present("half") {
+        scene.render_options.exr_precision = ExrPrecision::Half;
+    }
+    if matches.is_present("exposure") {
+        scene.render_options.exposure = matches
+            .value_of_t("exposure")
+            .unwrap_or_else(|err| err.exit());
+    }
+    if matches.is_present("tonemapping") {
+        scene.render_options.tone_mapping = matches
+            .value_of_t::<ToneMapping>("tonemapping")
+            .unwrap_or_else(|err| err.exit());
+    }
+    if matches.is_present("passes") {
+        scene.render_options.passes = matches
+            .values_of_t::<RenderPass>("passes")
+            .unwrap_or_else(|err| err.exit());
+    }
+}
+
+fn render(
+    mut scene: Scene,
+    matches: &ArgMatches,
+    asset_base: &Path,
+    assets: &mut AssetCache,
+    output_path: Option<&Path>,
+    use_progress: bool,
+) -> Result<(), RaytraceError> {
     let mut total_duration = Duration::ZERO;
 
-    let mut scene: Scene = serde_json::from_reader(scene_file).expect("failed to parse scene");
+    apply_overrides(&mut scene, matches);
+    validate(&mut scene, asset_base)?;
 
     let now = Instant::now();
-    scene.load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")));
+    scene.load_assets_from_cache(asset_base, assets)?;
     let duration = now.elapsed();
     total_duration += duration;
     println!("Took {:?} to load assets.", duration);
 
     let now = Instant::now();
-    let scene = scene.build_raytracing_scene();
+    let scene = scene.build_raytracing_scene()?;
     let duration = now.elapsed();
     total_duration += duration;
     println!(
@@ -55,8 +220,8 @@ fn main() {
         scene.get_num_objects()
     );
 
-    if let Some(filename) = output_filename {
-        let (image, cast_timings, _) = scene.raytrace_to_image(use_progress);
+    if let Some(output_path) = output_path {
+        let (passes, cast_timings, _) = scene.raytrace_passes(use_progress)?;
         total_duration += cast_timings.ray_casting_duration;
         println!(
             "Took {:?} to render the scene.",
@@ -70,9 +235,15 @@ fn main() {
             );
         }
 
-        image.save(filename).expect("unable to write image");
-        println!("Output written to {} in {:.3?}", filename, total_duration);
+        raytrace::save_passes(&passes, output_path, &scene.render_options)?;
+        println!(
+            "Output written to {} in {:.3?}",
+            output_path.display(),
+            total_duration
+        );
     } else {
-        scene.raytrace_to_buffer(use_progress);
+        scene.raytrace_to_buffer(use_progress)?;
     }
+
+    Ok(())
 }
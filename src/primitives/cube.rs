@@ -1,18 +1,21 @@
 use super::{HasMaterial, Object3D, Primitive, RaytracingObject};
 use crate::core::{
-    Axis, AxisDirection, BoundingVolume, Material, MaterialSide, ObjectWithBounds, Transform,
-    Transformed,
+    AnimatedTransform, Axis, AxisDirection, BoundingVolume, Material, MaterialRef, MaterialSide,
+    ObjectWithBounds, Transform, Transformed,
 };
 use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray, RayType};
+use crate::render::Validator;
 use nalgebra::{Point3, Unit, Vector2, Vector3};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct Cube {
     size: f64,
     transform: Transform,
-    pub material: Material,
+    end_transform: Option<Transform>,
+    pub material: MaterialRef,
 
     pub children: Option<Vec<Object3D>>,
 }
@@ -22,7 +25,8 @@ impl Default for Cube {
         Self {
             size: 1.0,
             transform: Transform::default(),
-            material: Material::default(),
+            end_transform: None,
+            material: MaterialRef::default(),
 
             children: None,
         }
@@ -30,23 +34,28 @@ impl Default for Cube {
 }
 
 impl Cube {
-    pub fn new(size: f64, transform: Transform, material: Material) -> Self {
+    pub fn new(size: f64, transform: Transform, material: impl Into<MaterialRef>) -> Self {
         Self {
             size,
             transform,
-            material,
+            material: material.into(),
             ..Cube::default()
         }
     }
 
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_positive("size", self.size);
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         if let Some(children) = self.children.as_mut() {
             children.push(object);
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
 
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
@@ -61,7 +70,7 @@ impl Cube {
         objects.push(Box::new(RaytracingCube::new(
             self.size,
             transform,
-            self.material,
+            self.material.into_material(),
         )));
 
         objects
@@ -71,12 +80,12 @@ impl Cube {
 #[derive(Debug)]
 pub struct RaytracingCube {
     size: f64,
-    world_transform: Transform,
+    world_transform: AnimatedTransform,
     material: Material,
 }
 
 impl RaytracingCube {
-    pub fn new(size: f64, world_transform: Transform, material: Material) -> Self {
+    pub fn new(size: f64, world_transform: AnimatedTransform, material: Material) -> Self {
         Self {
             size,
             world_transform,
@@ -93,7 +102,11 @@ impl HasMaterial for RaytracingCube {
 
 impl Transformed for RaytracingCube {
     fn get_transform(&self) -> &Transform {
-        &self.world_transform
+        self.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.world_transform.at(time)
     }
 }
 
@@ -176,10 +189,10 @@ impl Intersectable for RaytracingCube {
 impl Primitive for RaytracingCube {
     fn into_bounded_object(self: Box<Self>) -> ObjectWithBounds {
         let half = self.size / 2.0;
-        let bounding_volume = BoundingVolume::from_bounds_and_transform(
+        let bounding_volume = BoundingVolume::from_bounds_and_motion(
             Point3::from([-half; 3]),
             Point3::from([half; 3]),
-            self.get_transform(),
+            &self.world_transform,
         );
 
         ObjectWithBounds::bounded(self, bounding_volume)
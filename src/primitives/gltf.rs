@@ -0,0 +1,537 @@
+use super::{Group, MeshGeometry, Object3D, RaytracingObject, TriangleMesh};
+use crate::core::{
+    AnimatedTransform, Material, MaterialRef, MaterialSide, PhysicalMaterial, Texture, Transform,
+};
+use crate::lights::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::render::{AssetCache, Camera, Projection, Validator};
+use crate::RaytraceError;
+use gltf::image::Format;
+use gltf::khr_lights_punctual::Kind;
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+use image::RgbImage;
+use nalgebra::{Affine3, Matrix4, Point3, Unit, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+// Document of a glTF file along with its buffers and decoded images
+#[derive(Debug)]
+pub(crate) struct GltfFile {
+    document: gltf::Document,
+    buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
+}
+
+// Projection of a glTF camera, in the units of the file
+#[derive(Copy, Clone, Debug)]
+enum GltfProjection {
+    // Vertical fov in radians
+    Perspective { yfov: f64 },
+    // Half of the vertical extent of the view
+    Orthographic { ymag: f64 },
+}
+
+// Camera of a glTF file, looking along the negative z axis of its world transform
+#[derive(Clone, Debug)]
+pub(crate) struct GltfCamera {
+    transform: Affine3<f64>,
+    projection: GltfProjection,
+}
+
+impl GltfCamera {
+    // Scene camera with the position, orientation and projection of the glTF camera, keeping the lens and
+    // shutter settings of `camera`
+    pub fn into_camera(self, camera: &Camera, aspect_ratio: f64) -> Camera {
+        let position = self.transform * Point3::origin();
+        let target = self.transform * Point3::new(0.0, 0.0, -1.0);
+        let up = Unit::new_normalize(self.transform * Vector3::y());
+        let (fov, projection) = match self.projection {
+            GltfProjection::Perspective { yfov } => {
+                // The camera's fov spans the longer side of the image
+                let fov = if aspect_ratio > 1.0 {
+                    2.0 * ((yfov / 2.0).tan() * aspect_ratio).atan()
+                } else {
+                    yfov
+                };
+                (fov.to_degrees(), Projection::Perspective)
+            }
+            GltfProjection::Orthographic { ymag } => {
+                (camera.fov, Projection::Orthographic { height: 2.0 * ymag })
+            }
+        };
+
+        Camera {
+            fov,
+            projection,
+            position,
+            target,
+            up,
+            end_position: None,
+            end_target: None,
+            ..*camera
+        }
+    }
+}
+
+fn to_matrix(columns: [[f32; 4]; 4]) -> Affine3<f64> {
+    Affine3::from_matrix_unchecked(Matrix4::from_fn(|row, column| {
+        f64::from(columns[column][row])
+    }))
+}
+
+fn to_vector(values: [f32; 3]) -> Vector3<f64> {
+    Vector3::from(values.map(f64::from))
+}
+
+// Texture from a decoded glTF image, keeping the most significant byte of wider channels
+fn to_rgb_image(image: &gltf::image::Data) -> RgbImage {
+    let (channels, channel_size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let get_channel = |pixel: &[u8], channel: usize| {
+        let channel = pixel
+            .chunks_exact(channel_size)
+            .nth(channel.min(channels - 1));
+        match (channel, channel_size) {
+            (Some(value), 1) => value[0],
+            // Little endian 16 bit channels
+            (Some(value), 2) => value[1],
+            (Some(value), _) => {
+                let value = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                (value.clamp(0.0, 1.0) * f32::from(u8::MAX)).round() as u8
+            }
+            (None, _) => 0,
+        }
+    };
+    // Gray images use their single channel for red, green and blue
+    let pixels = image
+        .pixels
+        .chunks_exact(channels * channel_size)
+        .flat_map(|pixel| {
+            if channels < 3 {
+                [get_channel(pixel, 0); 3]
+            } else {
+                [0, 1, 2].map(|channel| get_channel(pixel, channel))
+            }
+        })
+        .collect();
+
+    RgbImage::from_raw(image.width, image.height, pixels).expect("image size matches its pixels")
+}
+
+// Physical material from a glTF metallic-roughness material, with textures named by `get_texture_path`
+fn from_gltf_material(
+    material: &gltf::Material,
+    get_texture_path: impl Fn(&gltf::texture::Info) -> String,
+) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let [red, green, blue, alpha] = pbr.base_color_factor().map(f64::from);
+    let defaults = PhysicalMaterial::default();
+    let transmission = material.transmission().map_or(0.0, |transmission| {
+        f64::from(transmission.transmission_factor())
+    });
+    let opacity = if material.alpha_mode() == AlphaMode::Blend {
+        alpha
+    } else {
+        1.0
+    };
+
+    Material::Physical(PhysicalMaterial {
+        side: if material.double_sided() {
+            MaterialSide::Both
+        } else {
+            MaterialSide::Front
+        },
+        color: Vector3::new(red, green, blue),
+        opacity: opacity * (1.0 - transmission),
+        emissive: to_vector(material.emissive_factor())
+            * f64::from(material.emissive_strength().unwrap_or(1.0)),
+        roughness: f64::from(pbr.roughness_factor()),
+        metalness: f64::from(pbr.metallic_factor()),
+        refractive_index: material
+            .ior()
+            .filter(|_| transmission > 0.0 || opacity < 1.0)
+            .map_or(defaults.refractive_index, f64::from),
+        texture_path: pbr.base_color_texture().map(|info| get_texture_path(&info)),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|info| get_texture_path(&info)),
+        ..defaults
+    })
+}
+
+fn from_gltf_light(light: &gltf::khr_lights_punctual::Light, transform: Affine3<f64>) -> Light {
+    let color = to_vector(light.color());
+    let intensity = f64::from(light.intensity());
+
+    match light.kind() {
+        Kind::Directional => Light::Directional(Box::new(DirectionalLight::new(
+            Unit::new_normalize(transform * -Vector3::z()),
+            color,
+            intensity,
+            0.0,
+        ))),
+        Kind::Point => Light::Point(Box::new(PointLight::new(
+            color,
+            intensity,
+            Transform::new(transform),
+        ))),
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => Light::Spot(Box::new(SpotLight::new(
+            color,
+            intensity,
+            Point3::new(0.0, 0.0, -1.0),
+            f64::from(inner_cone_angle).to_degrees(),
+            f64::from(outer_cone_angle).to_degrees(),
+            Transform::new(transform),
+        ))),
+    }
+}
+
+// Node hierarchy of the default scene of a glTF or GLB file, with each node as a group of the triangles of its
+// mesh
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gltf {
+    file: String,
+    #[serde(default)]
+    transform: Transform,
+    #[serde(default)]
+    end_transform: Option<Transform>,
+    // Material of primitives without a material in the file
+    #[serde(default)]
+    pub material: MaterialRef,
+    // Whether to render the scene through the file's first camera, keeping the lens and shutter settings of the
+    // scene's camera
+    #[serde(default)]
+    import_camera: bool,
+    // Whether to add the file's KHR_lights_punctual lights to the scene
+    #[serde(default)]
+    import_lights: bool,
+
+    #[serde(default)]
+    pub children: Option<Vec<Object3D>>,
+
+    // Camera and lights of the file once its assets are loaded, taken by the scene for glTF objects at its top
+    // level
+    #[serde(skip)]
+    camera: Option<GltfCamera>,
+    #[serde(skip)]
+    lights: Vec<Light>,
+}
+
+impl Gltf {
+    pub fn new(file: String, transform: Transform, material: impl Into<MaterialRef>) -> Self {
+        Self {
+            file,
+            transform,
+            end_transform: None,
+            material: material.into(),
+            import_camera: false,
+            import_lights: false,
+            children: None,
+            camera: None,
+            lights: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_camera(mut self) -> Self {
+        self.import_camera = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_lights(mut self) -> Self {
+        self.import_lights = true;
+        self
+    }
+
+    pub(crate) fn take_camera(&mut self) -> Option<GltfCamera> {
+        self.camera.take()
+    }
+
+    pub(crate) fn take_lights(&mut self) -> Vec<Light> {
+        std::mem::take(&mut self.lights)
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_file("file", &self.file);
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
+    pub fn add_child(&mut self, object: Object3D) {
+        if let Some(children) = self.children.as_mut() {
+            children.push(object);
+        }
+    }
+
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
+
+        let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
+
+        if let Some(children) = self.children {
+            for child in children {
+                objects.extend(child.flatten_to_world(&transform));
+            }
+        }
+
+        objects
+    }
+
+    pub fn load_assets(
+        &mut self,
+        asset_base: &Path,
+        assets: &mut AssetCache,
+    ) -> Result<(), RaytraceError> {
+        let path = asset_base.join(&self.file);
+        let gltf_file = assets
+            .get_or_load_gltf_file(&path, || {
+                let (document, buffers, images) = gltf::import(&path)?;
+
+                Ok::<_, gltf::Error>(GltfFile {
+                    document,
+                    buffers,
+                    images,
+                })
+            })
+            .map_err(|source| RaytraceError::Gltf {
+                path: path.clone(),
+                source,
+            })?;
+
+        // Images are decoded with the file, so they are cached as textures named after the file and the image
+        let get_texture_path = |info: &gltf::texture::Info| {
+            format!("{}#{}", self.file, info.texture().source().index())
+        };
+        let textures = assets.get_textures_mut();
+        for (index, image) in gltf_file.images.iter().enumerate() {
+            let texture_path = format!("{}#{index}", self.file);
+            textures
+                .entry(texture_path.clone())
+                .or_insert_with(|| Texture::from_image(&texture_path, to_rgb_image(image)));
+        }
+
+        let materials: HashMap<usize, MaterialRef> = gltf_file
+            .document
+            .materials()
+            .filter_map(|material| {
+                let index = material.index()?;
+                Some((
+                    index,
+                    from_gltf_material(&material, get_texture_path).into(),
+                ))
+            })
+            .collect();
+
+        let mut loader = NodeLoader {
+            file: &gltf_file,
+            materials: &materials,
+            fallback: &self.material,
+            geometries: HashMap::new(),
+            camera: None,
+            lights: Vec::new(),
+        };
+        let scene = gltf_file
+            .document
+            .default_scene()
+            .or_else(|| gltf_file.document.scenes().next());
+        let children = scene.map_or_else(Vec::new, |scene| {
+            scene
+                .nodes()
+                .map(|node| loader.load_node(&node, self.transform.matrix()))
+                .collect()
+        });
+
+        if self.import_camera {
+            self.camera = loader.camera;
+        }
+        if self.import_lights {
+            self.lights = loader.lights;
+        }
+        self.children = Some(children);
+
+        Ok(())
+    }
+}
+
+// Walks the node hierarchy of a glTF file, collecting its first camera and its lights on the way
+struct NodeLoader<'a> {
+    file: &'a GltfFile,
+    materials: &'a HashMap<usize, MaterialRef>,
+    fallback: &'a MaterialRef,
+    // Buffers of the primitives loaded so far, keyed by the indices of their mesh and primitive
+    geometries: HashMap<(usize, usize), Arc<MeshGeometry>>,
+    camera: Option<GltfCamera>,
+    lights: Vec<Light>,
+}
+
+impl NodeLoader<'_> {
+    fn load_node(&mut self, node: &gltf::Node, parent_transform: Affine3<f64>) -> Object3D {
+        let transform = to_matrix(node.transform().matrix());
+        let world_transform = parent_transform * transform;
+        let mut group = Group::new(Transform::new(transform));
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(mesh) = self.load_primitive(mesh.index(), &primitive) {
+                    group.add_child(mesh.into());
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            let projection = match camera.projection() {
+                gltf::camera::Projection::Perspective(perspective) => GltfProjection::Perspective {
+                    yfov: f64::from(perspective.yfov()),
+                },
+                gltf::camera::Projection::Orthographic(orthographic) => {
+                    GltfProjection::Orthographic {
+                        ymag: f64::from(orthographic.ymag()),
+                    }
+                }
+            };
+            self.camera.get_or_insert(GltfCamera {
+                transform: world_transform,
+                projection,
+            });
+        }
+
+        if let Some(light) = node.light() {
+            self.lights.push(from_gltf_light(&light, world_transform));
+        }
+
+        for child in node.children() {
+            group.add_child(self.load_node(&child, world_transform));
+        }
+
+        group.into()
+    }
+
+    fn load_primitive(
+        &mut self,
+        mesh_index: usize,
+        primitive: &gltf::Primitive,
+    ) -> Option<TriangleMesh> {
+        if primitive.mode() != Mode::Triangles {
+            return None;
+        }
+
+        let material = primitive
+            .material()
+            .index()
+            .and_then(|index| self.materials.get(&index))
+            .unwrap_or(self.fallback)
+            .clone();
+
+        // Nodes instancing the same mesh share the buffers of its primitives
+        let key = (mesh_index, primitive.index());
+        if let Some(geometry) = self.geometries.get(&key) {
+            return Some(TriangleMesh::new(Arc::clone(geometry), material));
+        }
+
+        let reader = primitive.reader(|buffer| Some(&*self.file.buffers[buffer.index()]));
+        let positions: Vec<Point3<f32>> = reader.read_positions()?.map(Point3::from).collect();
+        let normals = reader.read_normals().map_or_else(Vec::new, |normals| {
+            normals
+                .map(|normal| Vector3::from(normal).normalize())
+                .collect()
+        });
+        // glTF texture coordinates start at the top of the image
+        let texcoords = reader
+            .read_tex_coords(0)
+            .map_or_else(Vec::new, |texcoords| {
+                texcoords
+                    .into_f32()
+                    .map(|[u, v]| Vector2::new(u, 1.0 - v))
+                    .collect()
+            });
+        let indices: Vec<u32> = reader.read_indices().map_or_else(
+            || (0..positions.len() as u32).collect(),
+            |indices| indices.into_u32().collect(),
+        );
+        let geometry = MeshGeometry {
+            positions,
+            normals,
+            texcoords,
+            colors: Vec::new(),
+            faces: indices
+                .chunks_exact(3)
+                .map(|face| [face[0], face[1], face[2]])
+                .collect(),
+        };
+
+        let geometry = Arc::new(geometry.validated());
+        self.geometries.insert(key, Arc::clone(&geometry));
+        Some(TriangleMesh::new(geometry, material))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::Transformed;
+
+    #[test]
+    fn it_loads_gltf_node_hierarchies() {
+        let mut assets = AssetCache::default();
+        let mut gltf = Gltf::new(
+            "models/pyramid.gltf".to_string(),
+            Transform::default().translate(Vector3::new(0.0, 0.0, -2.0)),
+            MaterialRef::default(),
+        )
+        .with_camera()
+        .with_lights();
+        gltf.load_assets(Path::new("scenes"), &mut assets).unwrap();
+
+        let children = gltf.children.as_ref().unwrap();
+        assert_eq!(children.len(), 4);
+        let Object3D::Group(base) = &children[0] else {
+            panic!("expected a group for the base node");
+        };
+        let Object3D::Group(pyramid) = &base.children[0] else {
+            panic!("expected a group for the pyramid node");
+        };
+        let [Object3D::TriangleMesh(mesh)] = pyramid.children.as_slice() else {
+            panic!("expected a triangle mesh for the pyramid's primitive");
+        };
+        assert_eq!(mesh.len(), 6);
+
+        let Some(Material::Physical(material)) = mesh.material.get() else {
+            panic!("expected the pyramid's physical material");
+        };
+        assert!((material.roughness - 0.6).abs() < 1e-6);
+        let texture_path = material.texture_path.as_ref().unwrap();
+        assert_eq!(texture_path, "models/pyramid.gltf#0");
+        assert!(assets.get_textures().contains_key(texture_path));
+
+        let camera = gltf
+            .take_camera()
+            .unwrap()
+            .into_camera(&Camera::default(), 1.0);
+        assert!((camera.position - Point3::new(0.0, 0.5, 3.0)).magnitude() < 1e-6);
+        assert!((camera.target - Point3::new(0.0, 0.5, 2.0)).magnitude() < 1e-6);
+        assert!((camera.fov - 0.7_f64.to_degrees()).abs() < 1e-6);
+
+        let lights = gltf.take_lights();
+        assert_eq!(lights.len(), 2);
+        let Light::Point(lamp) = &lights[0] else {
+            panic!("expected a point light for the lamp node");
+        };
+        assert_eq!(lamp.get_position(), Point3::new(2.0, 3.0, 0.0));
+    }
+}
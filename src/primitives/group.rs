@@ -1,12 +1,15 @@
 use super::{Object3D, RaytracingObject};
-use crate::core::Transform;
-use serde::Deserialize;
+use crate::core::{AnimatedTransform, Transform};
+use crate::render::Validator;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Group {
     #[serde(default)]
     transform: Transform,
+    #[serde(default)]
+    end_transform: Option<Transform>,
 
     pub children: Vec<Object3D>,
 }
@@ -15,16 +18,21 @@ impl Group {
     pub fn new(transform: Transform) -> Self {
         Self {
             transform,
+            end_transform: None,
             children: Vec::new(),
         }
     }
 
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         self.children.push(object);
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
         for child in self.children {
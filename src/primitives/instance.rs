@@ -0,0 +1,219 @@
+use super::{HasMaterial, Object3D, Primitive, RaytracingObject};
+use crate::core::{
+    AnimatedTransform, BoundingVolume, KdTreeAccelerator, Material, MaterialRef, ObjectWithBounds,
+    Transform, Transformed,
+};
+use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray};
+use crate::render::Validator;
+use crate::RaytraceError;
+use nalgebra::{Point3, Unit, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+// Objects of a prototype flattened once into the prototype's space, with their own acceleration structure which
+// every instance of the prototype shares
+#[derive(Debug)]
+pub struct Prototype {
+    objects: KdTreeAccelerator,
+    // Material reported by instances which keep the materials of the prototype's objects, taken from its first
+    // object
+    material: Material,
+}
+
+impl Prototype {
+    pub fn new(objects: Vec<Box<dyn RaytracingObject>>) -> Self {
+        let material = objects
+            .first()
+            .map_or_else(Material::default, |object| object.get_material().clone());
+
+        Self {
+            objects: KdTreeAccelerator::new(objects),
+            material,
+        }
+    }
+
+    pub fn get_num_objects(&self) -> usize {
+        self.objects.get_num_objects()
+    }
+}
+
+// Copy of a named prototype of the scene, placed with its own transform and optionally with a material replacing
+// the materials of the prototype's objects
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Instance {
+    prototype: String,
+    #[serde(default)]
+    transform: Transform,
+    #[serde(default)]
+    end_transform: Option<Transform>,
+    #[serde(default)]
+    pub material: Option<MaterialRef>,
+
+    #[serde(default)]
+    pub children: Option<Vec<Object3D>>,
+
+    // Prototype once the scene's prototypes are built
+    #[serde(skip)]
+    resolved_prototype: Option<Arc<Prototype>>,
+}
+
+impl Instance {
+    pub fn new(prototype: &str, transform: Transform) -> Self {
+        Self {
+            prototype: prototype.to_string(),
+            transform,
+            end_transform: None,
+            material: None,
+            children: None,
+            resolved_prototype: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_material(mut self, material: impl Into<MaterialRef>) -> Self {
+        self.material = Some(material.into());
+        self
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        if !validator.has_prototype(&self.prototype) {
+            let message = format!("prototype \"{}\" is not defined", self.prototype);
+            validator.field("prototype", |validator| validator.report(message));
+        }
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
+    pub fn add_child(&mut self, object: Object3D) {
+        if let Some(children) = self.children.as_mut() {
+            children.push(object);
+        }
+    }
+
+    pub fn resolve_prototype(
+        &mut self,
+        prototypes: &BTreeMap<String, Arc<Prototype>>,
+    ) -> Result<(), RaytraceError> {
+        let prototype = prototypes
+            .get(&self.prototype)
+            .ok_or_else(|| RaytraceError::UnknownPrototype(self.prototype.clone()))?;
+        self.resolved_prototype = Some(Arc::clone(prototype));
+
+        Ok(())
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the prototype was not resolved
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
+
+        let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
+
+        if let Some(children) = self.children {
+            for child in children {
+                let child_objects: Vec<Box<dyn RaytracingObject>> =
+                    child.flatten_to_world(&transform);
+                objects.extend(child_objects);
+            }
+        }
+
+        let prototype = self
+            .resolved_prototype
+            .unwrap_or_else(|| panic!("prototype \"{}\" is not resolved", self.prototype));
+        objects.push(Box::new(RaytracingInstance {
+            prototype,
+            world_transform: transform,
+            material: self.material.map(MaterialRef::into_material),
+        }));
+
+        objects
+    }
+}
+
+// Instance placed in the world, which moves rays into the space of its prototype to intersect the prototype's
+// objects
+pub struct RaytracingInstance {
+    prototype: Arc<Prototype>,
+    world_transform: AnimatedTransform,
+    material: Option<Material>,
+}
+
+impl fmt::Debug for RaytracingInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RaytracingInstance {{ objects: {}, world_transform: {:?}, material: {:?} }}",
+            self.prototype.get_num_objects(),
+            self.world_transform,
+            self.material
+        )
+    }
+}
+
+impl RaytracingInstance {
+    pub fn get_material_override(&self) -> Option<&Material> {
+        self.material.as_ref()
+    }
+}
+
+impl HasMaterial for RaytracingInstance {
+    fn get_material(&self) -> &Material {
+        self.material.as_ref().unwrap_or(&self.prototype.material)
+    }
+}
+
+impl Transformed for RaytracingInstance {
+    fn get_transform(&self) -> &Transform {
+        self.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.world_transform.at(time)
+    }
+}
+
+impl Intersectable for RaytracingInstance {
+    fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection<'_>> {
+        let mut intersection = self.prototype.objects.raycast_within(ray, max_distance)?;
+        intersection.instance = Some(self);
+
+        Some(intersection)
+    }
+}
+
+impl Primitive for RaytracingInstance {
+    fn into_bounded_object(self: Box<Self>) -> ObjectWithBounds {
+        match self.prototype.objects.get_bounding_volume() {
+            Some(bounding_volume) => {
+                let (min, max) = bounding_volume.get_bounds();
+                let bounding_volume =
+                    BoundingVolume::from_bounds_and_motion(min, max, &self.world_transform);
+
+                ObjectWithBounds::bounded(self, bounding_volume)
+            }
+            None => ObjectWithBounds::unbounded(self),
+        }
+    }
+
+    // Intersections report the object hit in the prototype, whose surface is computed instead
+    fn surface_normal(
+        &self,
+        _object_hit_point: &Point3<f64>,
+        _intermediate: IntermediateData,
+    ) -> Unit<Vector3<f64>> {
+        unreachable!("instances are never reported as the hit object")
+    }
+
+    fn uv(
+        &self,
+        _object_hit_point: &Point3<f64>,
+        _object_normal: &Unit<Vector3<f64>>,
+        _intermediate: IntermediateData,
+    ) -> Vector2<f64> {
+        unreachable!("instances are never reported as the hit object")
+    }
+}
@@ -1,42 +1,240 @@
-use super::{Object3D, RaytracingObject, Triangle};
-use crate::core::{Material, Transform};
-use nalgebra::{Point3, Unit, Vector2, Vector3};
+use super::mesh_formats::{load_ply, load_stl};
+use super::{MeshGeometry, Object3D, RaytracingObject, TriangleMesh};
+use crate::core::{
+    AnimatedTransform, Material, MaterialRef, MaterialSide, PhongMaterial, PhysicalMaterial,
+    Transform,
+};
+use crate::render::{AssetCache, Validator};
+use crate::RaytraceError;
+use nalgebra::{Point3, Vector2, Vector3};
 use num_traits::identities::Zero;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use tobj::{load_obj, LoadOptions};
+use std::sync::Arc;
+use tobj::{load_obj, LoadError, LoadOptions, Model};
 
-#[derive(Debug, Deserialize)]
+// Model of a mesh file along with the index of its material in the file's MTL library
+#[derive(Debug)]
+struct MeshPart {
+    geometry: Arc<MeshGeometry>,
+    material_id: Option<usize>,
+}
+
+impl From<Model> for MeshPart {
+    fn from(model: Model) -> Self {
+        let mesh = model.mesh;
+        let to_vectors = |values: &[f32]| -> Vec<Vector3<f32>> {
+            values
+                .chunks_exact(3)
+                .map(|value| Vector3::new(value[0], value[1], value[2]))
+                .collect()
+        };
+
+        let geometry = MeshGeometry {
+            positions: to_vectors(&mesh.positions)
+                .into_iter()
+                .map(Point3::from)
+                .collect(),
+            normals: to_vectors(&mesh.normals)
+                .into_iter()
+                .map(|normal| normal.normalize())
+                .collect(),
+            texcoords: mesh
+                .texcoords
+                .chunks_exact(2)
+                .map(|texcoords| Vector2::new(texcoords[0], texcoords[1]))
+                .collect(),
+            colors: to_vectors(&mesh.vertex_color),
+            faces: mesh
+                .indices
+                .chunks_exact(3)
+                .map(|face| [face[0], face[1], face[2]])
+                .collect(),
+        };
+
+        Self {
+            geometry: Arc::new(geometry.validated()),
+            material_id: mesh.material_id,
+        }
+    }
+}
+
+// Models of a mesh file along with the materials of its MTL library, which only OBJ files have
+#[derive(Debug)]
+pub(crate) struct MeshFile {
+    parts: Vec<MeshPart>,
+    materials: Result<Vec<tobj::Material>, LoadError>,
+}
+
+impl MeshFile {
+    // Load an OBJ, PLY or STL file, detecting the format from the file's extension
+    fn load(path: &Path) -> Result<Self, RaytraceError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let load_model = match extension.as_deref() {
+            Some("ply") => load_ply,
+            Some("stl") => load_stl,
+            _ => {
+                let (models, materials) = load_obj(
+                    path,
+                    &LoadOptions {
+                        triangulate: true,
+                        single_index: true,
+                        ignore_lines: true,
+                        ignore_points: true,
+                    },
+                )
+                .map_err(|source| RaytraceError::Mesh {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+
+                return Ok(Self {
+                    parts: models.into_iter().map(MeshPart::from).collect(),
+                    materials,
+                });
+            }
+        };
+        let model = load_model(path).map_err(|source| RaytraceError::MeshData {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Ok(Self {
+            parts: vec![model.into()],
+            materials: Ok(Vec::new()),
+        })
+    }
+}
+
+// Role of a mesh's own material relative to the materials of the OBJ file's MTL library
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaterialMode {
+    // Use the mesh's material for every face, ignoring the MTL library
+    #[default]
+    Override,
+    // Use the MTL material of each model, and the mesh's material for models without one
+    Fallback,
+}
+
+fn parse_mtl_values(value: &str) -> Option<Vec<f64>> {
+    value
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect()
+}
+
+// Material of a model from its MTL definition, as a physical material if it uses the PBR extensions, is
+// transparent or refracts light
+fn from_mtl(material: &tobj::Material, texture_base: &Path, side: MaterialSide) -> Material {
+    let get_value = |key: &str| {
+        material
+            .unknown_param
+            .get(key)
+            .and_then(|value| parse_mtl_values(value))
+    };
+    let scalar = |key: &str| get_value(key).and_then(|values| values.first().copied());
+
+    let color = Vector3::from(material.diffuse.map(f64::from));
+    let emissive = get_value("Ke")
+        .filter(|values| values.len() == 3)
+        .map_or_else(Vector3::zero, Vector3::from_vec);
+    let texture_path = (!material.diffuse_texture.is_empty()).then(|| {
+        texture_base
+            .join(&material.diffuse_texture)
+            .to_string_lossy()
+            .into_owned()
+    });
+    let roughness = scalar("Pr");
+    let metalness = scalar("Pm");
+    let opacity = f64::from(material.dissolve);
+    let refractive_index = f64::from(material.optical_density);
+
+    if roughness.is_some()
+        || metalness.is_some()
+        || opacity < 1.0
+        || (refractive_index - 1.0).abs() > f64::EPSILON
+    {
+        let defaults = PhysicalMaterial::default();
+        Material::Physical(PhysicalMaterial {
+            side,
+            color,
+            opacity,
+            emissive,
+            roughness: roughness.unwrap_or(defaults.roughness),
+            metalness: metalness.unwrap_or(defaults.metalness),
+            refractive_index,
+            texture_path,
+            ..defaults
+        })
+    } else {
+        let defaults = PhongMaterial::default();
+        Material::Phong(PhongMaterial {
+            side,
+            color,
+            emissive,
+            specular: Vector3::from(material.specular.map(f64::from)),
+            shininess: if material.shininess > 0.0 {
+                f64::from(material.shininess)
+            } else {
+                defaults.shininess
+            },
+            texture_path,
+            ..defaults
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Mesh {
     file: String,
     #[serde(default)]
     transform: Transform,
     #[serde(default)]
-    pub material: Material,
+    end_transform: Option<Transform>,
+    #[serde(default)]
+    pub material: MaterialRef,
+    #[serde(default)]
+    material_mode: MaterialMode,
 
     #[serde(default)]
     pub children: Option<Vec<Object3D>>,
 }
 
 impl Mesh {
-    pub fn new(file: String, transform: Transform, material: Material) -> Self {
+    pub fn new(file: String, transform: Transform, material: impl Into<MaterialRef>) -> Self {
         Self {
             file,
             transform,
-            material,
+            end_transform: None,
+            material: material.into(),
+            material_mode: MaterialMode::default(),
             children: None,
         }
     }
 
+    #[must_use]
+    pub fn with_material_mode(mut self, material_mode: MaterialMode) -> Self {
+        self.material_mode = material_mode;
+        self
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_file("file", &self.file);
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         if let Some(children) = self.children.as_mut() {
             children.push(object);
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
 
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
@@ -51,102 +249,163 @@ impl Mesh {
         objects
     }
 
-    /// # Panics
-    ///
-    /// Will panic if object asset cannot be loaded
-    pub fn load_assets(&mut self, asset_base: &Path) {
-        let (models, _) = load_obj(
-            &asset_base.join(&self.file),
-            &LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ignore_lines: true,
-                ignore_points: true,
-            },
-        )
-        .unwrap_or_else(|err| {
-            panic!(
-                "failed to load object at path \"{}\": {}",
-                &asset_base.join(&self.file).display(),
-                err
-            )
-        });
+    pub fn load_assets(
+        &mut self,
+        asset_base: &Path,
+        assets: &mut AssetCache,
+    ) -> Result<(), RaytraceError> {
+        let path = asset_base.join(&self.file);
+        let mesh_file = assets.get_or_load_mesh_file(&path, || MeshFile::load(&path))?;
 
-        let mut children: Vec<Object3D> = Vec::new();
-        for model in &models {
-            let mesh = &model.mesh;
+        let file_materials: Vec<MaterialRef> = match self.material_mode {
+            MaterialMode::Override => Vec::new(),
+            MaterialMode::Fallback => {
+                let materials =
+                    mesh_file
+                        .materials
+                        .as_ref()
+                        .map_err(|&source| RaytraceError::Mesh {
+                            path: path.clone(),
+                            source,
+                        })?;
+                // Texture maps are relative to the OBJ file
+                let texture_base = Path::new(&self.file).parent().unwrap_or(Path::new(""));
+                let side = self
+                    .material
+                    .get()
+                    .map_or_else(MaterialSide::default, Material::side);
 
-            let positions: Vec<Point3<f64>> = mesh
-                .positions
-                .chunks_exact(3)
-                .map(|position| {
-                    Point3::new(
-                        f64::from(position[0]),
-                        f64::from(position[1]),
-                        f64::from(position[2]),
-                    )
-                })
-                .collect();
-            let normals: Vec<Unit<Vector3<f64>>> = mesh
-                .normals
-                .chunks_exact(3)
-                .map(|normal| {
-                    Unit::new_normalize(Vector3::new(
-                        f64::from(normal[0]),
-                        f64::from(normal[1]),
-                        f64::from(normal[2]),
-                    ))
-                })
-                .collect();
-            let texcoords: Vec<Vector2<f64>> = mesh
-                .texcoords
-                .chunks_exact(2)
-                .map(|texcoords| Vector2::new(f64::from(texcoords[0]), f64::from(texcoords[1])))
-                .collect();
-
-            for face_indices in mesh.indices.chunks_exact(3) {
-                let (idx0, idx1, idx2) = (
-                    face_indices[0] as usize,
-                    face_indices[1] as usize,
-                    face_indices[2] as usize,
-                );
-
-                let p0 = positions[idx0];
-                let p1 = positions[idx1];
-                let p2 = positions[idx2];
-
-                let normals = if mesh.normals.is_empty() {
-                    [Triangle::compute_normal([p0, p1, p2]); 3]
-                } else {
-                    let n0 = normals[idx0];
-                    let n1 = normals[idx1];
-                    let n2 = normals[idx2];
-
-                    [n0, n1, n2]
-                };
-
-                let texcoords = if mesh.texcoords.is_empty() {
-                    [Vector2::zero(); 3]
-                } else {
-                    let uv0 = texcoords[idx0];
-                    let uv1 = texcoords[idx1];
-                    let uv2 = texcoords[idx2];
-
-                    [uv0, uv1, uv2]
-                };
-
-                let face = Triangle::new(
-                    [p0, p1, p2],
-                    normals,
-                    texcoords,
-                    Transform::default(),
-                    self.material.clone(),
-                );
-
-                children.push(Object3D::Triangle(Box::new(face)));
+                materials
+                    .iter()
+                    .map(|material| from_mtl(material, texture_base, side).into())
+                    .collect()
             }
-        }
+        };
+
+        let children = mesh_file
+            .parts
+            .iter()
+            .map(|part| {
+                let material = part
+                    .material_id
+                    .and_then(|material_id| file_materials.get(material_id))
+                    .unwrap_or(&self.material);
+
+                TriangleMesh::new(Arc::clone(&part.geometry), material.clone()).into()
+            })
+            .collect();
 
         self.children = Some(children);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ray_intersection::IntermediateData;
+    use more_asserts::assert_le;
+    use std::io::BufReader;
+
+    #[test]
+    fn it_converts_mtl_materials() {
+        let mtl = "newmtl plastic\nKd 1 0 0\nKs 0.5 0.5 0.5\nNs 50\nKe 0 0 2\nmap_Kd red.png\n\
+                   newmtl metal\nKd 0.9 0.9 0.9\nPr 0.2\nPm 1\n";
+        let (materials, _) = tobj::load_mtl_buf(&mut BufReader::new(mtl.as_bytes())).unwrap();
+        let texture_base = Path::new("models");
+
+        assert_eq!(
+            from_mtl(&materials[0], texture_base, MaterialSide::Both),
+            Material::Phong(PhongMaterial {
+                side: MaterialSide::Both,
+                color: Vector3::new(1.0, 0.0, 0.0),
+                emissive: Vector3::new(0.0, 0.0, 2.0),
+                specular: Vector3::new(0.5, 0.5, 0.5),
+                shininess: 50.0,
+                texture_path: Some(Path::new("models/red.png").to_string_lossy().into_owned()),
+                ..PhongMaterial::default()
+            })
+        );
+
+        let metal = from_mtl(&materials[1], texture_base, MaterialSide::Front);
+        let Material::Physical(metal) = metal else {
+            panic!("expected a physical material for the PBR extensions");
+        };
+        assert!((metal.roughness - 0.2).abs() < 1e-6);
+        assert!((metal.metalness - 1.0).abs() < 1e-6);
+        assert_eq!(metal.texture_path, None);
+    }
+
+    #[test]
+    fn it_uses_mtl_materials_as_a_fallback() {
+        let fallback = Material::Physical(PhysicalMaterial::default());
+        let mut assets = AssetCache::default();
+        let mut mesh = Mesh::new(
+            "models/teapot.obj".to_string(),
+            Transform::default(),
+            fallback.clone(),
+        );
+        mesh.load_assets(Path::new("scenes"), &mut assets).unwrap();
+        let child = &mesh.children.as_ref().unwrap()[0];
+        assert_eq!(
+            child.get_material().and_then(MaterialRef::get),
+            Some(&fallback)
+        );
+
+        let mut mesh = Mesh::new(
+            "models/teapot.obj".to_string(),
+            Transform::default(),
+            fallback,
+        )
+        .with_material_mode(MaterialMode::Fallback);
+        mesh.load_assets(Path::new("scenes"), &mut assets).unwrap();
+        let child = &mesh.children.as_ref().unwrap()[0];
+        let Some(Material::Phong(material)) = child.get_material().and_then(MaterialRef::get)
+        else {
+            panic!("expected the teapot's phong material");
+        };
+        assert_eq!(material.specular, Vector3::repeat(f64::from(0.33_f32)));
+    }
+
+    #[test]
+    fn it_loads_ply_and_stl_files() {
+        let white = Material::Phong(PhongMaterial {
+            color: Vector3::repeat(1.0),
+            ..PhongMaterial::default()
+        });
+        let mut assets = AssetCache::default();
+
+        let mut stl = Mesh::new(
+            "models/pyramid.stl".to_string(),
+            Transform::default(),
+            white.clone(),
+        );
+        stl.load_assets(Path::new("scenes"), &mut assets).unwrap();
+        let Some([Object3D::TriangleMesh(stl_mesh)]) = stl.children.as_deref() else {
+            panic!("expected a single triangle mesh");
+        };
+        assert_eq!(stl_mesh.len(), 6);
+
+        // The square base of the PLY file is split into two triangles
+        let mut ply = Mesh::new(
+            "models/pyramid.ply".to_string(),
+            Transform::default(),
+            white,
+        );
+        ply.load_assets(Path::new("scenes"), &mut assets).unwrap();
+        let Some([Object3D::TriangleMesh(ply_mesh)]) = ply.children.as_deref() else {
+            panic!("expected a single triangle mesh");
+        };
+        assert_eq!(ply_mesh.len(), 6);
+
+        let triangles = ply.flatten_to_world(&AnimatedTransform::default());
+        assert_eq!(triangles.len(), 6);
+        let center = IntermediateData::Barycentric(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0);
+        let color = triangles[0].vertex_color(center).unwrap();
+        assert_le!(
+            (color - Vector3::new(1.0, 1.0 / 3.0, 1.0 / 3.0)).magnitude(),
+            1e-6
+        );
     }
 }
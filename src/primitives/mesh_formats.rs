@@ -0,0 +1,405 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+use std::str;
+use tobj::Model;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Mesh of an ASCII or binary STL file, as a single model with the normals left to be computed from its faces
+pub(crate) fn load_stl(path: &Path) -> io::Result<Model> {
+    read_stl(&mut BufReader::new(File::open(path)?))
+}
+
+fn read_stl(reader: &mut (impl Read + Seek)) -> io::Result<Model> {
+    let stl = stl_io::read_stl(reader)?;
+    let mut mesh = tobj::Mesh {
+        positions: stl.vertices.iter().flat_map(|vertex| vertex.0).collect(),
+        ..tobj::Mesh::default()
+    };
+    for face in &stl.faces {
+        mesh.indices
+            .extend(face.vertices.iter().map(|&index| index as u32));
+    }
+
+    Ok(Model::new(mesh, String::new()))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> io::Result<Self> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::Int8),
+            "uchar" | "uint8" => Ok(ScalarType::UInt8),
+            "short" | "int16" => Ok(ScalarType::Int16),
+            "ushort" | "uint16" => Ok(ScalarType::UInt16),
+            "int" | "int32" => Ok(ScalarType::Int32),
+            "uint" | "uint32" => Ok(ScalarType::UInt32),
+            "float" | "float32" => Ok(ScalarType::Float32),
+            "double" | "float64" => Ok(ScalarType::Float64),
+            _ => Err(invalid_data(&format!(
+                "unknown PLY property type \"{name}\""
+            ))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // Scale of color components stored with the type, which are between 0 and 1 for floating point types
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::UInt8 => f64::from(u8::MAX),
+            ScalarType::UInt16 => f64::from(u16::MAX),
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+// Values of the body of a PLY file, read in the order the header declares them
+enum ValueReader<'a> {
+    Ascii(str::SplitAsciiWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl ValueReader<'_> {
+    fn read(&mut self, scalar_type: ScalarType) -> io::Result<f64> {
+        match self {
+            ValueReader::Ascii(tokens) => tokens
+                .next()
+                .ok_or_else(|| invalid_data("unexpected end of PLY data"))?
+                .parse()
+                .map_err(|_| invalid_data("invalid number in PLY data")),
+            ValueReader::Binary { data, big_endian } => {
+                let size = scalar_type.size();
+                if data.len() < size {
+                    return Err(invalid_data("unexpected end of PLY data"));
+                }
+                let (bytes, rest) = data.split_at(size);
+                *data = rest;
+
+                let mut buffer = [0; 8];
+                buffer[..size].copy_from_slice(bytes);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buffer;
+
+                Ok(match scalar_type {
+                    ScalarType::Int8 => f64::from(i8::from_le_bytes([b0])),
+                    ScalarType::UInt8 => f64::from(b0),
+                    ScalarType::Int16 => f64::from(i16::from_le_bytes([b0, b1])),
+                    ScalarType::UInt16 => f64::from(u16::from_le_bytes([b0, b1])),
+                    ScalarType::Int32 => f64::from(i32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::UInt32 => f64::from(u32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::Float32 => f64::from(f32::from_le_bytes([b0, b1, b2, b3])),
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            }
+        }
+    }
+}
+
+fn parse_ply_header(header: &str) -> io::Result<(Encoding, Vec<Element>)> {
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid_data("missing PLY magic number"));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => return Err(invalid_data(&format!("unknown PLY format \"{format}\""))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: (*name).to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("invalid PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property declared before any element"))?;
+                element.properties.push(Property {
+                    name: (*name).to_string(),
+                    property_type: PropertyType::List {
+                        count: ScalarType::parse(count)?,
+                        item: ScalarType::parse(item)?,
+                    },
+                });
+            }
+            ["property", scalar_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property declared before any element"))?;
+                element.properties.push(Property {
+                    name: (*name).to_string(),
+                    property_type: PropertyType::Scalar(ScalarType::parse(scalar_type)?),
+                });
+            }
+            ["comment" | "obj_info", ..] | [] => {}
+            _ => return Err(invalid_data(&format!("invalid PLY header line \"{line}\""))),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| invalid_data("missing PLY format"))?;
+
+    Ok((encoding, elements))
+}
+
+fn get_values<'a>(
+    values: &'a [f64],
+    indices: &'a [usize],
+    scale: f64,
+) -> impl Iterator<Item = f32> + 'a {
+    indices
+        .iter()
+        .map(move |&index| (values[index] / scale) as f32)
+}
+
+// Mesh of an ASCII or binary PLY file with its vertex positions, normals, texture coordinates and colors, and
+// its faces split into triangle fans
+pub(crate) fn load_ply(path: &Path) -> io::Result<Model> {
+    parse_ply(&fs::read(path)?)
+}
+
+fn parse_ply(data: &[u8]) -> io::Result<Model> {
+    const END_HEADER: &[u8] = b"end_header";
+    let header_end = data
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or_else(|| invalid_data("missing end of PLY header"))?;
+    let header = str::from_utf8(&data[..header_end])
+        .map_err(|_| invalid_data("PLY header is not valid text"))?;
+    let (encoding, elements) = parse_ply_header(header)?;
+
+    // The body starts on the line after the end of the header
+    let body_start = data[header_end..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(data.len(), |offset| header_end + offset + 1);
+    let body = &data[body_start..];
+    let mut reader = match encoding {
+        Encoding::Ascii => ValueReader::Ascii(
+            str::from_utf8(body)
+                .map_err(|_| invalid_data("PLY data is not valid text"))?
+                .split_ascii_whitespace(),
+        ),
+        Encoding::BinaryLittleEndian | Encoding::BinaryBigEndian => ValueReader::Binary {
+            data: body,
+            big_endian: encoding == Encoding::BinaryBigEndian,
+        },
+    };
+
+    let mut mesh = tobj::Mesh::default();
+    let mut vertex_count = 0;
+    for element in &elements {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let find_all = |names: [&[&str]; 3]| {
+            names
+                .iter()
+                .map(|names| find(names))
+                .collect::<Option<Vec<_>>>()
+        };
+        let positions = find_all([&["x"], &["y"], &["z"]]);
+        let normals = find_all([&["nx"], &["ny"], &["nz"]]);
+        let colors = find_all([&["red", "r"], &["green", "g"], &["blue", "b"]]);
+        let texcoords = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+        let color_scale = colors.as_ref().map_or(1.0, |colors| {
+            match element.properties[colors[0]].property_type {
+                PropertyType::Scalar(scalar_type) => scalar_type.color_scale(),
+                PropertyType::List { .. } => 1.0,
+            }
+        });
+
+        let mut values = Vec::with_capacity(element.properties.len());
+        for _ in 0..element.count {
+            values.clear();
+            let mut face = Vec::new();
+            for (index, property) in element.properties.iter().enumerate() {
+                match property.property_type {
+                    PropertyType::Scalar(scalar_type) => values.push(reader.read(scalar_type)?),
+                    PropertyType::List { count, item } => {
+                        values.push(0.0);
+                        let count = reader.read(count)? as usize;
+                        for _ in 0..count {
+                            let value = reader.read(item)?;
+                            if Some(index) == indices {
+                                if value < 0.0
+                                    || value.fract() != 0.0
+                                    || value > f64::from(u32::MAX)
+                                {
+                                    return Err(invalid_data("invalid PLY vertex index"));
+                                }
+                                face.push(value as u32);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if element.name == "vertex" {
+                if let Some(positions) = &positions {
+                    mesh.positions.extend(get_values(&values, positions, 1.0));
+                }
+                if let Some(normals) = &normals {
+                    mesh.normals.extend(get_values(&values, normals, 1.0));
+                }
+                if let [Some(u), Some(v)] = texcoords {
+                    mesh.texcoords.extend(get_values(&values, &[u, v], 1.0));
+                }
+                if let Some(colors) = &colors {
+                    mesh.vertex_color
+                        .extend(get_values(&values, colors, color_scale));
+                }
+            } else if element.name == "face" && face.len() >= 3 {
+                for corner in 1..face.len() - 1 {
+                    mesh.indices
+                        .extend([face[0], face[corner], face[corner + 1]]);
+                }
+            }
+        }
+
+        if element.name == "vertex" {
+            if positions.is_none() {
+                return Err(invalid_data("PLY vertices have no positions"));
+            }
+            vertex_count = element.count;
+        }
+    }
+
+    if mesh
+        .indices
+        .iter()
+        .any(|&index| index as usize >= vertex_count)
+    {
+        return Err(invalid_data(
+            "PLY face refers to a vertex which does not exist",
+        ));
+    }
+
+    Ok(Model::new(mesh, String::new()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn it_parses_ascii_and_binary_ply_files() {
+        let header = "ply\nformat {format} 1.0\ncomment square\nelement vertex 4\n\
+                      property float x\nproperty float y\nproperty float z\n\
+                      property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                      element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let ascii = header.replace("{format}", "ascii")
+            + "0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n4 0 1 2 3\n";
+
+        let mut binary = header.replace("{format}", "binary_big_endian").into_bytes();
+        for (x, y, red) in [
+            (0.0_f32, 0.0_f32, 255),
+            (1.0, 0.0, 255),
+            (1.0, 1.0, 0),
+            (0.0, 1.0, 0),
+        ] {
+            for value in [x, y, 0.0] {
+                binary.extend(value.to_be_bytes());
+            }
+            binary.extend([red, 0, 255 - red]);
+        }
+        binary.push(4);
+        for index in 0..4_i32 {
+            binary.extend(index.to_be_bytes());
+        }
+
+        for data in [ascii.as_bytes(), &binary] {
+            let mesh = parse_ply(data).unwrap().mesh;
+            assert_eq!(mesh.positions.len(), 12);
+            assert_eq!(&mesh.positions[6..9], &[1.0, 1.0, 0.0]);
+            assert_eq!(&mesh.vertex_color[..6], &[1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+            assert!(mesh.normals.is_empty());
+            assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        }
+
+        let out_of_bounds = ascii.replace("4 0 1 2 3", "3 0 1 4");
+        assert!(parse_ply(out_of_bounds.as_bytes()).is_err());
+
+        for invalid_index in ["-1", "1.5"] {
+            let invalid = ascii.replace("4 0 1 2 3", &format!("3 0 {invalid_index} 2"));
+            assert!(parse_ply(invalid.as_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn it_reads_stl_files() {
+        let stl = "solid triangle\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                   vertex 0 1 0\nendloop\nendfacet\nendsolid triangle\n";
+        let mesh = read_stl(&mut Cursor::new(stl)).unwrap().mesh;
+        assert_eq!(mesh.positions.len(), 9);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+    }
+}
@@ -1,27 +1,41 @@
 mod cube;
+mod gltf;
 mod group;
+mod instance;
 mod mesh;
+mod mesh_formats;
 mod plane;
 mod sphere;
 mod triangle;
+mod triangle_mesh;
 
-use crate::core::{Material, ObjectWithBounds, Texture, Transform, Transformed};
+use crate::core::{AnimatedTransform, Material, MaterialRef, ObjectWithBounds, Transformed};
+use crate::lights::EmitterShape;
 use crate::ray_intersection::{IntermediateData, Intersectable};
+use crate::render::{AssetCache, Validator};
+use crate::RaytraceError;
 use nalgebra::{Point3, Unit, Vector2, Vector3};
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::marker::{Send, Sync};
 use std::path::Path;
+use std::sync::Arc;
 
 pub use cube::{Cube, RaytracingCube};
+pub use gltf::Gltf;
+pub(crate) use gltf::GltfFile;
 pub use group::Group;
-pub use mesh::Mesh;
+pub use instance::{Instance, Prototype, RaytracingInstance};
+pub(crate) use mesh::MeshFile;
+pub use mesh::{MaterialMode, Mesh};
 pub use plane::{Plane, RaytracingPlane};
 pub use sphere::{RaytracingSphere, Sphere};
 pub use triangle::{RaytracingTriangle, Triangle};
+pub(crate) use triangle_mesh::MeshGeometry;
+pub use triangle_mesh::{RaytracingMeshTriangle, TriangleMesh};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields, tag = "type", rename_all = "lowercase")]
 pub enum Object3D {
     Cube(Box<Cube>),
@@ -29,36 +43,63 @@ pub enum Object3D {
     Sphere(Box<Sphere>),
     Triangle(Box<Triangle>),
     Mesh(Box<Mesh>),
+    Gltf(Box<Gltf>),
     Group(Box<Group>),
+    Instance(Box<Instance>),
+    // Built from the files of meshes rather than deserialized
+    #[serde(skip)]
+    TriangleMesh(Box<TriangleMesh>),
 }
 
 impl Object3D {
     pub fn load_assets(
         object: &mut Object3D,
         asset_base: &Path,
-        textures: &mut HashMap<String, Texture>,
-    ) {
-        if let Object3D::Mesh(mesh) = object {
-            mesh.load_assets(asset_base);
+        assets: &mut AssetCache,
+    ) -> Result<(), RaytraceError> {
+        match object {
+            Object3D::Mesh(mesh) => mesh.load_assets(asset_base, assets)?,
+            Object3D::Gltf(gltf) => gltf.load_assets(asset_base, assets)?,
+            _ => {}
         }
 
-        let material = match object {
-            Object3D::Cube(cube) => Some(&cube.material),
-            Object3D::Plane(plane) => Some(&plane.material),
-            Object3D::Sphere(sphere) => Some(&sphere.material),
-            Object3D::Triangle(triangle) => Some(&triangle.material),
-            Object3D::Mesh(mesh) => Some(&mesh.material),
-            Object3D::Group(_) => None,
-        };
-        if let Some(material) = material {
-            material.load_textures(asset_base, textures);
+        if let Some(material) = object.get_material().and_then(MaterialRef::get) {
+            material.load_textures(asset_base, assets.get_textures_mut())?;
         }
 
         if let Some(children) = object.get_children_mut() {
             for child in children {
-                Object3D::load_assets(child, asset_base, textures);
+                Object3D::load_assets(child, asset_base, assets)?;
             }
         }
+
+        Ok(())
+    }
+
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        match self {
+            Object3D::Cube(cube) => cube.validate(validator),
+            Object3D::Triangle(triangle) => triangle.validate(validator),
+            Object3D::Plane(plane) => plane.validate(validator),
+            Object3D::Sphere(sphere) => sphere.validate(validator),
+            Object3D::Mesh(mesh) => mesh.validate(validator),
+            Object3D::Gltf(gltf) => gltf.validate(validator),
+            Object3D::Group(group) => group.validate(validator),
+            Object3D::Instance(instance) => instance.validate(validator),
+            Object3D::TriangleMesh(_) => {}
+        }
+
+        if let Some(material) = self.get_material() {
+            validator.field("material", |validator| material.validate(validator));
+        }
+
+        if let Some(children) = self.get_children() {
+            validator.field("children", |validator| {
+                for (index, child) in children.iter().enumerate() {
+                    validator.index(index, |validator| child.validate(validator));
+                }
+            });
+        }
     }
 
     pub fn add_child(&mut self, object: Object3D) {
@@ -68,7 +109,96 @@ impl Object3D {
             Object3D::Plane(plane) => plane.add_child(object),
             Object3D::Sphere(sphere) => sphere.add_child(object),
             Object3D::Mesh(mesh) => mesh.add_child(object),
+            Object3D::Gltf(gltf) => gltf.add_child(object),
             Object3D::Group(group) => group.add_child(object),
+            Object3D::Instance(instance) => instance.add_child(object),
+            Object3D::TriangleMesh(_) => {}
+        }
+    }
+
+    // Replace material names with the named materials, for this object and its children
+    pub fn resolve_materials(
+        &mut self,
+        materials: &BTreeMap<String, Material>,
+    ) -> Result<(), RaytraceError> {
+        if let Some(material) = self.get_material_mut() {
+            material.resolve(materials)?;
+        }
+
+        if let Some(children) = self.get_children_mut() {
+            for child in children {
+                child.resolve_materials(materials)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Attach the built prototypes to the instances among this object and its children
+    pub fn resolve_prototypes(
+        &mut self,
+        prototypes: &BTreeMap<String, Arc<Prototype>>,
+    ) -> Result<(), RaytraceError> {
+        if let Object3D::Instance(instance) = self {
+            instance.resolve_prototype(prototypes)?;
+        }
+
+        if let Some(children) = self.get_children_mut() {
+            for child in children {
+                child.resolve_prototypes(prototypes)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Whether this object or one of its children is an instance, which prototypes cannot contain
+    pub(crate) fn has_instances(&self) -> bool {
+        matches!(self, Object3D::Instance(_))
+            || self
+                .get_children()
+                .is_some_and(|children| children.iter().any(Object3D::has_instances))
+    }
+
+    fn get_material(&self) -> Option<&MaterialRef> {
+        match self {
+            Object3D::Cube(cube) => Some(&cube.material),
+            Object3D::Plane(plane) => Some(&plane.material),
+            Object3D::Sphere(sphere) => Some(&sphere.material),
+            Object3D::Triangle(triangle) => Some(&triangle.material),
+            Object3D::Mesh(mesh) => Some(&mesh.material),
+            Object3D::Gltf(gltf) => Some(&gltf.material),
+            Object3D::TriangleMesh(mesh) => Some(&mesh.material),
+            Object3D::Instance(instance) => instance.material.as_ref(),
+            Object3D::Group(_) => None,
+        }
+    }
+
+    fn get_material_mut(&mut self) -> Option<&mut MaterialRef> {
+        match self {
+            Object3D::Cube(cube) => Some(&mut cube.material),
+            Object3D::Plane(plane) => Some(&mut plane.material),
+            Object3D::Sphere(sphere) => Some(&mut sphere.material),
+            Object3D::Triangle(triangle) => Some(&mut triangle.material),
+            Object3D::Mesh(mesh) => Some(&mut mesh.material),
+            Object3D::Gltf(gltf) => Some(&mut gltf.material),
+            Object3D::TriangleMesh(mesh) => Some(&mut mesh.material),
+            Object3D::Instance(instance) => instance.material.as_mut(),
+            Object3D::Group(_) => None,
+        }
+    }
+
+    fn get_children(&self) -> Option<&Vec<Object3D>> {
+        match self {
+            Object3D::Cube(cube) => cube.children.as_ref(),
+            Object3D::Triangle(triangle) => triangle.children.as_ref(),
+            Object3D::Plane(plane) => plane.children.as_ref(),
+            Object3D::Sphere(sphere) => sphere.children.as_ref(),
+            Object3D::Mesh(mesh) => mesh.children.as_ref(),
+            Object3D::Gltf(gltf) => gltf.children.as_ref(),
+            Object3D::Group(group) => Some(&group.children),
+            Object3D::Instance(instance) => instance.children.as_ref(),
+            Object3D::TriangleMesh(_) => None,
         }
     }
 
@@ -79,22 +209,82 @@ impl Object3D {
             Object3D::Plane(plane) => plane.children.as_mut(),
             Object3D::Sphere(sphere) => sphere.children.as_mut(),
             Object3D::Mesh(mesh) => mesh.children.as_mut(),
+            Object3D::Gltf(gltf) => gltf.children.as_mut(),
             Object3D::Group(group) => Some(&mut group.children),
+            Object3D::Instance(instance) => instance.children.as_mut(),
+            Object3D::TriangleMesh(_) => None,
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
         match self {
             Object3D::Cube(cube) => cube.flatten_to_world(transform),
             Object3D::Triangle(triangle) => triangle.flatten_to_world(transform),
             Object3D::Plane(plane) => plane.flatten_to_world(transform),
             Object3D::Sphere(sphere) => sphere.flatten_to_world(transform),
             Object3D::Mesh(mesh) => mesh.flatten_to_world(transform),
+            Object3D::Gltf(gltf) => gltf.flatten_to_world(transform),
             Object3D::Group(group) => group.flatten_to_world(transform),
+            Object3D::Instance(instance) => instance.flatten_to_world(transform),
+            Object3D::TriangleMesh(mesh) => mesh.flatten_to_world(transform),
         }
     }
 }
 
+impl From<Cube> for Object3D {
+    fn from(value: Cube) -> Self {
+        Object3D::Cube(Box::new(value))
+    }
+}
+
+impl From<Plane> for Object3D {
+    fn from(value: Plane) -> Self {
+        Object3D::Plane(Box::new(value))
+    }
+}
+
+impl From<Sphere> for Object3D {
+    fn from(value: Sphere) -> Self {
+        Object3D::Sphere(Box::new(value))
+    }
+}
+
+impl From<Triangle> for Object3D {
+    fn from(value: Triangle) -> Self {
+        Object3D::Triangle(Box::new(value))
+    }
+}
+
+impl From<Mesh> for Object3D {
+    fn from(value: Mesh) -> Self {
+        Object3D::Mesh(Box::new(value))
+    }
+}
+
+impl From<Gltf> for Object3D {
+    fn from(value: Gltf) -> Self {
+        Object3D::Gltf(Box::new(value))
+    }
+}
+
+impl From<Instance> for Object3D {
+    fn from(value: Instance) -> Self {
+        Object3D::Instance(Box::new(value))
+    }
+}
+
+impl From<TriangleMesh> for Object3D {
+    fn from(value: TriangleMesh) -> Self {
+        Object3D::TriangleMesh(Box::new(value))
+    }
+}
+
+impl From<Group> for Object3D {
+    fn from(value: Group) -> Self {
+        Object3D::Group(Box::new(value))
+    }
+}
+
 pub trait HasMaterial {
     fn get_material(&self) -> &Material;
 }
@@ -113,6 +303,16 @@ pub trait Primitive: Transformed {
         object_normal: &Unit<Vector3<f64>>,
         intermediate: IntermediateData,
     ) -> Vector2<f64>;
+
+    // Color multiplying the color of the material at the hit point, such as interpolated vertex colors
+    fn vertex_color(&self, _intermediate: IntermediateData) -> Option<Vector3<f64>> {
+        None
+    }
+
+    // World space surface used to sample the primitive as a light, if it supports area sampling
+    fn get_emitter_shape(&self) -> Option<EmitterShape> {
+        None
+    }
 }
 
 pub trait RaytracingObject:
@@ -124,3 +324,5 @@ impl RaytracingObject for RaytracingCube {}
 impl RaytracingObject for RaytracingPlane {}
 impl RaytracingObject for RaytracingSphere {}
 impl RaytracingObject for RaytracingTriangle {}
+impl RaytracingObject for RaytracingMeshTriangle {}
+impl RaytracingObject for RaytracingInstance {}
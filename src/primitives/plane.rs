@@ -1,16 +1,22 @@
 use super::{HasMaterial, Object3D, Primitive, RaytracingObject};
-use crate::core::{Material, MaterialSide, ObjectWithBounds, Transform, Transformed};
+use crate::core::{
+    AnimatedTransform, Material, MaterialRef, MaterialSide, ObjectWithBounds, Transform,
+    Transformed,
+};
 use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray, RayType};
+use crate::render::Validator;
 use nalgebra::{Point3, Rotation3, Unit, Vector2, Vector3};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::f64::EPSILON;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct Plane {
     normal: Unit<Vector3<f64>>,
     transform: Transform,
-    pub material: Material,
+    end_transform: Option<Transform>,
+    pub material: MaterialRef,
 
     pub children: Option<Vec<Object3D>>,
 }
@@ -20,7 +26,8 @@ impl Default for Plane {
         Self {
             normal: Vector3::y_axis(),
             transform: Transform::default(),
-            material: Material::default(),
+            end_transform: None,
+            material: MaterialRef::default(),
 
             children: None,
         }
@@ -28,23 +35,32 @@ impl Default for Plane {
 }
 
 impl Plane {
-    pub fn new(normal: Unit<Vector3<f64>>, transform: Transform, material: Material) -> Self {
+    pub fn new(
+        normal: Unit<Vector3<f64>>,
+        transform: Transform,
+        material: impl Into<MaterialRef>,
+    ) -> Self {
         Self {
             normal,
             transform,
-            material,
+            material: material.into(),
             ..Plane::default()
         }
     }
 
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_direction("normal", &self.normal);
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         if let Some(children) = self.children.as_mut() {
             children.push(object);
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
 
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
@@ -59,7 +75,7 @@ impl Plane {
         objects.push(Box::new(RaytracingPlane::new(
             self.normal,
             transform,
-            self.material,
+            self.material.into_material(),
         )));
 
         objects
@@ -69,12 +85,16 @@ impl Plane {
 #[derive(Debug)]
 pub struct RaytracingPlane {
     normal: Unit<Vector3<f64>>,
-    world_transform: Transform,
+    world_transform: AnimatedTransform,
     material: Material,
 }
 
 impl RaytracingPlane {
-    pub fn new(normal: Unit<Vector3<f64>>, world_transform: Transform, material: Material) -> Self {
+    pub fn new(
+        normal: Unit<Vector3<f64>>,
+        world_transform: AnimatedTransform,
+        material: Material,
+    ) -> Self {
         Self {
             normal,
             world_transform,
@@ -91,7 +111,11 @@ impl HasMaterial for RaytracingPlane {
 
 impl Transformed for RaytracingPlane {
     fn get_transform(&self) -> &Transform {
-        &self.world_transform
+        self.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.world_transform.at(time)
     }
 }
 
@@ -1,19 +1,24 @@
 use super::{HasMaterial, Object3D, Primitive, RaytracingObject};
 use crate::core::{
-    BoundingVolume, Material, MaterialSide, ObjectWithBounds, Transform, Transformed,
+    AnimatedTransform, BoundingVolume, Material, MaterialRef, MaterialSide, ObjectWithBounds,
+    Transform, Transformed,
 };
+use crate::lights::EmitterShape;
 use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray, RayType};
+use crate::render::Validator;
 use crate::utils;
 use nalgebra::{Point3, Unit, Vector2, Vector3};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::f64::consts::FRAC_1_PI;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct Sphere {
     radius: f64,
     transform: Transform,
-    pub material: Material,
+    end_transform: Option<Transform>,
+    pub material: MaterialRef,
 
     pub children: Option<Vec<Object3D>>,
 }
@@ -23,7 +28,8 @@ impl Default for Sphere {
         Self {
             radius: 1.0,
             transform: Transform::default(),
-            material: Material::default(),
+            end_transform: None,
+            material: MaterialRef::default(),
 
             children: None,
         }
@@ -31,23 +37,28 @@ impl Default for Sphere {
 }
 
 impl Sphere {
-    pub fn new(radius: f64, transform: Transform, material: Material) -> Self {
+    pub fn new(radius: f64, transform: Transform, material: impl Into<MaterialRef>) -> Self {
         Self {
             radius,
             transform,
-            material,
+            material: material.into(),
             ..Sphere::default()
         }
     }
 
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        validator.check_positive("radius", self.radius);
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         if let Some(children) = self.children.as_mut() {
             children.push(object);
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
 
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
@@ -62,7 +73,7 @@ impl Sphere {
         objects.push(Box::new(RaytracingSphere::new(
             self.radius,
             transform,
-            self.material,
+            self.material.into_material(),
         )));
 
         objects
@@ -72,12 +83,12 @@ impl Sphere {
 #[derive(Debug)]
 pub struct RaytracingSphere {
     radius: f64,
-    world_transform: Transform,
+    world_transform: AnimatedTransform,
     material: Material,
 }
 
 impl RaytracingSphere {
-    pub fn new(radius: f64, world_transform: Transform, material: Material) -> Self {
+    pub fn new(radius: f64, world_transform: AnimatedTransform, material: Material) -> Self {
         Self {
             radius,
             world_transform,
@@ -94,7 +105,11 @@ impl HasMaterial for RaytracingSphere {
 
 impl Transformed for RaytracingSphere {
     fn get_transform(&self) -> &Transform {
-        &self.world_transform
+        self.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.world_transform.at(time)
     }
 }
 
@@ -131,10 +146,10 @@ impl Intersectable for RaytracingSphere {
 
 impl Primitive for RaytracingSphere {
     fn into_bounded_object(self: Box<Self>) -> ObjectWithBounds {
-        let bounding_volume = BoundingVolume::from_bounds_and_transform(
+        let bounding_volume = BoundingVolume::from_bounds_and_motion(
             Point3::from([-self.radius; 3]),
             Point3::from([self.radius; 3]),
-            self.get_transform(),
+            &self.world_transform,
         );
 
         ObjectWithBounds::bounded(self, bounding_volume)
@@ -161,4 +176,19 @@ impl Primitive for RaytracingSphere {
             hit_point.y.asin() * FRAC_1_PI + 0.5,
         )
     }
+
+    fn get_emitter_shape(&self) -> Option<EmitterShape> {
+        let matrix = self.get_transform().matrix();
+        let scale = matrix
+            .matrix()
+            .fixed_slice::<3, 3>(0, 0)
+            .determinant()
+            .abs()
+            .cbrt();
+
+        Some(EmitterShape::Sphere {
+            center: matrix * Point3::origin(),
+            radius: self.radius * scale,
+        })
+    }
 }
@@ -1,14 +1,18 @@
 use super::{HasMaterial, Object3D, Primitive, RaytracingObject};
 use crate::core::{
-    BoundingVolume, Material, MaterialSide, ObjectWithBounds, Transform, Transformed,
+    AnimatedTransform, BoundingVolume, Material, MaterialRef, MaterialSide, ObjectWithBounds,
+    Transform, Transformed,
 };
+use crate::lights::EmitterShape;
 use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray, RayType};
+use crate::render::Validator;
 use nalgebra::{Point3, Unit, Vector2, Vector3};
 use num_traits::identities::Zero;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::f64::EPSILON;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 struct VertexPNT {
     position: Point3<f64>,
@@ -26,20 +30,21 @@ impl VertexPNT {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 enum VertexData {
     VertexPNT([VertexPNT; 3]),
     Position([Point3<f64>; 3]),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct Triangle {
-    #[serde(alias = "vertices")]
+    #[serde(rename = "vertices", alias = "vertex_data")]
     vertex_data: VertexData,
     transform: Transform,
-    pub material: Material,
+    end_transform: Option<Transform>,
+    pub material: MaterialRef,
 
     pub children: Option<Vec<Object3D>>,
 }
@@ -53,7 +58,8 @@ impl Default for Triangle {
                 Point3::origin(),
             ]),
             transform: Transform::default(),
-            material: Material::default(),
+            end_transform: None,
+            material: MaterialRef::default(),
 
             children: None,
         }
@@ -66,7 +72,7 @@ impl Triangle {
         normals: [Unit<Vector3<f64>>; 3],
         texcoords: [Vector2<f64>; 3],
         transform: Transform,
-        material: Material,
+        material: impl Into<MaterialRef>,
     ) -> Self {
         let vertex_data = VertexData::VertexPNT([
             VertexPNT::new(positions[0], normals[0], texcoords[0]),
@@ -77,7 +83,8 @@ impl Triangle {
         Self {
             vertex_data,
             transform,
-            material,
+            end_transform: None,
+            material: material.into(),
 
             children: None,
         }
@@ -86,12 +93,13 @@ impl Triangle {
     pub fn new_with_positions(
         positions: [Point3<f64>; 3],
         transform: Transform,
-        material: Material,
+        material: impl Into<MaterialRef>,
     ) -> Self {
         Self {
             vertex_data: VertexData::Position(positions),
             transform,
-            material,
+            end_transform: None,
+            material: material.into(),
 
             children: None,
         }
@@ -104,14 +112,38 @@ impl Triangle {
         Unit::new_normalize(edge1.cross(&edge2))
     }
 
+    pub(crate) fn validate(&self, validator: &mut Validator) {
+        let positions = match &self.vertex_data {
+            VertexData::VertexPNT(vertices) => {
+                for (index, vertex) in vertices.iter().enumerate() {
+                    validator.field("vertices", |validator| {
+                        validator.index(index, |validator| {
+                            validator.check_direction("normal", &vertex.normal);
+                        });
+                    });
+                }
+                vertices.each_ref().map(|vertex| vertex.position)
+            }
+            VertexData::Position(positions) => *positions,
+        };
+        let edge1 = positions[1] - positions[0];
+        let edge2 = positions[2] - positions[0];
+        validator.check(
+            "vertices",
+            edge1.cross(&edge2).magnitude() > f64::EPSILON,
+            "must not be collinear",
+        );
+        validator.check_transforms(&self.transform, self.end_transform.as_ref());
+    }
+
     pub fn add_child(&mut self, object: Object3D) {
         if let Some(children) = self.children.as_mut() {
             children.push(object);
         }
     }
 
-    pub fn flatten_to_world(self, transform: &Transform) -> Vec<Box<dyn RaytracingObject>> {
-        let transform = transform * self.transform;
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let transform = transform.compose(&self.transform, self.end_transform.as_ref());
 
         let mut objects: Vec<Box<dyn RaytracingObject>> = Vec::new();
 
@@ -128,14 +160,14 @@ impl Triangle {
                 objects.push(Box::new(RaytracingTriangle::new(
                     vertex_data,
                     transform,
-                    self.material,
+                    self.material.into_material(),
                 )));
             }
             VertexData::Position(vertices) => {
                 objects.push(Box::new(RaytracingTriangle::new_with_positions(
                     vertices,
                     transform,
-                    self.material,
+                    self.material.into_material(),
                 )));
             }
         }
@@ -147,12 +179,16 @@ impl Triangle {
 #[derive(Debug)]
 pub struct RaytracingTriangle {
     vertex_data: [VertexPNT; 3],
-    world_transform: Transform,
+    world_transform: AnimatedTransform,
     material: Material,
 }
 
 impl RaytracingTriangle {
-    fn new(vertex_data: [VertexPNT; 3], world_transform: Transform, material: Material) -> Self {
+    fn new(
+        vertex_data: [VertexPNT; 3],
+        world_transform: AnimatedTransform,
+        material: Material,
+    ) -> Self {
         Self {
             vertex_data,
             world_transform,
@@ -162,7 +198,7 @@ impl RaytracingTriangle {
 
     fn new_with_positions(
         positions: [Point3<f64>; 3],
-        world_transform: Transform,
+        world_transform: AnimatedTransform,
         material: Material,
     ) -> Self {
         let normals = [Triangle::compute_normal(positions); 3];
@@ -186,66 +222,115 @@ impl HasMaterial for RaytracingTriangle {
 
 impl Transformed for RaytracingTriangle {
     fn get_transform(&self) -> &Transform {
-        &self.world_transform
+        self.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.world_transform.at(time)
     }
 }
 
-impl Intersectable for RaytracingTriangle {
-    fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection> {
-        let edge1 = self.vertex_data[1].position - self.vertex_data[0].position;
-        let edge2 = self.vertex_data[2].position - self.vertex_data[0].position;
-        let p_vec = ray.direction.cross(&edge2);
-        let det = edge1.dot(&p_vec);
-
-        if match (self.material.side(), ray.ray_type) {
-            (MaterialSide::Both, _) | (_, RayType::Shadow) => det.abs() < EPSILON,
-            (MaterialSide::Front, _) => det < EPSILON,
-            (MaterialSide::Back, _) => -det < EPSILON,
-        } {
-            return None;
-        }
+// Distance along the ray to the triangle and barycentric coordinates of the hit point, using the Möller-Trumbore
+// algorithm and culling faces which the material's side does not render
+pub(super) fn intersect_triangle(
+    positions: [Point3<f64>; 3],
+    side: MaterialSide,
+    ray: &Ray,
+    max_distance: Option<f64>,
+) -> Option<(f64, IntermediateData)> {
+    let edge1 = positions[1] - positions[0];
+    let edge2 = positions[2] - positions[0];
+    let p_vec = ray.direction.cross(&edge2);
+    let det = edge1.dot(&p_vec);
+
+    if match (side, ray.ray_type) {
+        (MaterialSide::Both, _) | (_, RayType::Shadow) => det.abs() < EPSILON,
+        (MaterialSide::Front, _) => det < EPSILON,
+        (MaterialSide::Back, _) => -det < EPSILON,
+    } {
+        return None;
+    }
 
-        let t_vec = ray.origin - self.vertex_data[0].position;
-        let u = t_vec.dot(&p_vec) / det;
-        if !(0.0..=1.0).contains(&u) {
-            return None;
-        }
+    let t_vec = ray.origin - positions[0];
+    let u = t_vec.dot(&p_vec) / det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
 
-        let q_vec = t_vec.cross(&edge1);
-        let v = ray.direction.dot(&q_vec) / det;
-        if v < 0.0 || 1.0 < u + v {
-            return None;
-        }
+    let q_vec = t_vec.cross(&edge1);
+    let v = ray.direction.dot(&q_vec) / det;
+    if v < 0.0 || 1.0 < u + v {
+        return None;
+    }
 
-        let distance = edge2.dot(&q_vec) / det;
+    let distance = edge2.dot(&q_vec) / det;
 
-        if distance < 0.0 || (max_distance.is_some() && max_distance.unwrap() < distance) {
-            return None;
-        }
+    if distance < 0.0 || (max_distance.is_some() && max_distance.unwrap() < distance) {
+        return None;
+    }
 
-        Some(Intersection::new_with_data(
-            self,
-            distance,
-            IntermediateData::Barycentric(u, v, 1.0 - u - v),
-        ))
+    Some((distance, IntermediateData::Barycentric(u, v, 1.0 - u - v)))
+}
+
+pub(super) fn triangle_bounds(positions: [Point3<f64>; 3]) -> (Point3<f64>, Point3<f64>) {
+    let mut min = positions[0];
+    let mut max = min;
+    for position in &positions[1..] {
+        min.x = min.x.min(position.x);
+        min.y = min.y.min(position.y);
+        min.z = min.z.min(position.z);
+
+        max.x = max.x.max(position.x);
+        max.y = max.y.max(position.y);
+        max.z = max.z.max(position.z);
+    }
+
+    (min, max)
+}
+
+// World space triangle of a triangle emitting light
+pub(super) fn triangle_emitter_shape(
+    positions: [Point3<f64>; 3],
+    transform: &Transform,
+) -> EmitterShape {
+    let matrix = transform.matrix();
+    let [a, b, c] = positions.map(|position| matrix * position);
+
+    // Keep the winding order consistent with the front face if the transform mirrors the triangle
+    if matrix.matrix().fixed_slice::<3, 3>(0, 0).determinant() < 0.0 {
+        EmitterShape::Triangle([a, c, b])
+    } else {
+        EmitterShape::Triangle([a, b, c])
+    }
+}
+
+pub(super) fn barycentric(intermediate: IntermediateData) -> (f64, f64, f64) {
+    match intermediate {
+        IntermediateData::Barycentric(u, v, w) => (u, v, w),
+        _ => unreachable!(),
+    }
+}
+
+impl RaytracingTriangle {
+    fn positions(&self) -> [Point3<f64>; 3] {
+        self.vertex_data.each_ref().map(|vertex| vertex.position)
+    }
+}
+
+impl Intersectable for RaytracingTriangle {
+    fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection> {
+        let (distance, intermediate) =
+            intersect_triangle(self.positions(), self.material.side(), ray, max_distance)?;
+
+        Some(Intersection::new_with_data(self, distance, intermediate))
     }
 }
 
 impl Primitive for RaytracingTriangle {
     fn into_bounded_object(self: Box<Self>) -> ObjectWithBounds {
-        let mut min = self.vertex_data[0].position;
-        let mut max = min;
-        for VertexPNT { position, .. } in self.vertex_data[1..].iter() {
-            min.x = min.x.min(position.x);
-            min.y = min.y.min(position.y);
-            min.z = min.z.min(position.z);
-
-            max.x = max.x.max(position.x);
-            max.y = max.y.max(position.y);
-            max.z = max.z.max(position.z);
-        }
+        let (min, max) = triangle_bounds(self.positions());
         let bounding_volume =
-            BoundingVolume::from_bounds_and_transform(min, max, self.get_transform());
+            BoundingVolume::from_bounds_and_motion(min, max, &self.world_transform);
 
         ObjectWithBounds::bounded(self, bounding_volume)
     }
@@ -255,10 +340,7 @@ impl Primitive for RaytracingTriangle {
         _object_hit_point: &Point3<f64>,
         intermediate: IntermediateData,
     ) -> Unit<Vector3<f64>> {
-        let (u, v, w) = match intermediate {
-            IntermediateData::Barycentric(u, v, w) => (u, v, w),
-            _ => unreachable!(),
-        };
+        let (u, v, w) = barycentric(intermediate);
 
         Unit::new_normalize(
             w * self.vertex_data[0].normal.into_inner()
@@ -273,13 +355,17 @@ impl Primitive for RaytracingTriangle {
         _object_normal: &Unit<Vector3<f64>>,
         intermediate: IntermediateData,
     ) -> Vector2<f64> {
-        let (u, v, w) = match intermediate {
-            IntermediateData::Barycentric(u, v, w) => (u, v, w),
-            _ => unreachable!(),
-        };
+        let (u, v, w) = barycentric(intermediate);
 
         w * self.vertex_data[0].texcoords
             + u * self.vertex_data[1].texcoords
             + v * self.vertex_data[2].texcoords
     }
+
+    fn get_emitter_shape(&self) -> Option<EmitterShape> {
+        Some(triangle_emitter_shape(
+            self.positions(),
+            self.get_transform(),
+        ))
+    }
 }
@@ -0,0 +1,275 @@
+use super::triangle::{barycentric, intersect_triangle, triangle_bounds, triangle_emitter_shape};
+use super::{HasMaterial, Primitive, RaytracingObject, Triangle};
+use crate::core::{
+    AnimatedTransform, BoundingVolume, Material, MaterialRef, ObjectWithBounds, Transform,
+    Transformed,
+};
+use crate::lights::EmitterShape;
+use crate::ray_intersection::{IntermediateData, Intersectable, Intersection, Ray};
+use nalgebra::{Point3, Unit, Vector2, Vector3};
+use num_traits::identities::Zero;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+// Vertex and index buffers which the triangles of a mesh share. Vertices are stored in single precision, as mesh
+// files store them.
+#[derive(Debug, Default)]
+pub(crate) struct MeshGeometry {
+    pub positions: Vec<Point3<f32>>,
+    // Per vertex normals, or empty for flat shaded faces
+    pub normals: Vec<Vector3<f32>>,
+    // Per vertex texture coordinates, or empty if the mesh has none
+    pub texcoords: Vec<Vector2<f32>>,
+    // Per vertex colors tinting the color of the material, or empty if the mesh has none
+    pub colors: Vec<Vector3<f32>>,
+    pub faces: Vec<[u32; 3]>,
+}
+
+impl MeshGeometry {
+    // Drop attributes which do not have a value for each vertex, and faces which refer to missing vertices
+    pub fn validated(mut self) -> Self {
+        let vertex_count = self.positions.len();
+        if self.normals.len() != vertex_count {
+            self.normals.clear();
+        }
+        if self.texcoords.len() != vertex_count {
+            self.texcoords.clear();
+        }
+        if self.colors.len() != vertex_count {
+            self.colors.clear();
+        }
+        self.faces
+            .retain(|face| face.iter().all(|&index| (index as usize) < vertex_count));
+
+        self
+    }
+
+    fn positions(&self, face: usize) -> [Point3<f64>; 3] {
+        self.faces[face].map(|index| self.positions[index as usize].cast())
+    }
+}
+
+// Triangles of a mesh with one material, built from mesh files rather than deserialized
+#[derive(Debug)]
+pub struct TriangleMesh {
+    geometry: Arc<MeshGeometry>,
+    pub material: MaterialRef,
+}
+
+impl TriangleMesh {
+    pub(crate) fn new(geometry: Arc<MeshGeometry>, material: impl Into<MaterialRef>) -> Self {
+        Self {
+            geometry,
+            material: material.into(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.geometry.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.geometry.faces.is_empty()
+    }
+
+    pub fn flatten_to_world(self, transform: &AnimatedTransform) -> Vec<Box<dyn RaytracingObject>> {
+        let mesh = Arc::new(RaytracingMesh {
+            geometry: self.geometry,
+            world_transform: transform.clone(),
+            material: self.material.into_material(),
+        });
+
+        (0..mesh.geometry.faces.len())
+            .map(|face| -> Box<dyn RaytracingObject> {
+                Box::new(RaytracingMeshTriangle {
+                    mesh: Arc::clone(&mesh),
+                    face: face as u32,
+                })
+            })
+            .collect()
+    }
+}
+
+// Mesh shared by its triangles once it is placed in the world
+#[derive(Debug)]
+struct RaytracingMesh {
+    geometry: Arc<MeshGeometry>,
+    world_transform: AnimatedTransform,
+    material: Material,
+}
+
+// Face of a mesh, referring to the mesh for its vertices, material and transform
+pub struct RaytracingMeshTriangle {
+    mesh: Arc<RaytracingMesh>,
+    face: u32,
+}
+
+impl fmt::Debug for RaytracingMeshTriangle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RaytracingMeshTriangle {{ face: {} }}", self.face)
+    }
+}
+
+impl RaytracingMeshTriangle {
+    fn positions(&self) -> [Point3<f64>; 3] {
+        self.mesh.geometry.positions(self.face as usize)
+    }
+
+    fn get_vertices(&self) -> [usize; 3] {
+        self.mesh.geometry.faces[self.face as usize].map(|index| index as usize)
+    }
+}
+
+impl HasMaterial for RaytracingMeshTriangle {
+    fn get_material(&self) -> &Material {
+        &self.mesh.material
+    }
+}
+
+impl Transformed for RaytracingMeshTriangle {
+    fn get_transform(&self) -> &Transform {
+        self.mesh.world_transform.get_start()
+    }
+
+    fn get_transform_at(&self, time: f64) -> Cow<'_, Transform> {
+        self.mesh.world_transform.at(time)
+    }
+}
+
+impl Intersectable for RaytracingMeshTriangle {
+    fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection<'_>> {
+        let (distance, intermediate) = intersect_triangle(
+            self.positions(),
+            self.get_material().side(),
+            ray,
+            max_distance,
+        )?;
+
+        Some(Intersection::new_with_data(self, distance, intermediate))
+    }
+}
+
+impl Primitive for RaytracingMeshTriangle {
+    fn into_bounded_object(self: Box<Self>) -> ObjectWithBounds {
+        let (min, max) = triangle_bounds(self.positions());
+        let bounding_volume =
+            BoundingVolume::from_bounds_and_motion(min, max, &self.mesh.world_transform);
+
+        ObjectWithBounds::bounded(self, bounding_volume)
+    }
+
+    fn surface_normal(
+        &self,
+        _object_hit_point: &Point3<f64>,
+        intermediate: IntermediateData,
+    ) -> Unit<Vector3<f64>> {
+        let normals = &self.mesh.geometry.normals;
+        if normals.is_empty() {
+            return Triangle::compute_normal(self.positions());
+        }
+
+        let (u, v, w) = barycentric(intermediate);
+        let [n0, n1, n2] = self
+            .get_vertices()
+            .map(|index| normals[index].cast::<f64>());
+
+        Unit::new_normalize(w * n0 + u * n1 + v * n2)
+    }
+
+    fn uv(
+        &self,
+        _object_hit_point: &Point3<f64>,
+        _object_normal: &Unit<Vector3<f64>>,
+        intermediate: IntermediateData,
+    ) -> Vector2<f64> {
+        let texcoords = &self.mesh.geometry.texcoords;
+        if texcoords.is_empty() {
+            return Vector2::zero();
+        }
+
+        let (u, v, w) = barycentric(intermediate);
+        let [uv0, uv1, uv2] = self
+            .get_vertices()
+            .map(|index| texcoords[index].cast::<f64>());
+
+        w * uv0 + u * uv1 + v * uv2
+    }
+
+    fn vertex_color(&self, intermediate: IntermediateData) -> Option<Vector3<f64>> {
+        let colors = &self.mesh.geometry.colors;
+        if colors.is_empty() {
+            return None;
+        }
+
+        let (u, v, w) = barycentric(intermediate);
+        let [c0, c1, c2] = self.get_vertices().map(|index| colors[index].cast::<f64>());
+
+        Some(w * c0 + u * c1 + v * c2)
+    }
+
+    fn get_emitter_shape(&self) -> Option<EmitterShape> {
+        Some(triangle_emitter_shape(
+            self.positions(),
+            self.get_transform(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::PhongMaterial;
+    use crate::ray_intersection::RayType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn it_shares_mesh_buffers_between_triangles() {
+        let geometry = MeshGeometry {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            colors: vec![Vector3::new(1.0, 0.0, 0.0); 4],
+            faces: vec![[0, 1, 2], [0, 2, 3], [0, 3, 4]],
+            ..MeshGeometry::default()
+        }
+        .validated();
+        assert_eq!(geometry.faces.len(), 2);
+
+        let white = Material::Phong(PhongMaterial {
+            color: Vector3::repeat(1.0),
+            ..PhongMaterial::default()
+        });
+        let mesh = TriangleMesh::new(Arc::new(geometry), white);
+        let transform =
+            AnimatedTransform::from(Transform::default().translate(Vector3::new(0.0, 0.0, -1.0)));
+        let triangles = mesh.flatten_to_world(&transform);
+        assert_eq!(triangles.len(), 2);
+
+        let ray = Ray {
+            ray_type: RayType::Primary,
+            origin: Point3::new(0.25, 0.75, 1.0),
+            direction: -Vector3::z(),
+            refractive_index: 1.0,
+            time: 0.0,
+        };
+        let object_ray = ray.transform(triangles[1].get_transform().inverse());
+        let mut intersection = triangles[1].intersect(&object_ray, None).unwrap();
+        assert!((intersection.distance - 2.0).abs() < 1e-9);
+        assert!(triangles[0].intersect(&object_ray, None).is_none());
+
+        intersection.compute_data(&ray);
+        assert_eq!(intersection.get_normal(), Vector3::z_axis());
+        assert!(std::ptr::eq(
+            triangles[0].get_material(),
+            triangles[1].get_material()
+        ));
+        assert_eq!(
+            intersection.get_material_color(&HashMap::new()),
+            Vector3::new(1.0, 0.0, 0.0)
+        );
+    }
+}
@@ -1,6 +1,8 @@
-use crate::core::{AxisDirection, MaterialSide};
-use crate::primitives::RaytracingObject;
+use crate::core::{AxisDirection, Material, MaterialSide, Texture, Transformed};
+use crate::lights::EmitterShape;
+use crate::primitives::{RaytracingInstance, RaytracingObject};
 use nalgebra::{Affine3, Point3, Unit, Vector2, Vector3};
+use std::collections::HashMap;
 
 pub trait Intersectable {
     fn intersect(&self, ray: &Ray, max_distance: Option<f64>) -> Option<Intersection>;
@@ -19,9 +21,14 @@ pub struct Ray {
     pub origin: Point3<f64>,
     pub direction: Vector3<f64>,
     pub refractive_index: f64,
+    // Time the ray is cast at in the motion of animated objects, between 0 and 1
+    pub time: f64,
 }
 
 impl Ray {
+    /// # Panics
+    ///
+    /// Will panic if called on a shadow ray
     pub fn get_depth(&self) -> u8 {
         match self.ray_type {
             RayType::Primary => 0,
@@ -30,6 +37,7 @@ impl Ray {
         }
     }
 
+    #[must_use]
     pub fn transform(&self, transform: Affine3<f64>) -> Ray {
         let origin = transform * self.origin;
         let direction = transform * self.direction;
@@ -39,6 +47,7 @@ impl Ray {
             origin,
             direction,
             refractive_index: self.refractive_index,
+            time: self.time,
         }
     }
 }
@@ -55,11 +64,14 @@ struct IntersectionData {
     hit_point: Point3<f64>,
     normal: Unit<Vector3<f64>>,
     uv: Vector2<f64>,
+    vertex_color: Option<Vector3<f64>>,
 }
 
 #[derive(Debug)]
 pub struct Intersection<'a> {
     pub object: &'a dyn RaytracingObject,
+    // Instance the object was hit through, placing the object of a prototype in the world
+    pub instance: Option<&'a RaytracingInstance>,
     pub distance: f64,
     intermediate: IntermediateData,
     data: Option<IntersectionData>,
@@ -73,6 +85,7 @@ impl<'a> Intersection<'a> {
     ) -> Self {
         Self {
             object,
+            instance: None,
             distance,
             intermediate,
             data: None,
@@ -83,17 +96,48 @@ impl<'a> Intersection<'a> {
         Self::new_with_data(object, distance, IntermediateData::Empty)
     }
 
+    // Material of the hit object, unless the instance it was hit through replaces it
+    pub fn get_material(&self) -> &'a Material {
+        self.instance
+            .and_then(RaytracingInstance::get_material_override)
+            .unwrap_or_else(|| self.object.get_material())
+    }
+
+    // World space surface the hit object is sampled by as a light, which instanced objects are not
+    pub fn get_emitter_shape(&self) -> Option<EmitterShape> {
+        if self.instance.is_some() {
+            return None;
+        }
+
+        self.object.get_emitter_shape()
+    }
+
     pub fn compute_data(&mut self, ray: &Ray) {
-        let transform = self.object.get_transform();
+        let transform = self.object.get_transform_at(ray.time);
+        let instance_transform = self
+            .instance
+            .map(|instance| instance.get_transform_at(ray.time));
         let hit_point = ray.origin + ray.direction * self.distance;
-        let object_hit_point = transform.inverse() * hit_point;
+        // Objects hit through an instance are in the space of its prototype, which the instance places in the world
+        let prototype_hit_point = instance_transform
+            .as_ref()
+            .map_or(hit_point, |instance_transform| {
+                instance_transform.inverse() * hit_point
+            });
+        let object_hit_point = transform.inverse() * prototype_hit_point;
 
         let object_normal = self
             .object
             .surface_normal(&object_hit_point, self.intermediate);
-        let normal =
-            Unit::new_normalize(transform.inverse_transpose() * object_normal.into_inner());
-        let normal = match self.object.get_material().side() {
+        let prototype_normal = transform.inverse_transpose() * object_normal.into_inner();
+        let normal = Unit::new_normalize(
+            instance_transform
+                .as_ref()
+                .map_or(prototype_normal, |instance_transform| {
+                    instance_transform.inverse_transpose() * prototype_normal
+                }),
+        );
+        let normal = match self.get_material().side() {
             MaterialSide::Both => {
                 if normal.dot(&ray.direction) > 0.0 {
                     -normal
@@ -113,6 +157,7 @@ impl<'a> Intersection<'a> {
             hit_point,
             normal,
             uv,
+            vertex_color: self.object.vertex_color(self.intermediate),
         });
     }
 
@@ -131,4 +176,13 @@ impl<'a> Intersection<'a> {
     pub fn get_uv(&self) -> Vector2<f64> {
         self.get_data().uv
     }
+
+    // Color of the material at the hit point, tinted by the vertex colors of the hit object
+    pub fn get_material_color(&self, textures: &HashMap<String, Texture>) -> Vector3<f64> {
+        let data = self.get_data();
+        let color = self.get_material().get_color(data.uv, textures);
+
+        data.vertex_color
+            .map_or(color, |vertex_color| color.component_mul(&vertex_color))
+    }
 }
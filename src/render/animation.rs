@@ -0,0 +1,293 @@
+use super::Scene;
+use crate::RaytraceError;
+use serde::de::Error;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// Keyframed keys which also set the value at the end of the shutter interval, so moving cameras and objects
+// are motion blurred over the length of a frame
+const CAMERA_MOTION_KEYS: [(&str, &str); 2] =
+    [("position", "end_position"), ("target", "end_target")];
+const OBJECT_MOTION_KEYS: [(&str, &str); 1] = [("transform", "end_transform")];
+// Keys of integer fields, which keyframes written as numbers hold at whole values
+const SCENE_INTEGER_KEYS: [&str; 6] = [
+    "width",
+    "height",
+    "max_depth",
+    "samples_per_pixel",
+    "max_reflected_rays",
+    "max_illumination_rays",
+];
+const CAMERA_INTEGER_KEYS: [&str; 1] = ["aperture_blades"];
+
+// Curve followed from a keyframe to the next one
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Easing {
+    #[default]
+    Linear,
+    // Hold the value until the next keyframe
+    Step,
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::Step => 0.0,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Keyframe {
+    frame: f64,
+    value: Value,
+    #[serde(default)]
+    easing: Easing,
+}
+
+#[derive(Debug)]
+struct Keyframes(Vec<Keyframe>);
+
+impl Keyframes {
+    // Keyframes of a value written as `{ "keyframes": [...] }`, or `None` for any other value
+    fn parse(value: &Value) -> serde_json::Result<Option<Self>> {
+        let keyframes = match value {
+            Value::Object(map) if map.len() == 1 && map.contains_key("keyframes") => {
+                &map["keyframes"]
+            }
+            _ => return Ok(None),
+        };
+
+        let mut keyframes = Vec::<Keyframe>::deserialize(keyframes)?;
+        if keyframes.is_empty() {
+            return Err(serde_json::Error::custom("keyframes cannot be empty"));
+        }
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+
+        Ok(Some(Self(keyframes)))
+    }
+
+    fn evaluate(&self, frame: f64) -> Value {
+        let keyframes = &self.0;
+        let next = keyframes.partition_point(|keyframe| keyframe.frame <= frame);
+        if next == 0 {
+            return keyframes[0].value.clone();
+        }
+        if next == keyframes.len() {
+            return keyframes[next - 1].value.clone();
+        }
+
+        let (start, end) = (&keyframes[next - 1], &keyframes[next]);
+        let t = (frame - start.frame) / (end.frame - start.frame);
+
+        interpolate(&start.value, &end.value, start.easing.apply(t))
+    }
+
+    fn evaluate_integer(&self, frame: f64) -> Value {
+        match self.evaluate(frame) {
+            Value::Number(number) if !number.is_i64() && !number.is_u64() => number
+                .as_f64()
+                .map_or(Value::Null, |value| Value::from(value.round() as i64)),
+            value => value,
+        }
+    }
+}
+
+// Interpolate numbers, and arrays or objects of numbers with matching shapes, holding any other value
+fn interpolate(start: &Value, end: &Value, t: f64) -> Value {
+    match (start, end) {
+        (Value::Number(a), Value::Number(b)) => {
+            let value = a.as_f64().unwrap() * (1.0 - t) + b.as_f64().unwrap() * t;
+            // Write whole values as integers so they still deserialize into integer fields
+            if value.fract() == 0.0 && value.abs() < 1e15 {
+                Value::from(value as i64)
+            } else {
+                Number::from_f64(value).map_or(Value::Null, Value::Number)
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            Value::Array(a.iter().zip(b).map(|(a, b)| interpolate(a, b, t)).collect())
+        }
+        (Value::Object(a), Value::Object(b))
+            if a.len() == b.len() && a.keys().all(|key| b.contains_key(key)) =>
+        {
+            Value::Object(
+                a.iter()
+                    .map(|(key, value)| (key.clone(), interpolate(value, &b[key], t)))
+                    .collect(),
+            )
+        }
+        _ if t < 1.0 => start.clone(),
+        _ => end.clone(),
+    }
+}
+
+fn resolve(
+    value: &Value,
+    frame: f64,
+    motion_keys: &[(&str, &str)],
+    integer_keys: &[&str],
+) -> serde_json::Result<Value> {
+    if let Some(keyframes) = Keyframes::parse(value)? {
+        return Ok(keyframes.evaluate(frame));
+    }
+
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .map(|value| resolve(value, frame, motion_keys, &[]))
+            .collect::<serde_json::Result<_>>()
+            .map(Value::Array),
+        Value::Object(map) => {
+            let mut resolved = Map::new();
+            for (key, value) in map {
+                let (child_motion_keys, child_integer_keys): (&[(&str, &str)], &[&str]) =
+                    match key.as_str() {
+                        "camera" => (&CAMERA_MOTION_KEYS, &CAMERA_INTEGER_KEYS),
+                        "objects" | "children" => (&OBJECT_MOTION_KEYS, &[]),
+                        _ => (&[], &[]),
+                    };
+                let resolved_value = match Keyframes::parse(value)? {
+                    Some(keyframes) if integer_keys.contains(&key.as_str()) => {
+                        keyframes.evaluate_integer(frame)
+                    }
+                    _ => resolve(value, frame, child_motion_keys, child_integer_keys)?,
+                };
+                resolved.insert(key.clone(), resolved_value);
+
+                let end_key = motion_keys
+                    .iter()
+                    .find_map(|(motion_key, end_key)| (motion_key == key).then_some(*end_key));
+                if let Some(end_key) = end_key.filter(|end_key| !map.contains_key(*end_key)) {
+                    if let Some(keyframes) = Keyframes::parse(value)? {
+                        resolved.insert(end_key.to_string(), keyframes.evaluate(frame + 1.0));
+                    }
+                }
+            }
+
+            Ok(Value::Object(resolved))
+        }
+        _ => Ok(value.clone()),
+    }
+}
+
+// Scene file whose values may be keyframed as `{ "keyframes": [{ "frame": 1, "value": ... }, ...] }`, building
+// the scene seen at any frame
+#[derive(Debug)]
+pub struct SceneAnimation {
+    description: Value,
+}
+
+impl SceneAnimation {
+    pub fn new(description: Value) -> Self {
+        Self { description }
+    }
+
+    pub fn from_reader(reader: impl Read) -> Result<Self, RaytraceError> {
+        Ok(Self::new(serde_json::from_reader(reader)?))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, RaytraceError> {
+        let file = File::open(path).map_err(|source| RaytraceError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_reader(BufReader::new(file))
+    }
+
+    // Scene at `frame`, with animated cameras and objects moving towards their values at the next frame while
+    // the shutter is open
+    pub fn get_scene(&self, frame: f64) -> Result<Scene, RaytraceError> {
+        let description = resolve(&self.description, frame, &[], &SCENE_INTEGER_KEYS)?;
+        Ok(serde_json::from_value(description)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_resolves_keyframed_values() {
+        let description = json!({
+            "samples_per_pixel": { "keyframes": [{ "frame": 1, "value": 4 }, { "frame": 11, "value": 8 }] },
+            "camera": {
+                "position": { "keyframes": [
+                    { "frame": 1, "value": [0, 0, 5] },
+                    { "frame": 11, "value": [10, 0, 5], "easing": "ease_in_out" },
+                    { "frame": 21, "value": [10, 10, 5] }
+                ] }
+            },
+            "lights": [{
+                "type": "rectangle",
+                "intensity": { "keyframes": [{ "frame": 1, "value": 1 }, { "frame": 11, "value": 2 }] },
+                "width": { "keyframes": [{ "frame": 1, "value": 1 }, { "frame": 11, "value": 2 }] }
+            }],
+            "objects": [{
+                "type": "sphere",
+                "transform": { "keyframes": [
+                    { "frame": 1, "value": [{ "translate": [0, 0, 0] }] },
+                    { "frame": 11, "value": [{ "translate": [0, 5, 0] }] }
+                ] },
+                "material": {
+                    "type": "phong",
+                    "color": { "keyframes": [
+                        { "frame": 1, "value": [1, 0, 0], "easing": "step" },
+                        { "frame": 11, "value": [0, 0, 1] }
+                    ] }
+                }
+            }]
+        });
+        let resolved = |frame| resolve(&description, frame, &[], &SCENE_INTEGER_KEYS).unwrap();
+
+        let start = resolved(1.0);
+        assert_eq!(start["samples_per_pixel"], json!(4));
+        assert_eq!(start["camera"]["position"], json!([0, 0, 5]));
+        assert_eq!(start["camera"]["end_position"], json!([1, 0, 5]));
+        assert!(start["camera"].get("end_target").is_none());
+        assert_eq!(
+            start["objects"][0]["end_transform"],
+            json!([{ "translate": [0, 0.5, 0] }])
+        );
+
+        let middle = resolved(6.0);
+        assert_eq!(middle["samples_per_pixel"], json!(6));
+        assert_eq!(middle["camera"]["position"], json!([5, 0, 5]));
+        assert_eq!(
+            middle["objects"][0]["transform"],
+            json!([{ "translate": [0, 2.5, 0] }])
+        );
+        assert_eq!(middle["objects"][0]["material"]["color"], json!([1, 0, 0]));
+        // Float fields written with integer keyframes still ramp between them
+        assert_eq!(middle["lights"][0]["intensity"], json!(1.5));
+        assert_eq!(middle["lights"][0]["width"], json!(1.5));
+
+        let between = resolved(2.0);
+        assert_eq!(between["samples_per_pixel"], json!(4));
+        assert_eq!(resolved(3.0)["samples_per_pixel"], json!(5));
+        assert_eq!(between["camera"]["position"], json!([1, 0, 5]));
+
+        let eased = resolved(16.0);
+        assert_eq!(eased["camera"]["position"], json!([10, 5, 5]));
+        let eased_y = resolved(13.0)["camera"]["position"][1].as_f64().unwrap();
+        assert!((eased_y - 1.04).abs() < 1e-9);
+        assert_eq!(resolved(30.0)["camera"]["position"], json!([10, 10, 5]));
+        assert_eq!(
+            resolved(11.0)["objects"][0]["material"]["color"],
+            json!([0, 0, 1])
+        );
+
+        let scene = SceneAnimation::new(description).get_scene(6.0).unwrap();
+        assert_eq!(scene.render_options.samples_per_pixel, 6);
+    }
+}
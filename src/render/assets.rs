@@ -0,0 +1,68 @@
+use crate::core::Texture;
+use crate::lights::EnvironmentImage;
+use crate::primitives::{GltfFile, MeshFile};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn get_or_load<K, T, E>(
+    assets: &mut HashMap<K, Arc<T>>,
+    key: K,
+    load: impl FnOnce() -> Result<T, E>,
+) -> Result<Arc<T>, E>
+where
+    K: Eq + Hash,
+{
+    if let Some(asset) = assets.get(&key) {
+        return Ok(Arc::clone(asset));
+    }
+
+    let asset = Arc::new(load()?);
+    assets.insert(key, Arc::clone(&asset));
+
+    Ok(asset)
+}
+
+// Assets read from disk, which scenes built from the same file can share so each asset is only loaded once
+#[derive(Debug, Default)]
+pub struct AssetCache {
+    textures: HashMap<String, Texture>,
+    mesh_files: HashMap<PathBuf, Arc<MeshFile>>,
+    gltf_files: HashMap<PathBuf, Arc<GltfFile>>,
+    environment_images: HashMap<PathBuf, Arc<EnvironmentImage>>,
+}
+
+impl AssetCache {
+    pub fn get_textures(&self) -> &HashMap<String, Texture> {
+        &self.textures
+    }
+
+    pub fn get_textures_mut(&mut self) -> &mut HashMap<String, Texture> {
+        &mut self.textures
+    }
+
+    pub(crate) fn get_or_load_mesh_file<E>(
+        &mut self,
+        path: &Path,
+        load: impl FnOnce() -> Result<MeshFile, E>,
+    ) -> Result<Arc<MeshFile>, E> {
+        get_or_load(&mut self.mesh_files, path.to_path_buf(), load)
+    }
+
+    pub(crate) fn get_or_load_gltf_file<E>(
+        &mut self,
+        path: &Path,
+        load: impl FnOnce() -> Result<GltfFile, E>,
+    ) -> Result<Arc<GltfFile>, E> {
+        get_or_load(&mut self.gltf_files, path.to_path_buf(), load)
+    }
+
+    pub(crate) fn get_or_load_environment_image<E>(
+        &mut self,
+        path: &Path,
+        load: impl FnOnce() -> Result<EnvironmentImage, E>,
+    ) -> Result<Arc<EnvironmentImage>, E> {
+        get_or_load(&mut self.environment_images, path.to_path_buf(), load)
+    }
+}
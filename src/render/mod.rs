@@ -1,59 +1,68 @@
+mod animation;
+mod assets;
+mod output;
+mod passes;
 mod raytracing_scene;
 mod scene;
+mod tone_mapping;
+mod validation;
 
+use crate::integrators::IntegratorType;
 use crate::utils;
 use nalgebra::{Point3, Unit, Vector3};
 use num_traits::Zero;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::ops::AddAssign;
 use std::time::{Duration, Instant};
 
-pub use scene::Scene;
+pub use animation::SceneAnimation;
+pub use assets::AssetCache;
+pub use output::{get_frame_path, save_image, save_passes, to_display_image, ExrPrecision};
+pub use passes::{ObjectIdMap, ObjectIds, RenderPass, RenderPasses};
+pub use raytracing_scene::RaytracingScene;
+pub use scene::{Scene, SceneBuilder};
+pub use tone_mapping::ToneMapping;
+pub use validation::Diagnostic;
+pub(crate) use validation::Validator;
 
-const GAMMA: f64 = 2.2;
-const BIAS: f64 = 1e-10;
+pub(crate) const BIAS: f64 = 1e-10;
 
 pub struct ColorData {
-    color: Vector3<f64>,
-    albedo: Vector3<f64>,
-    normal: Unit<Vector3<f64>>,
+    pub color: Vector3<f64>,
+    pub albedo: Vector3<f64>,
+    pub normal: Unit<Vector3<f64>>,
+    // Part of `color` arriving after at most one bounce
+    pub direct: Vector3<f64>,
 }
 
 impl ColorData {
-    fn new(color: Vector3<f64>, albedo: Vector3<f64>, normal: Unit<Vector3<f64>>) -> Self {
+    pub fn new(color: Vector3<f64>, albedo: Vector3<f64>, normal: Unit<Vector3<f64>>) -> Self {
         Self {
             color,
             albedo,
             normal,
+            direct: Vector3::zero(),
         }
     }
 
-    fn zero() -> Self {
+    pub fn zero() -> Self {
         Self {
             color: Vector3::zero(),
             albedo: Vector3::zero(),
             normal: Vector3::z_axis(),
+            direct: Vector3::zero(),
         }
     }
 
-    fn black() -> Self {
+    pub fn black() -> Self {
         Self::new(Vector3::zero(), Vector3::zero(), Vector3::z_axis())
     }
+}
 
-    fn clamp(mut self) -> Self {
-        self.color = self.color.map(|c| c.clamp(0.0, 1.0));
-        self.albedo = self.albedo.map(|c| c.clamp(0.0, 1.0));
-        self
-    }
-
-    fn compute_color(&self) -> Vector3<f64> {
-        self.color.map(|c| c.clamp(0.0, 1.0))
-    }
-
-    fn gamma_correct(mut self) -> Self {
-        self.color = utils::gamma_correct(self.compute_color(), GAMMA);
-        self
-    }
+// Expose, tone map and sRGB encode linear radiance for 8-bit images and the render window
+pub fn to_display_color(color: Vector3<f64>, render_options: &RenderOptions) -> Vector3<f64> {
+    let exposed = color * 2f64.powf(render_options.exposure);
+    utils::srgb_oetf(render_options.tone_mapping.apply(exposed))
 }
 
 #[derive(Copy, Clone)]
@@ -110,29 +119,165 @@ impl AddAssign for CastStats {
     }
 }
 
-#[derive(Debug, Deserialize)]
+// Mapping from positions on the screen to the directions of camera rays
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Projection {
+    // Pinhole or thin lens projection with the camera's fov across the longer side of the image
+    #[default]
+    Perspective,
+    // Parallel rays covering `height` world units vertically
+    Orthographic {
+        #[serde(default = "Projection::default_orthographic_height")]
+        height: f64,
+    },
+    // Equidistant fisheye covering `fov` degrees over a circle filling the shorter side of the image
+    Fisheye {
+        #[serde(default = "Projection::default_fisheye_fov")]
+        fov: f64,
+    },
+    // Full 360° by 180° panorama
+    Equirectangular,
+}
+
+impl Projection {
+    fn default_orthographic_height() -> f64 {
+        2.0
+    }
+
+    fn default_fisheye_fov() -> f64 {
+        180.0
+    }
+}
+
+// Arrangement of the views of the left and right eyes in a stereo image
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StereoLayout {
+    #[default]
+    None,
+    // Left eye in the left half of the image
+    SideBySide,
+    // Left eye in the top half of the image
+    TopBottom,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Camera {
     pub fov: f64,
+    pub projection: Projection,
     pub position: Point3<f64>,
     pub target: Point3<f64>,
     pub up: Unit<Vector3<f64>>,
+    // Diameter of the lens, with 0 rendering everything in focus through a pinhole
+    pub aperture: f64,
+    // Relative aperture overriding `aperture`, for a 36mm wide sensor behind a lens with the camera's fov and a
+    // scene measured in meters
+    pub f_stop: Option<f64>,
+    // Distance along the viewing axis of the plane in focus, defaulting to the distance to `target`
+    pub focus_distance: Option<f64>,
+    // Number of straight aperture blades shaping out of focus highlights, with a round aperture below 3
+    pub aperture_blades: u8,
+    // Rotation of the aperture blades in degrees
+    pub aperture_rotation: f64,
+    pub stereo: StereoLayout,
+    // Distance between the eyes of a stereo camera
+    pub interocular_distance: f64,
+    // Distance along the viewing axis at which both eyes see the same point, defaulting to the focus distance
+    pub convergence_distance: Option<f64>,
+    // Position and target at the end of the motion of a camera which moves while the shutter is open
+    pub end_position: Option<Point3<f64>>,
+    pub end_target: Option<Point3<f64>>,
+    // Interval the shutter is open for, between the start (0) and the end (1) of the motion of animated objects
+    pub shutter_open: f64,
+    pub shutter_close: f64,
 }
 
 impl Default for Camera {
     fn default() -> Self {
         Self {
             fov: 65.0,
+            projection: Projection::default(),
             position: Point3::from([0.0, 0.0, 1.0]),
             target: Point3::origin(),
             up: Vector3::y_axis(),
+            aperture: 0.0,
+            f_stop: None,
+            focus_distance: None,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            stereo: StereoLayout::default(),
+            interocular_distance: 0.064,
+            convergence_distance: None,
+            end_position: None,
+            end_target: None,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl Camera {
+    fn validate(&self, validator: &mut Validator) {
+        validator.check(
+            "fov",
+            self.fov > 0.0 && self.fov < 180.0,
+            "must be between 0 and 180 degrees, exclusive",
+        );
+        match self.projection {
+            Projection::Orthographic { height } => validator.field("projection", |validator| {
+                validator.check_positive("height", height);
+            }),
+            Projection::Fisheye { fov } => validator.field("projection", |validator| {
+                validator.check(
+                    "fov",
+                    fov > 0.0 && fov <= 360.0,
+                    "must be positive and at most 360 degrees",
+                );
+            }),
+            Projection::Perspective | Projection::Equirectangular => {}
+        }
+
+        let view = self.target - self.position;
+        validator.check(
+            "target",
+            view.magnitude() > f64::EPSILON,
+            "must differ from the camera's position",
+        );
+        validator.check_direction("up", &self.up);
+        validator.check(
+            "up",
+            view.cross(&self.up).magnitude() > f64::EPSILON || view.magnitude() <= f64::EPSILON,
+            "must not be parallel to the viewing direction",
+        );
+
+        validator.check_non_negative("aperture", self.aperture);
+        if let Some(f_stop) = self.f_stop {
+            validator.check_positive("f_stop", f_stop);
+        }
+        if let Some(focus_distance) = self.focus_distance {
+            validator.check_positive("focus_distance", focus_distance);
+        }
+        validator.check_finite("aperture_rotation", self.aperture_rotation);
+        validator.check_non_negative("interocular_distance", self.interocular_distance);
+        if let Some(convergence_distance) = self.convergence_distance {
+            validator.check_positive("convergence_distance", convergence_distance);
+        }
+        validator.check_range("shutter_open", self.shutter_open, 0.0..=1.0);
+        validator.check_range("shutter_close", self.shutter_close, 0.0..=1.0);
+        validator.check(
+            "shutter_close",
+            self.shutter_close >= self.shutter_open,
+            "must not be before shutter_open",
+        );
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct RenderOptions {
+    pub integrator: IntegratorType,
     pub width: u32,
     pub height: u32,
     pub max_depth: u8,
@@ -141,11 +286,18 @@ pub struct RenderOptions {
     pub max_illumination_rays: u16,
     pub max_occlusion_distance: f64,
     pub skip_denoise_pass: bool,
+    // Exposure adjustment in stops applied before tone mapping
+    pub exposure: f64,
+    pub tone_mapping: ToneMapping,
+    pub exr_precision: ExrPrecision,
+    // Images to render besides the beauty pass
+    pub passes: Vec<RenderPass>,
 }
 
 impl Default for RenderOptions {
     fn default() -> Self {
         Self {
+            integrator: IntegratorType::default(),
             width: 100,
             height: 100,
             max_depth: 3,
@@ -154,17 +306,37 @@ impl Default for RenderOptions {
             max_illumination_rays: 16,
             max_occlusion_distance: 1.0,
             skip_denoise_pass: false,
+            exposure: 0.0,
+            tone_mapping: ToneMapping::default(),
+            exr_precision: ExrPrecision::default(),
+            passes: vec![RenderPass::Beauty],
         }
     }
 }
 
+impl RenderOptions {
+    fn validate(&self, validator: &mut Validator) {
+        validator.check("width", self.width > 0, "must be positive");
+        validator.check("height", self.height > 0, "must be positive");
+        validator.check(
+            "samples_per_pixel",
+            self.samples_per_pixel > 0,
+            "must be positive",
+        );
+        validator.check_non_negative("max_occlusion_distance", self.max_occlusion_distance);
+        validator.check_finite("exposure", self.exposure);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::core::{Material, PhongMaterial, Transform};
-    use crate::lights::{AmbientLight, Light, PointLight};
-    use crate::primitives::{Cube, Object3D};
+    use crate::lights::{AmbientLight, Light, PointLight, RectangleLight};
+    use crate::primitives::{Cube, Instance, Object3D};
+    use crate::RaytraceError;
     use serde_json::json;
+    use std::path::Path;
 
     #[test]
     fn it_builds_a_raytracing_scene_from_an_empty_scene_json() {
@@ -172,7 +344,7 @@ mod test {
         let scene: Result<Scene, serde_json::error::Error> = serde_json::from_value(scene_json);
         assert!(scene.is_ok(), "failed to deserialize scene");
 
-        scene.unwrap().build_raytracing_scene();
+        scene.unwrap().build_raytracing_scene().unwrap();
     }
 
     #[test]
@@ -203,13 +375,178 @@ mod test {
         let scene: Result<Scene, serde_json::error::Error> = serde_json::from_value(scene_json);
         assert!(scene.is_ok(), "failed to deserialize scene");
 
-        scene.unwrap().build_raytracing_scene();
+        scene.unwrap().build_raytracing_scene().unwrap();
+    }
+
+    #[test]
+    fn it_builds_a_raytracing_scene_with_a_selected_integrator() {
+        let scene_json = json!({ "integrator": "path" });
+        let scene: Result<Scene, serde_json::error::Error> = serde_json::from_value(scene_json);
+        assert!(scene.is_ok(), "failed to deserialize scene");
+
+        let scene = scene.unwrap();
+        assert_eq!(scene.render_options.integrator, IntegratorType::Path);
+        scene.build_raytracing_scene().unwrap();
+    }
+
+    #[test]
+    fn it_renders_visible_area_lights() {
+        let scene_json = json!({
+          "lights": [
+            { "type": "rectangle", "width": 0.5, "height": 0.5, "transform": [{ "rotate": [[1, 0, 0], 90] }] },
+            { "type": "disk", "radius": 0.5, "two_sided": true, "transform": [{ "translate": [0, -2, 0] }] },
+            { "type": "sphere", "radius": 0.5, "transform": [{ "translate": [0, 2, 0] }] }
+          ]
+        });
+        let scene: Result<Scene, serde_json::error::Error> = serde_json::from_value(scene_json);
+        assert!(scene.is_ok(), "failed to deserialize scene");
+        scene.unwrap().build_raytracing_scene().unwrap();
+
+        for integrator in [IntegratorType::Whitted, IntegratorType::Path] {
+            let mut scene = Scene::new(
+                RenderOptions {
+                    integrator,
+                    width: 9,
+                    height: 9,
+                    ..RenderOptions::default()
+                },
+                Camera::default(),
+            );
+            scene.add_light(Light::Rectangle(Box::new(RectangleLight::new(
+                Vector3::from([1.0, 1.0, 1.0]),
+                1.0,
+                0.5,
+                0.5,
+                false,
+                Transform::identity().rotate(Vector3::x_axis(), 90.0),
+            ))));
+            let scene = scene.build_raytracing_scene().unwrap();
+
+            let (center, _) = scene.screen_raycast(4, 4);
+            let (corner, _) = scene.screen_raycast(0, 0);
+            assert_eq!(center.color, Vector3::from([1.0, 1.0, 1.0]));
+            assert_eq!(corner.color, Vector3::zeros());
+        }
+    }
+
+    #[test]
+    fn it_lights_scenes_with_emissive_primitives() {
+        for integrator in [IntegratorType::Whitted, IntegratorType::Path] {
+            let scene_json = json!({
+              "width": 9,
+              "height": 9,
+              "samples_per_pixel": 64,
+              "max_illumination_rays": 0,
+              "objects": [
+                { "type": "plane", "normal": [0, 0, 1], "material": { "type": "phong", "color": [1, 1, 1] } },
+                {
+                  "type": "sphere",
+                  "radius": 0.1,
+                  "transform": [{ "translate": [0, 0, 0.5] }],
+                  "material": { "type": "phong", "emissive": [10, 10, 10] }
+                }
+              ]
+            });
+            let mut scene: Scene = serde_json::from_value(scene_json).unwrap();
+            scene.render_options.integrator = integrator;
+            let scene = scene.build_raytracing_scene().unwrap();
+
+            assert!(scene
+                .get_lights()
+                .iter()
+                .any(|light| matches!(light, Light::Emissive(_))));
+
+            let (corner, _) = scene.screen_raycast(0, 0);
+            assert!(corner.color.min() > 0.0, "plane is not lit by the sphere");
+        }
+    }
+
+    #[test]
+    fn it_reports_scenes_which_fail_to_load_or_build() {
+        let textured_json = json!({
+          "objects": [
+            { "type": "sphere", "material": { "type": "phong", "texture": "missing.png" } }
+          ]
+        });
+
+        let scene: Scene = serde_json::from_value(textured_json.clone()).unwrap();
+        assert!(matches!(
+            scene.build_raytracing_scene(),
+            Err(RaytraceError::MissingTexture(texture)) if texture == "missing.png"
+        ));
+
+        let mut scene: Scene = serde_json::from_value(textured_json).unwrap();
+        assert!(matches!(
+            scene.load_assets(Path::new("")),
+            Err(RaytraceError::Texture { .. })
+        ));
+
+        let mut scene: Scene = serde_json::from_value(
+            json!({ "objects": [{ "type": "mesh", "file": "missing.obj" }] }),
+        )
+        .unwrap();
+        assert!(matches!(
+            scene.load_assets(Path::new("")),
+            Err(RaytraceError::Mesh { .. })
+        ));
+
+        let mut scene = Scene::new(RenderOptions::default(), Camera::default());
+        scene.load_assets(Path::new("")).unwrap();
+        assert!(matches!(
+            scene.load_assets(Path::new("")),
+            Err(RaytraceError::AssetsLoaded)
+        ));
+        assert!(matches!(
+            scene.add_object(Object3D::Cube(Box::default())),
+            Err(RaytraceError::AssetsLoaded)
+        ));
+    }
+
+    #[test]
+    fn it_validates_scenes() {
+        let scene = Scene::new(RenderOptions::default(), Camera::default());
+        assert!(scene.validate(Path::new("")).is_empty());
+
+        let scene: Scene = serde_json::from_value(json!({
+          "height": 0,
+          "camera": { "fov": 0, "up": [0, 0, 1] },
+          "lights": [{ "type": "rectangle", "width": -1 }],
+          "objects": [
+            { "type": "cube", "material": { "type": "physical", "roughness": 2 } },
+            {
+              "type": "group",
+              "children": [
+                { "type": "sphere", "radius": -1, "end_transform": [{ "scale": [0, 1, 1] }] },
+                { "type": "mesh", "file": "missing.obj" }
+              ]
+            }
+          ]
+        }))
+        .unwrap();
+        let diagnostics: Vec<String> = scene
+            .validate(Path::new(""))
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "height: must be positive",
+                "camera.fov: must be between 0 and 180 degrees, exclusive",
+                "camera.up: must not be parallel to the viewing direction",
+                "lights[0].width: must be positive",
+                "objects[0].material.roughness: must be between 0 and 1",
+                "objects[1].children[0].radius: must be positive",
+                "objects[1].children[0].end_transform: must be invertible",
+                "objects[1].children[1].file: file \"missing.obj\" does not exist",
+            ]
+        );
     }
 
     #[test]
     fn it_builds_a_raytracing_scene_from_an_empty_scene() {
         let scene = Scene::new(RenderOptions::default(), Camera::default());
-        scene.build_raytracing_scene();
+        scene.build_raytracing_scene().unwrap();
     }
 
     #[test]
@@ -233,17 +570,141 @@ mod test {
             Transform::identity().translate(Vector3::from([-8.0, 3.0, 0.0])),
         ))));
 
-        scene.add_object(Object3D::Cube(Box::new(Cube::new(
-            1.0,
-            Transform::identity()
-                .rotate(Vector3::y_axis(), 30.0)
-                .translate(Vector3::from([0.0, 2.0, 0.0])),
-            Material::Phong(PhongMaterial {
-                color: Vector3::from([1.0, 0.1, 0.1]),
-                ..PhongMaterial::default()
-            }),
-        ))));
+        scene
+            .add_object(Object3D::Cube(Box::new(Cube::new(
+                1.0,
+                Transform::identity()
+                    .rotate(Vector3::y_axis(), 30.0)
+                    .translate(Vector3::from([0.0, 2.0, 0.0])),
+                Material::Phong(PhongMaterial {
+                    color: Vector3::from([1.0, 0.1, 0.1]),
+                    ..PhongMaterial::default()
+                }),
+            ))))
+            .unwrap();
+
+        scene.build_raytracing_scene().unwrap();
+    }
+
+    #[test]
+    fn it_round_trips_scenes_through_json() {
+        let scene = Scene::builder()
+            .size(200, 100)
+            .samples_per_pixel(8)
+            .camera(Camera {
+                position: Point3::from([0.0, 1.0, 5.0]),
+                ..Camera::default()
+            })
+            .light(AmbientLight::new(Vector3::from([0.01, 0.01, 0.01])))
+            .light(PointLight::new(
+                Vector3::from([0.5, 0.5, 0.5]),
+                1.0,
+                Transform::identity().translate(Vector3::from([-8.0, 3.0, 0.0])),
+            ))
+            .object(Cube::new(
+                1.0,
+                Transform::identity()
+                    .rotate(Vector3::y_axis(), 30.0)
+                    .translate(Vector3::from([0.0, 2.0, 0.0])),
+                Material::Phong(PhongMaterial {
+                    color: Vector3::from([1.0, 0.1, 0.1]),
+                    ..PhongMaterial::default()
+                }),
+            ))
+            .build();
+
+        let value = serde_json::to_value(&scene).unwrap();
+        assert_eq!(value["width"], json!(200));
+        assert_eq!(value["lights"][1]["type"], json!("point"));
+        assert_eq!(
+            value["objects"][0]["transform"],
+            json!([
+                { "rotate": [[0.0, 1.0, 0.0], 30.0] },
+                { "translate": [0.0, 2.0, 0.0] }
+            ])
+        );
 
-        scene.build_raytracing_scene();
+        let scene: Scene = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(scene.render_options.samples_per_pixel, 8);
+        assert_eq!(serde_json::to_value(&scene).unwrap(), value);
+        scene.build_raytracing_scene().unwrap();
+    }
+
+    #[test]
+    fn it_resolves_named_materials_and_includes() {
+        let mut scene: Scene = serde_json::from_value(json!({
+          "include": ["includes/cornell_box_room.json"],
+          "materials": { "white": { "type": "phong", "color": [0.5, 0.5, 0.5] } },
+          "objects": [{ "type": "sphere", "material": "red" }]
+        }))
+        .unwrap();
+        scene.resolve_includes(Path::new("scenes")).unwrap();
+        assert!(scene.validate(Path::new("scenes")).is_empty());
+
+        let value = serde_json::to_value(&scene).unwrap();
+        assert_eq!(value["include"], json!([]));
+        assert_eq!(value["objects"].as_array().unwrap().len(), 7);
+        assert_eq!(value["objects"][2]["material"], json!("white"));
+        // Materials of the including scene take precedence over included ones
+        assert_eq!(value["materials"]["white"]["color"], json!([0.5, 0.5, 0.5]));
+        assert_eq!(
+            value["materials"]["red"]["color"],
+            json!([0.57, 0.025, 0.025])
+        );
+        scene.build_raytracing_scene().unwrap();
+
+        let scene: Scene = serde_json::from_value(json!({
+          "objects": [{ "type": "group", "children": [{ "type": "cube", "material": "gold" }] }]
+        }))
+        .unwrap();
+        assert_eq!(
+            scene.validate(Path::new(""))[0].to_string(),
+            "objects[0].children[0].material: material \"gold\" is not defined"
+        );
+        assert!(matches!(
+            scene.build_raytracing_scene(),
+            Err(RaytraceError::UnknownMaterial(name)) if name == "gold"
+        ));
+    }
+
+    #[test]
+    fn it_validates_instances_of_prototypes() {
+        let scene: Scene = serde_json::from_value(json!({
+          "prototypes": {
+            "box": { "type": "cube" },
+            "boxes": { "type": "group", "children": [{ "type": "instance", "prototype": "box" }] }
+          },
+          "objects": [
+            { "type": "instance", "prototype": "box" },
+            { "type": "instance", "prototype": "tree", "material": "bark" }
+          ]
+        }))
+        .unwrap();
+        let diagnostics: Vec<String> = scene
+            .validate(Path::new(""))
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "prototypes.boxes: prototypes cannot contain instances",
+                "objects[1].prototype: prototype \"tree\" is not defined",
+                "objects[1].material: material \"bark\" is not defined",
+            ]
+        );
+        assert!(matches!(
+            scene.build_raytracing_scene(),
+            Err(RaytraceError::NestedInstance(name)) if name == "boxes"
+        ));
+
+        let scene = Scene::builder()
+            .prototype("box", Cube::default())
+            .object(Instance::new("tree", Transform::default()))
+            .build();
+        assert!(matches!(
+            scene.build_raytracing_scene(),
+            Err(RaytraceError::UnknownPrototype(name)) if name == "tree"
+        ));
     }
 }
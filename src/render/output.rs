@@ -0,0 +1,403 @@
+use super::passes::{get_id_color, BACKGROUND_DEPTH};
+use super::{to_display_color, RenderOptions, RenderPass, RenderPasses};
+use crate::utils;
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec,
+    WritableImage,
+};
+use image::codecs::hdr::HdrEncoder;
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ImageError, ImageFormat, ImageResult, Rgb32FImage, RgbaImage};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Channel type written to OpenEXR files
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExrPrecision {
+    Half,
+    #[default]
+    Float,
+}
+
+// Convert a linear framebuffer to 8-bit color with the exposure and tone mapping of the render options
+pub fn to_display_image(image: &Rgb32FImage, render_options: &RenderOptions) -> RgbaImage {
+    map_to_display_image(image, |color| to_display_color(color, render_options))
+}
+
+// Visualize a render pass as 8-bit color, remapping data passes into the displayable range
+fn to_pass_display_image(
+    pass: RenderPass,
+    image: &Rgb32FImage,
+    render_options: &RenderOptions,
+) -> RgbaImage {
+    match pass {
+        RenderPass::Beauty | RenderPass::Direct | RenderPass::Indirect => {
+            to_display_image(image, render_options)
+        }
+        RenderPass::Albedo => map_to_display_image(image, |color| {
+            utils::srgb_oetf(color.map(|c| c.clamp(0.0, 1.0)))
+        }),
+        RenderPass::Normal | RenderPass::CameraNormal => {
+            map_to_display_image(image, |normal| normal.map(|c| c * 0.5 + 0.5))
+        }
+        RenderPass::Uv => map_to_display_image(image, |uv| {
+            Vector3::new(uv.x.rem_euclid(1.0), uv.y.rem_euclid(1.0), 0.0)
+        }),
+        // Inverse depth relative to the closest surface in view, which stays readable with distant surfaces
+        RenderPass::Depth => {
+            let min_depth = image
+                .pixels()
+                .map(|pixel| pixel.0[0])
+                .filter(|&depth| depth > 0.0)
+                .fold(BACKGROUND_DEPTH, f32::min);
+            map_to_display_image(image, |depth| {
+                if depth.x >= f64::from(BACKGROUND_DEPTH) || depth.x <= 0.0 {
+                    Vector3::zeros()
+                } else {
+                    Vector3::repeat(f64::from(min_depth) / depth.x)
+                }
+            })
+        }
+        RenderPass::PrimitiveId | RenderPass::ObjectId | RenderPass::MaterialId => {
+            map_to_display_image(image, |id| get_id_color(id.x as f32))
+        }
+    }
+}
+
+fn map_to_display_image<F>(image: &Rgb32FImage, to_display: F) -> RgbaImage
+where
+    F: Fn(Vector3<f64>) -> Vector3<f64>,
+{
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let color = to_display(Vector3::from(image.get_pixel(x, y).0.map(f64::from)))
+            .map(|c| c.clamp(0.0, 1.0));
+        image::Rgba([
+            (color.x * 255.0) as u8,
+            (color.y * 255.0) as u8,
+            (color.z * 255.0) as u8,
+            255,
+        ])
+    })
+}
+
+fn get_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+// Write a linear framebuffer to `path`, keeping the unclamped values for .exr, .hdr and .pfm files and
+// converting to an 8-bit display image for any other format
+pub fn save_image(
+    image: &Rgb32FImage,
+    path: &Path,
+    render_options: &RenderOptions,
+) -> ImageResult<()> {
+    match get_extension(path).as_deref() {
+        Some("exr") => write_exr(image, path, render_options.exr_precision),
+        Some("hdr") => {
+            let pixels: Vec<image::Rgb<f32>> = image.pixels().copied().collect();
+            HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(
+                &pixels,
+                image.width() as usize,
+                image.height() as usize,
+            )
+        }
+        Some("pfm") => write_pfm(image, path),
+        _ => to_display_image(image, render_options).save(path),
+    }
+}
+
+fn write_exr(image: &Rgb32FImage, path: &Path, exr_precision: ExrPrecision) -> ImageResult<()> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let get_pixel = |x: usize, y: usize| image.get_pixel(x as u32, y as u32).0;
+
+    match exr_precision {
+        ExrPrecision::Half => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+            get_pixel(x, y).map(f16::from_f32).into()
+        }),
+        ExrPrecision::Float => {
+            exr::prelude::write_rgb_file(path, width, height, |x, y| get_pixel(x, y).into())
+        }
+    }
+    .map_err(to_exr_error)
+}
+
+fn to_exr_error(err: exr::error::Error) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::OpenExr),
+        err,
+    ))
+}
+
+// Path of a frame in an image sequence, replacing a run of `#` in the file name with the zero padded frame
+// number or otherwise writing to `<stem>.<frame>.<extension>`
+pub fn get_frame_path(path: &Path, frame: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .unwrap_or("");
+
+    let file_name = if let Some(end) = file_name.rfind('#') {
+        let start = file_name[..end].trim_end_matches('#').len();
+        let width = end + 1 - start;
+        format!(
+            "{}{frame:0width$}{}",
+            &file_name[..start],
+            &file_name[end + 1..]
+        )
+    } else {
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => format!("{stem}.{frame:04}.{extension}"),
+            None => format!("{stem}.{frame:04}"),
+        }
+    };
+
+    path.with_file_name(file_name)
+}
+
+// Write every render pass. OpenEXR files hold all passes as channels of a single image, named after the
+// pass, while other formats write the beauty pass to `path` and every other pass to
+// `<stem>.<pass>.<extension>` next to it
+pub fn save_passes(
+    passes: &RenderPasses,
+    path: &Path,
+    render_options: &RenderOptions,
+) -> ImageResult<()> {
+    let extension = get_extension(path);
+    if extension.as_deref() == Some("exr") {
+        return write_exr_passes(passes, path, render_options.exr_precision);
+    }
+
+    for (pass, image) in passes.iter() {
+        if pass == RenderPass::Beauty {
+            save_image(image, path, render_options)?;
+            continue;
+        }
+
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => format!("{stem}.{}.{extension}", pass.get_name()),
+            None => format!("{stem}.{}", pass.get_name()),
+        };
+        let pass_path = path.with_file_name(file_name);
+
+        match extension.as_deref() {
+            Some("hdr" | "pfm") => save_image(image, &pass_path, render_options)?,
+            _ => to_pass_display_image(pass, image, render_options).save(&pass_path)?,
+        }
+    }
+
+    Ok(())
+}
+
+// Names of the channels holding a pass and whether they may be stored at half precision, following the
+// usual names of compositing applications
+fn get_exr_channels(pass: RenderPass) -> (Vec<String>, bool) {
+    let name = pass.get_name();
+    let with_suffixes = |suffixes: &[&str]| {
+        suffixes
+            .iter()
+            .map(|suffix| format!("{name}.{suffix}"))
+            .collect()
+    };
+
+    match pass {
+        RenderPass::Beauty => (vec!["R".into(), "G".into(), "B".into()], true),
+        RenderPass::Depth => (vec!["Z".into()], false),
+        RenderPass::Normal | RenderPass::CameraNormal => (with_suffixes(&["X", "Y", "Z"]), true),
+        RenderPass::Albedo | RenderPass::Direct | RenderPass::Indirect => {
+            (with_suffixes(&["R", "G", "B"]), true)
+        }
+        RenderPass::Uv => (with_suffixes(&["U", "V"]), false),
+        RenderPass::PrimitiveId | RenderPass::ObjectId | RenderPass::MaterialId => {
+            (vec![name.into()], false)
+        }
+    }
+}
+
+fn write_exr_passes(
+    passes: &RenderPasses,
+    path: &Path,
+    exr_precision: ExrPrecision,
+) -> ImageResult<()> {
+    let mut channels = SmallVec::new();
+    for (pass, image) in passes.iter() {
+        let (names, allows_half) = get_exr_channels(pass);
+
+        for (component, name) in names.into_iter().enumerate() {
+            let samples: Vec<f32> = image.pixels().map(|pixel| pixel.0[component]).collect();
+            let samples = if allows_half && exr_precision == ExrPrecision::Half {
+                FlatSamples::F16(samples.into_iter().map(f16::from_f32).collect())
+            } else {
+                FlatSamples::F32(samples)
+            };
+            channels.push(AnyChannel::new(name.as_str(), samples));
+        }
+    }
+
+    let (width, height) = passes.get_dimensions();
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(to_exr_error)
+}
+
+// Portable float map with little-endian samples, stored from the bottom row up
+fn write_pfm(image: &Rgb32FImage, path: &Path) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+    for row in image.rows().rev() {
+        for sample in row.flat_map(|pixel| pixel.0) {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::ColorData;
+    use image::codecs::hdr::HdrDecoder;
+    use image::Rgb;
+    use nalgebra::Matrix3;
+    use std::env;
+    use std::fs;
+    use std::io::BufReader;
+
+    fn build_test_image() -> Rgb32FImage {
+        Rgb32FImage::from_fn(3, 2, |x, y| Rgb([x as f32 * 4.0, y as f32, 0.25]))
+    }
+
+    #[test]
+    fn it_keeps_unclamped_values_in_high_dynamic_range_formats() {
+        let image = build_test_image();
+
+        for exr_precision in [ExrPrecision::Float, ExrPrecision::Half] {
+            let path =
+                env::temp_dir().join(format!("raytrace_output_test_{:?}.exr", exr_precision));
+            let render_options = RenderOptions {
+                exr_precision,
+                ..RenderOptions::default()
+            };
+            save_image(&image, &path, &render_options).unwrap();
+            let output = image::open(&path).unwrap().into_rgb32f();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(output, image);
+        }
+
+        let path = env::temp_dir().join("raytrace_output_test.hdr");
+        save_image(&image, &path, &RenderOptions::default()).unwrap();
+        let decoder = HdrDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let output = decoder.read_image_hdr().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(output.len(), 6);
+        let bright = output[5].0;
+        assert!((bright[0] - 8.0).abs() < 0.1, "lost values above 1");
+    }
+
+    #[test]
+    fn it_writes_render_passes_as_channels_or_separate_files() {
+        let color_data = ColorData::new(
+            Vector3::new(2.0, 1.0, 0.5),
+            Vector3::repeat(0.5),
+            Vector3::y_axis(),
+        );
+        let passes = RenderPasses::new(
+            &[RenderPass::Depth, RenderPass::Normal],
+            (1, 1),
+            &[color_data],
+            &[None],
+            &Matrix3::identity(),
+        );
+
+        let path = env::temp_dir().join("raytrace_passes_test.exr");
+        save_passes(&passes, &path, &RenderOptions::default()).unwrap();
+        let image = exr::prelude::read_all_flat_layers_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let channel_names: Vec<String> = image.layer_data[0]
+            .channel_data
+            .list
+            .iter()
+            .map(|channel| channel.name.to_string())
+            .collect();
+        assert_eq!(
+            channel_names,
+            ["B", "G", "R", "Z", "normal.X", "normal.Y", "normal.Z"]
+        );
+
+        let path = env::temp_dir().join("raytrace_passes_test.png");
+        save_passes(&passes, &path, &RenderOptions::default()).unwrap();
+        for file_name in [
+            "raytrace_passes_test.png",
+            "raytrace_passes_test.depth.png",
+            "raytrace_passes_test.normal.png",
+        ] {
+            let pass_path = env::temp_dir().join(file_name);
+            assert!(pass_path.exists(), "{file_name} was not written");
+            fs::remove_file(&pass_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_numbers_the_frames_of_an_image_sequence() {
+        assert_eq!(
+            get_frame_path(Path::new("renders/frame.png"), 7),
+            Path::new("renders/frame.0007.png")
+        );
+        assert_eq!(
+            get_frame_path(Path::new("renders/shot_###.exr"), 42),
+            Path::new("renders/shot_042.exr")
+        );
+        assert_eq!(
+            get_frame_path(Path::new("frame"), 12_345),
+            Path::new("frame.12345")
+        );
+    }
+
+    #[test]
+    fn it_writes_portable_float_maps_bottom_up() {
+        let image = build_test_image();
+        let path = env::temp_dir().join("raytrace_output_test.pfm");
+        save_image(&image, &path, &RenderOptions::default()).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(data.len(), header.len() + 3 * 2 * 3 * 4);
+
+        // The first stored pixel is the bottom left one
+        let first_sample =
+            f32::from_le_bytes(data[header.len()..header.len() + 4].try_into().unwrap());
+        let second_sample =
+            f32::from_le_bytes(data[header.len() + 4..header.len() + 8].try_into().unwrap());
+        assert_eq!(first_sample, 0.0);
+        assert_eq!(second_sample, 1.0);
+    }
+}
@@ -0,0 +1,353 @@
+use super::ColorData;
+use crate::core::Material;
+use crate::primitives::RaytracingObject;
+use crate::ray_intersection::Intersection;
+use image::Rgb32FImage;
+use nalgebra::{Matrix3, Unit, Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// Depth written for pixels which do not see any surface
+pub const BACKGROUND_DEPTH: f32 = 1e10;
+// Identifier written for pixels which do not see any surface
+pub const BACKGROUND_ID: f32 = -1.0;
+
+// Image written by a render, besides the beauty pass holding the final radiance
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderPass {
+    Beauty,
+    // Distance along the camera's viewing axis
+    Depth,
+    // World space surface normal
+    Normal,
+    // Surface normal in camera space, with -z pointing away from the camera
+    CameraNormal,
+    Albedo,
+    Uv,
+    // Index of the primitive after the scene is flattened
+    PrimitiveId,
+    // Index of the top-level object in the scene
+    ObjectId,
+    // Index of the distinct material
+    MaterialId,
+    // Light arriving at the eye after at most one bounce
+    Direct,
+    Indirect,
+}
+
+impl FromStr for RenderPass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beauty" => Ok(RenderPass::Beauty),
+            "depth" => Ok(RenderPass::Depth),
+            "normal" => Ok(RenderPass::Normal),
+            "camera_normal" => Ok(RenderPass::CameraNormal),
+            "albedo" => Ok(RenderPass::Albedo),
+            "uv" => Ok(RenderPass::Uv),
+            "primitive_id" => Ok(RenderPass::PrimitiveId),
+            "object_id" => Ok(RenderPass::ObjectId),
+            "material_id" => Ok(RenderPass::MaterialId),
+            "direct" => Ok(RenderPass::Direct),
+            "indirect" => Ok(RenderPass::Indirect),
+            _ => Err(format!("unknown render pass \"{s}\"")),
+        }
+    }
+}
+
+impl RenderPass {
+    pub const ALL: [RenderPass; 11] = [
+        RenderPass::Beauty,
+        RenderPass::Depth,
+        RenderPass::Normal,
+        RenderPass::CameraNormal,
+        RenderPass::Albedo,
+        RenderPass::Uv,
+        RenderPass::PrimitiveId,
+        RenderPass::ObjectId,
+        RenderPass::MaterialId,
+        RenderPass::Direct,
+        RenderPass::Indirect,
+    ];
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            RenderPass::Beauty => "beauty",
+            RenderPass::Depth => "depth",
+            RenderPass::Normal => "normal",
+            RenderPass::CameraNormal => "camera_normal",
+            RenderPass::Albedo => "albedo",
+            RenderPass::Uv => "uv",
+            RenderPass::PrimitiveId => "primitive_id",
+            RenderPass::ObjectId => "object_id",
+            RenderPass::MaterialId => "material_id",
+            RenderPass::Direct => "direct",
+            RenderPass::Indirect => "indirect",
+        }
+    }
+
+    // Whether the pass is built from the surface seen through the center of each pixel
+    pub fn is_geometric(self) -> bool {
+        !matches!(
+            self,
+            RenderPass::Beauty | RenderPass::Albedo | RenderPass::Direct | RenderPass::Indirect
+        )
+    }
+
+    // Whether the pass is built from the identifiers of the surface seen through the center of each pixel
+    pub fn is_id(self) -> bool {
+        matches!(
+            self,
+            RenderPass::PrimitiveId | RenderPass::ObjectId | RenderPass::MaterialId
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObjectIds {
+    pub primitive: u32,
+    pub object: u32,
+    pub material: u32,
+}
+
+// Identifiers of every primitive in a scene which renders an ID pass, keyed by the address of the primitive
+#[derive(Debug, Default)]
+pub struct ObjectIdMap {
+    ids: HashMap<usize, ObjectIds>,
+    materials: Vec<Material>,
+}
+
+impl ObjectIdMap {
+    fn get_key(object: &dyn RaytracingObject) -> usize {
+        std::ptr::from_ref(object).cast::<()>() as usize
+    }
+
+    pub fn insert(&mut self, object: &dyn RaytracingObject, object_index: usize) {
+        let material = object.get_material();
+        let material_index = self
+            .materials
+            .iter()
+            .position(|other| other == material)
+            .unwrap_or_else(|| {
+                self.materials.push(material.clone());
+                self.materials.len() - 1
+            });
+
+        let ids = ObjectIds {
+            primitive: self.ids.len() as u32,
+            object: object_index as u32,
+            material: material_index as u32,
+        };
+        self.ids.insert(Self::get_key(object), ids);
+    }
+
+    // Identifiers of the hit object. Objects of a prototype share their primitive ids between instances, which
+    // have their own object ids and material ids when they replace the material.
+    pub fn get(&self, intersection: &Intersection) -> Option<ObjectIds> {
+        let ids = self.ids.get(&Self::get_key(intersection.object)).copied()?;
+        let Some(instance) = intersection.instance else {
+            return Some(ids);
+        };
+
+        let instance_ids = self.ids.get(&Self::get_key(instance))?;
+        let material = if instance.get_material_override().is_some() {
+            instance_ids.material
+        } else {
+            ids.material
+        };
+
+        Some(ObjectIds {
+            object: instance_ids.object,
+            material,
+            ..ids
+        })
+    }
+}
+
+// Geometry seen through the center of a pixel
+#[derive(Copy, Clone, Debug)]
+pub struct SurfaceData {
+    pub depth: f64,
+    pub normal: Unit<Vector3<f64>>,
+    pub uv: Vector2<f64>,
+    pub ids: Option<ObjectIds>,
+}
+
+// Linear images of each requested render pass
+#[derive(Debug)]
+pub struct RenderPasses {
+    images: Vec<(RenderPass, Rgb32FImage)>,
+}
+
+impl RenderPasses {
+    /// # Panics
+    ///
+    /// Will panic if the buffers do not match the image dimensions
+    pub fn new(
+        passes: &[RenderPass],
+        (width, height): (u32, u32),
+        color_data: &[ColorData],
+        surfaces: &[Option<SurfaceData>],
+        world_to_camera: &Matrix3<f64>,
+    ) -> Self {
+        let mut requested = vec![RenderPass::Beauty];
+        for pass in passes {
+            if !requested.contains(pass) {
+                requested.push(*pass);
+            }
+        }
+
+        let images = requested
+            .into_iter()
+            .map(|pass| {
+                let pixels: Vec<f32> = (0..color_data.len())
+                    .flat_map(|index| {
+                        let surface = surfaces.get(index).copied().flatten();
+                        Self::get_value(pass, &color_data[index], surface, world_to_camera)
+                    })
+                    .collect();
+                let image = Rgb32FImage::from_raw(width, height, pixels)
+                    .expect("render pass does not match the image dimensions");
+
+                (pass, image)
+            })
+            .collect();
+
+        Self { images }
+    }
+
+    fn get_value(
+        pass: RenderPass,
+        color_data: &ColorData,
+        surface: Option<SurfaceData>,
+        world_to_camera: &Matrix3<f64>,
+    ) -> [f32; 3] {
+        let value = match pass {
+            RenderPass::Beauty => color_data.color,
+            RenderPass::Albedo => color_data.albedo,
+            RenderPass::Direct => color_data.direct,
+            RenderPass::Indirect => color_data.color - color_data.direct,
+            RenderPass::Depth => {
+                return [surface.map_or(BACKGROUND_DEPTH, |surface| surface.depth as f32); 3]
+            }
+            RenderPass::Normal => {
+                surface.map_or(Vector3::zeros(), |surface| surface.normal.into_inner())
+            }
+            RenderPass::CameraNormal => surface.map_or(Vector3::zeros(), |surface| {
+                world_to_camera * surface.normal.into_inner()
+            }),
+            RenderPass::Uv => surface.map_or(Vector3::zeros(), |surface| {
+                Vector3::new(surface.uv.x, surface.uv.y, 0.0)
+            }),
+            RenderPass::PrimitiveId | RenderPass::ObjectId | RenderPass::MaterialId => {
+                let id = surface
+                    .and_then(|surface| surface.ids)
+                    .map_or(BACKGROUND_ID, |ids| {
+                        let id = match pass {
+                            RenderPass::PrimitiveId => ids.primitive,
+                            RenderPass::ObjectId => ids.object,
+                            _ => ids.material,
+                        };
+                        id as f32
+                    });
+                return [id; 3];
+            }
+        };
+
+        value.map(|c| c as f32).into()
+    }
+
+    pub fn get(&self, pass: RenderPass) -> Option<&Rgb32FImage> {
+        self.images
+            .iter()
+            .find_map(|(image_pass, image)| (*image_pass == pass).then_some(image))
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.images[0].1.dimensions()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RenderPass, &Rgb32FImage)> {
+        self.images.iter().map(|(pass, image)| (*pass, image))
+    }
+
+    /// # Panics
+    ///
+    /// Will panic if the beauty pass is missing, which cannot happen for passes built with `new`
+    pub fn into_beauty(self) -> Rgb32FImage {
+        self.images
+            .into_iter()
+            .find_map(|(pass, image)| (pass == RenderPass::Beauty).then_some(image))
+            .expect("render passes are missing the beauty pass")
+    }
+}
+
+// Pixel value of a pass for an identifier, spreading neighboring indices over distinct colors
+pub fn get_id_color(id: f32) -> Vector3<f64> {
+    if id < 0.0 {
+        return Vector3::zeros();
+    }
+
+    let hash = (id as u32).wrapping_add(1).wrapping_mul(0x9E37_79B9);
+    Vector3::new(hash >> 24, (hash >> 16) & 0xFF, (hash >> 8) & 0xFF).map(|c| f64::from(c) / 255.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_builds_requested_passes_with_the_beauty_pass() {
+        let mut color_data = ColorData::new(
+            Vector3::new(2.0, 1.0, 0.5),
+            Vector3::repeat(0.5),
+            Vector3::y_axis(),
+        );
+        color_data.direct = Vector3::new(1.5, 0.5, 0.5);
+        let surface = SurfaceData {
+            depth: 3.0,
+            normal: Vector3::y_axis(),
+            uv: Vector2::new(0.25, 0.75),
+            ids: Some(ObjectIds {
+                primitive: 4,
+                object: 1,
+                material: 2,
+            }),
+        };
+        // Camera looking straight down
+        let world_to_camera = Matrix3::new(1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0);
+
+        let passes = RenderPasses::new(
+            &[
+                RenderPass::Depth,
+                RenderPass::CameraNormal,
+                RenderPass::Indirect,
+                RenderPass::ObjectId,
+                RenderPass::Depth,
+            ],
+            (2, 1),
+            &[color_data, ColorData::black()],
+            &[Some(surface), None],
+            &world_to_camera,
+        );
+
+        let names: Vec<&str> = passes.iter().map(|(pass, _)| pass.get_name()).collect();
+        assert_eq!(
+            names,
+            ["beauty", "depth", "camera_normal", "indirect", "object_id"]
+        );
+
+        let get_pixel = |pass, x| passes.get(pass).unwrap().get_pixel(x, 0).0;
+        assert_eq!(get_pixel(RenderPass::Beauty, 0), [2.0, 1.0, 0.5]);
+        assert_eq!(get_pixel(RenderPass::Depth, 0), [3.0; 3]);
+        assert_eq!(get_pixel(RenderPass::Depth, 1), [BACKGROUND_DEPTH; 3]);
+        assert_eq!(get_pixel(RenderPass::CameraNormal, 0), [0.0, 0.0, 1.0]);
+        assert_eq!(get_pixel(RenderPass::Indirect, 0), [0.5, 0.5, 0.0]);
+        assert_eq!(get_pixel(RenderPass::ObjectId, 0), [1.0; 3]);
+        assert_eq!(get_pixel(RenderPass::ObjectId, 1), [BACKGROUND_ID; 3]);
+        assert!(passes.get(RenderPass::Uv).is_none());
+    }
+}
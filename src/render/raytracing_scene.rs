@@ -1,31 +1,52 @@
-use super::{Camera, CastStats, CastTimings, ColorData, RenderOptions, BIAS};
-use crate::core::{
-    KdTreeAccelerator, Material, PhongMaterial, PhysicalMaterial, Texture, Transformed,
+use super::passes::SurfaceData;
+use super::{
+    to_display_color, to_display_image, Camera, CastStats, CastTimings, ColorData, ObjectIdMap,
+    Projection, RenderOptions, RenderPass, RenderPasses, StereoLayout, BIAS,
 };
-use crate::lights::Light;
+use crate::core::{KdTreeAccelerator, Texture};
+use crate::integrators::{Integrator, Sampler};
+use crate::lights::{Light, LightIntersection};
 use crate::ray_intersection::{Intersection, Ray, RayType};
 use crate::utils;
-use image::RgbaImage;
+use crate::RaytraceError;
+use image::{Rgb32FImage, RgbaImage};
 use indicatif::{ProgressBar, ProgressStyle};
 use minifb::{Key, Window, WindowOptions};
-use nalgebra::{Matrix4, Point3, Unit, Vector3};
-use num_traits::identities::Zero;
+use nalgebra::{Matrix3, Matrix4, Point2, Point3, Unit, Vector3};
 use rand::Rng;
 use rand::{seq::SliceRandom, thread_rng};
 use rayon::prelude::*;
 use std::collections::HashMap;
-use std::f64::consts::{FRAC_1_PI, FRAC_PI_2};
+use std::f64::consts::{FRAC_PI_2, PI};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
 #[cfg(feature = "denoise")]
 use std::time::Instant;
 
+// Width of a full frame sensor in meters, used to find the focal length of a lens from its field of view
+const SENSOR_WIDTH: f64 = 0.036;
+
 #[derive(Debug)]
 pub struct RaytracingCamera {
     fov: f64,
+    projection: Projection,
+    stereo: StereoLayout,
+    interocular_distance: f64,
+    convergence_distance: f64,
     position: Point3<f64>,
+    target: Point3<f64>,
+    up: Unit<Vector3<f64>>,
     camera_to_world: Matrix4<f64>,
+    // Position and target at the end of the motion of a camera which moves while the shutter is open
+    end_position: Point3<f64>,
+    end_target: Point3<f64>,
+    shutter_open: f64,
+    shutter_close: f64,
+    lens_radius: f64,
+    focus_distance: f64,
+    aperture_blades: u8,
+    aperture_rotation: f64,
 }
 
 impl From<Camera> for RaytracingCamera {
@@ -33,11 +54,81 @@ impl From<Camera> for RaytracingCamera {
         let camera_to_world =
             Matrix4::look_at_rh(&camera.position, &camera.target, &camera.up).transpose();
 
+        let aperture = camera.f_stop.map_or(camera.aperture, |f_stop| {
+            let focal_length = SENSOR_WIDTH / 2.0 / (camera.fov.to_radians() / 2.0).tan();
+            focal_length / f_stop
+        });
+        let focus_distance = camera
+            .focus_distance
+            .unwrap_or_else(|| (camera.target - camera.position).magnitude());
+
         Self {
             fov: camera.fov,
+            projection: camera.projection,
+            stereo: camera.stereo,
+            interocular_distance: camera.interocular_distance,
+            convergence_distance: camera.convergence_distance.unwrap_or(focus_distance),
             position: camera.position,
+            target: camera.target,
+            up: camera.up,
             camera_to_world,
+            end_position: camera.end_position.unwrap_or(camera.position),
+            end_target: camera.end_target.unwrap_or(camera.target),
+            shutter_open: camera.shutter_open,
+            shutter_close: camera.shutter_close,
+            lens_radius: aperture.max(0.0) / 2.0,
+            focus_distance,
+            aperture_blades: camera.aperture_blades,
+            aperture_rotation: camera.aperture_rotation.to_radians(),
+        }
+    }
+}
+
+impl RaytracingCamera {
+    // Position and camera to world matrix at `time` in the motion of the camera
+    fn get_frame_at(&self, time: f64) -> (Point3<f64>, Matrix4<f64>) {
+        if self.end_position == self.position && self.end_target == self.target {
+            return (self.position, self.camera_to_world);
+        }
+
+        let position = self
+            .position
+            .coords
+            .lerp(&self.end_position.coords, time)
+            .into();
+        let target = self
+            .target
+            .coords
+            .lerp(&self.end_target.coords, time)
+            .into();
+
+        (
+            position,
+            Matrix4::look_at_rh(&position, &target, &self.up).transpose(),
+        )
+    }
+
+    fn sample_time(&self, sampler: &mut Sampler) -> f64 {
+        utils::lerp(self.shutter_open, self.shutter_close, sampler.gen())
+    }
+
+    fn get_mid_shutter_time(&self) -> f64 {
+        self.shutter_open.midpoint(self.shutter_close)
+    }
+
+    // Point on the lens in camera space, which is the pinhole at the origin without an aperture
+    fn sample_lens(&self, sampler: &mut Sampler) -> Point2<f64> {
+        if self.lens_radius <= 0.0 {
+            return Point2::origin();
         }
+
+        let point = if self.aperture_blades >= 3 {
+            utils::sample_regular_polygon(self.aperture_blades, self.aperture_rotation, sampler)
+        } else {
+            utils::concentric_sample_disk(sampler)
+        };
+
+        point * self.lens_radius
     }
 }
 
@@ -48,6 +139,8 @@ pub struct RaytracingScene {
     lights: Vec<Light>,
     textures: HashMap<String, Texture>,
     object_tree: KdTreeAccelerator,
+    object_ids: ObjectIdMap,
+    integrator: Box<dyn Integrator>,
 }
 
 impl RaytracingScene {
@@ -57,16 +150,28 @@ impl RaytracingScene {
         lights: Vec<Light>,
         textures: HashMap<String, Texture>,
         object_tree: KdTreeAccelerator,
+        object_ids: ObjectIdMap,
     ) -> Self {
+        let integrator = render_options.integrator.into();
+
         Self {
             render_options,
             camera,
             lights,
             textures,
             object_tree,
+            object_ids,
+            integrator,
         }
     }
 
+    // Replace the integrator selected by the render options, e.g. with a custom implementation
+    #[must_use]
+    pub fn with_integrator(mut self, integrator: Box<dyn Integrator>) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
     pub fn get_width(&self) -> u32 {
         self.render_options.width
     }
@@ -75,550 +180,322 @@ impl RaytracingScene {
         self.render_options.height
     }
 
-    fn get_aspect(&self) -> f64 {
-        f64::from(self.get_width()) / f64::from(self.get_height())
+    pub fn get_num_objects(&self) -> usize {
+        self.object_tree.get_num_objects()
     }
 
-    fn compute_screen_to_fov(&self) -> f64 {
-        (self.camera.fov.to_radians() / 2.0).tan()
+    pub fn get_lights(&self) -> &[Light] {
+        &self.lights
     }
 
-    pub fn get_num_objects(&self) -> usize {
-        self.object_tree.get_num_objects()
+    pub fn get_textures(&self) -> &HashMap<String, Texture> {
+        &self.textures
     }
 
-    fn raycast(&self, ray: &Ray) -> Option<Intersection> {
+    pub fn raycast(&self, ray: &Ray) -> Option<Intersection> {
         self.object_tree.raycast(ray)
     }
 
-    fn shadow_cast(&self, ray: &Ray, max_distance: f64) -> bool {
+    pub fn shadow_cast(&self, ray: &Ray, max_distance: f64) -> bool {
         self.object_tree.shadow_cast(ray, max_distance - BIAS)
     }
 
-    fn compute_global_illumination(
-        &self,
-        intersection: &Intersection,
-        depth: u8,
-    ) -> (Vector3<f64>, f64, CastStats) {
-        let mut cast_stats = CastStats::zero();
-        let d = 4_u16.pow(depth.into());
-        let illumination_rays = (self.render_options.max_illumination_rays / d).max(1);
-
-        let mut incoming_emissive = Vector3::zero();
-        let mut ambient_occlusion = 0;
-        for _ in 0..illumination_rays {
-            let direction =
-                utils::cosine_sample_hemisphere(&intersection.get_normal()).into_inner();
-
-            let illumination_ray = Ray {
-                ray_type: RayType::Secondary(depth + 1),
-                origin: intersection.get_hit_point() + direction * BIAS,
-                direction,
-                refractive_index: 1.0,
-            };
-            let (emissive, illumination_stats, occluded) = self.get_illumination(&illumination_ray);
-            cast_stats += illumination_stats;
-            incoming_emissive += emissive;
-
-            if !occluded {
-                ambient_occlusion += 1;
-            }
-        }
-
-        (
-            incoming_emissive / f64::from(illumination_rays),
-            f64::from(ambient_occlusion) / f64::from(illumination_rays),
-            cast_stats,
-        )
+    // Find the closest area light hit by the ray before `max_distance`
+    pub fn light_cast(&self, ray: &Ray, max_distance: f64) -> Option<(&Light, LightIntersection)> {
+        let direction = Unit::new_normalize(ray.direction);
+
+        self.lights
+            .iter()
+            .filter_map(|light| {
+                light
+                    .intersect(&ray.origin, &direction)
+                    .filter(|intersection| intersection.distance < max_distance)
+                    .map(|intersection| (light, intersection))
+            })
+            .min_by(|(_, a), (_, b)| a.distance.total_cmp(&b.distance))
     }
 
-    fn get_emissive_phong(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        material: &PhongMaterial,
-    ) -> (Vector3<f64>, CastStats) {
-        let mut cast_stats = CastStats::zero();
-        let depth = ray.get_depth();
-        let hit_point = intersection.get_hit_point();
-
-        let normal = intersection.get_normal();
-
-        let uv = intersection.get_uv();
-        let material_color = material.get_color(uv, &self.textures);
-
-        let mut emissive = material.emissive;
-
-        if material.reflectivity > 0.0 {
-            let reflection_dir = utils::reflect(&ray.direction, &normal).into_inner();
-            let reflection_ray = Ray {
-                ray_type: RayType::Secondary(depth + 1),
-                origin: hit_point + reflection_dir * BIAS,
-                direction: reflection_dir,
-                refractive_index: 1.0,
-            };
-            let (incoming_emissive, stats, _) = self.get_illumination(&reflection_ray);
-            cast_stats += stats;
-
-            emissive += incoming_emissive.component_mul(&material_color) * material.reflectivity;
-        };
-
-        (emissive, cast_stats)
+    // Radiance seen along rays leaving the scene directly from the camera
+    pub fn get_background(&self, direction: &Unit<Vector3<f64>>) -> Vector3<f64> {
+        self.lights
+            .iter()
+            .fold(Vector3::zeros(), |acc, light| match light {
+                Light::Directional(light) => acc + light.get_environment_radiance(direction),
+                Light::Environment(environment) => acc + environment.get_radiance(direction),
+                _ => acc,
+            })
     }
 
-    fn get_color_phong(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        material: &PhongMaterial,
-    ) -> (ColorData, CastStats) {
-        let mut cast_stats = CastStats::zero();
-        let depth = ray.get_depth();
-        let hit_point = intersection.get_hit_point();
-
-        let normal = intersection.get_normal();
-
-        let uv = intersection.get_uv();
-        let material_color = material.get_color(uv, &self.textures);
-
-        let reflection = if material.reflectivity > 0.0 {
-            let reflection_dir = utils::reflect(&ray.direction, &normal).into_inner();
-            let reflection_ray = Ray {
-                ray_type: RayType::Secondary(depth + 1),
-                origin: hit_point + reflection_dir * BIAS,
-                direction: reflection_dir,
-                refractive_index: 1.0,
-            };
-            let (mut color_data, stats) = self.get_color(&reflection_ray);
-            color_data.color.component_mul_assign(&material_color);
-            cast_stats += stats;
-
-            Some(color_data)
-        } else {
-            None
-        };
-
-        let mut ambient_light = Vector3::zero();
-        let mut irradiance = Vector3::zero();
-        if material.reflectivity < 1.0 {
-            for light in &self.lights {
-                match light {
-                    Light::Ambient(light) => {
-                        ambient_light += light.get_color().component_mul(&material_color);
-                    }
-                    Light::Point(light) => {
-                        let light_position = light.get_position();
-                        let light_dir = light_position - hit_point;
-                        let light_distance = light_dir.magnitude();
-                        let light_dir = light_dir.normalize();
-
-                        let n_dot_l = normal.dot(&light_dir);
-                        if n_dot_l > 0.0 {
-                            let shadow_ray = Ray {
-                                ray_type: RayType::Shadow,
-                                origin: light_position,
-                                direction: -light_dir,
-                                refractive_index: 1.0,
-                            };
-
-                            cast_stats.ray_count += 1;
-                            if !self.shadow_cast(&shadow_ray, light_distance) {
-                                let light_color = light.get_color(light_distance);
-                                irradiance += light_color.component_mul(&material_color) * n_dot_l;
-
-                                let half_vec = Unit::new_normalize(light_dir - ray.direction);
-                                let n_dot_h = normal.dot(&half_vec);
-                                if n_dot_h > 0.0 {
-                                    irradiance += light_color.component_mul(&material.specular)
-                                        * n_dot_h.powf(material.shininess);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        let (incoming_emissive, ambient_occlusion, illumination_stats) =
-            self.compute_global_illumination(intersection, depth);
-        cast_stats += illumination_stats;
-
-        let mut color_data = ColorData::new(
-            material.emissive
-                + (ambient_light + irradiance + incoming_emissive.component_mul(&material_color))
-                    * ambient_occlusion,
-            material_color,
-            normal,
-        );
-
-        if let Some(reflection) = reflection {
-            color_data.color = color_data
-                .color
-                .lerp(&reflection.compute_color(), material.reflectivity);
+    // Solid angle density of light sampling reaching the object hit by `intersection`, at an angle with cosine
+    // `cos_theta` to its surface
+    pub fn emitter_pdf(&self, intersection: &Intersection, cos_theta: f64) -> f64 {
+        if intersection.get_emitter_shape().is_none() {
+            return 0.0;
         }
 
-        (color_data, cast_stats)
+        self.lights
+            .iter()
+            .find_map(|light| match light {
+                Light::Emissive(light) => Some(light.pdf(
+                    intersection.get_material().emissive(),
+                    intersection.distance,
+                    cos_theta,
+                )),
+                _ => None,
+            })
+            .unwrap_or(0.0)
     }
 
-    fn get_emissive_physical(
-        &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        material: &PhysicalMaterial,
-    ) -> (Vector3<f64>, CastStats) {
-        let mut cast_stats = CastStats::zero();
-        let depth = ray.get_depth();
-        let hit_point = intersection.get_hit_point();
-
-        let normal = intersection.get_normal();
-
-        let uv = intersection.get_uv();
-        let material_color = material.get_color(uv, &self.textures);
-
-        let reflected_emissive = if self.render_options.max_reflected_rays > 0 {
-            let d = 8_u16.pow(depth.into());
-            let reflected_rays = (self.render_options.max_reflected_rays / d).max(1);
-
-            let max_angle = FRAC_PI_2 * material.roughness;
-            let reflection_dir = utils::reflect(&ray.direction, &normal);
-
-            let mut emissive = (0..reflected_rays).fold(Vector3::zero(), |mut acc, _| {
-                let direction = utils::uniform_sample_cone(&reflection_dir, max_angle).into_inner();
-                let reflection_ray = Ray {
-                    ray_type: RayType::Secondary(depth + 1),
-                    origin: hit_point + direction * BIAS,
-                    direction,
-                    refractive_index: 1.0,
-                };
-                let (incoming_emissive, stats, _) = self.get_illumination(&reflection_ray);
-                cast_stats += stats;
-
-                acc += incoming_emissive;
-                acc
-            });
-            emissive *= FRAC_PI_2 / f64::from(reflected_rays);
-            emissive.component_mul_assign(&material_color);
-
-            Some(emissive)
-        } else {
-            None
-        };
-
-        let refracted_emissive = if material.opacity < 1.0 {
-            let eta = ray.refractive_index / material.refractive_index;
-            utils::refract(&ray.direction, &normal, eta).map(|refraction_dir| {
-                let refraction_dir = refraction_dir.into_inner();
-                let refraction_ray = Ray {
-                    ray_type: RayType::Secondary(depth + 1),
-                    origin: hit_point + refraction_dir * BIAS,
-                    direction: refraction_dir,
-                    refractive_index: material.refractive_index,
-                };
-                let (passthrough_emissive, stats, _) = self.get_illumination(&refraction_ray);
-                cast_stats += stats;
-
-                passthrough_emissive
-            })
-        } else {
-            None
-        };
+    // Rays through random positions in a pixel, leaving out positions which are not covered by the projection
+    fn build_camera_rays(&self, x: u32, y: u32, sampler: &mut Sampler) -> Vec<Ray> {
+        assert!(x < self.get_width() && y < self.get_height());
 
-        let mut emissive = material.emissive;
+        let samples = self.render_options.samples_per_pixel;
+        let (x, y) = (f64::from(x), f64::from(y));
 
-        if let Some(reflected_emissive) = reflected_emissive {
-            emissive += reflected_emissive;
-        }
+        let mut ray_pixel_positions = Vec::with_capacity(samples.into());
+        ray_pixel_positions.push((x + 0.5, y + 0.5));
 
-        if let Some(refracted_emissive) = refracted_emissive {
-            emissive += refracted_emissive * (1.0 - material.opacity);
+        for _ in 1..samples {
+            let rx: f64 = sampler.gen();
+            let ry: f64 = sampler.gen();
+            ray_pixel_positions.push((x + rx, y + ry));
         }
 
-        (emissive, cast_stats)
+        ray_pixel_positions
+            .into_iter()
+            .filter_map(|position| {
+                let time = self.camera.sample_time(sampler);
+                let lens_point = self.camera.sample_lens(sampler);
+                self.build_camera_ray(position, &lens_point, time)
+            })
+            .collect()
     }
 
-    fn get_color_physical(
+    // Ray through a position on the screen from a point on the lens, which converges with rays from other points
+    // on the lens at the focus distance. The lens is only used by the perspective and orthographic projections.
+    fn build_camera_ray(
         &self,
-        ray: &Ray,
-        intersection: &Intersection,
-        material: &PhysicalMaterial,
-    ) -> (ColorData, CastStats) {
-        let mut cast_stats = CastStats::zero();
-        let depth = ray.get_depth();
-        let hit_point = intersection.get_hit_point();
-
-        let normal = intersection.get_normal();
-        let view_dir = Unit::new_normalize(-ray.direction);
-        let n_dot_v = normal.dot(&view_dir).max(0.0);
-
-        let uv = intersection.get_uv();
-        let material_color = material.get_color(uv, &self.textures);
-
-        let roughness = material.roughness.max(0.04);
-        let base_reflectivity = Vector3::repeat(0.04).lerp(&material_color, material.metalness);
-        let f = utils::fresnel(n_dot_v, base_reflectivity);
-        let k_s = f;
-        let k_d = (Vector3::repeat(1.0) - k_s) * (1.0 - material.metalness);
-
-        let reflection = if self.render_options.max_reflected_rays > 0 {
-            let d = 8_u16.pow(depth.into());
-            let reflected_rays = (self.render_options.max_reflected_rays / d).max(1);
-
-            let max_angle = FRAC_PI_2 * material.roughness;
-            let reflection_dir = utils::reflect(&ray.direction, &normal);
-
-            let mut reflection = (0..reflected_rays).fold(ColorData::zero(), |mut acc, _| {
-                let direction = utils::uniform_sample_cone(&reflection_dir, max_angle).into_inner();
-                let reflection_ray = Ray {
-                    ray_type: RayType::Secondary(depth + 1),
-                    origin: hit_point + direction * BIAS,
-                    direction,
-                    refractive_index: 1.0,
-                };
-                let (color_data, stats) = self.get_color(&reflection_ray);
-                cast_stats += stats;
-
-                acc.color += color_data.compute_color();
-                acc
-            });
-            reflection.color *= FRAC_PI_2 / f64::from(reflected_rays);
+        position: (f64, f64),
+        lens_point: &Point2<f64>,
+        time: f64,
+    ) -> Option<Ray> {
+        let (eye_offset, (x, y), (width, height)) = self.get_eye_viewport(position);
+        let aspect = width / height;
+
+        let (x, y) = (
+            utils::remap_value(x, (0.0, width), (-1.0, 1.0)),
+            utils::remap_value(y, (0.0, height), (1.0, -1.0)),
+        );
 
-            Some(reflection)
-        } else {
-            None
-        };
+        let (origin, direction) = match self.camera.projection {
+            Projection::Perspective => {
+                let fov = (self.camera.fov.to_radians() / 2.0).tan();
 
-        let refraction = if material.opacity < 1.0 {
-            let eta = ray.refractive_index / material.refractive_index;
-            utils::refract(&ray.direction, &normal, eta).map(|refraction_dir| {
-                let refraction_dir = refraction_dir.into_inner();
-                let refraction_ray = Ray {
-                    ray_type: RayType::Secondary(depth + 1),
-                    origin: hit_point + refraction_dir * BIAS,
-                    direction: refraction_dir,
-                    refractive_index: material.refractive_index,
+                // Apply fov and scale to aspect ratio
+                let (x, y) = if width < height {
+                    (x * aspect, y)
+                } else {
+                    (x, y / aspect)
                 };
-                let (mut refraction, stats) = self.get_color(&refraction_ray);
-                cast_stats += stats;
-
-                refraction
-                    .color
-                    .component_mul_assign(&Vector3::repeat(1.0).lerp(&f, material.opacity));
 
-                refraction
-            })
-        } else {
-            None
-        };
-
-        let mut ambient_light = Vector3::zero();
-        let mut irradiance = Vector3::zero();
-        let diffuse = FRAC_1_PI * k_d.component_mul(&material_color);
-        for light in &self.lights {
-            match light {
-                Light::Ambient(light) => {
-                    ambient_light += light.get_color().component_mul(&material_color);
-                }
-                Light::Point(light) => {
-                    let light_position = light.get_position();
-                    let light_dir = light_position - hit_point;
-                    let light_distance = light_dir.magnitude();
-                    let light_dir = light_dir.normalize();
-
-                    let n_dot_l = normal.dot(&light_dir);
-                    if n_dot_l > 0.0 {
-                        let shadow_ray = Ray {
-                            ray_type: RayType::Shadow,
-                            origin: light_position,
-                            direction: -light_dir,
-                            refractive_index: 1.0,
-                        };
-
-                        cast_stats.ray_count += 1;
-                        if !self.shadow_cast(&shadow_ray, light_distance) {
-                            let half_vec = Unit::new_normalize(light_dir - ray.direction);
-                            let n_dot_h = normal.dot(&half_vec).max(0.0);
-
-                            let light_color = light.get_color(light_distance);
-                            let radiance = light_color * n_dot_l;
-
-                            let ndf = utils::ndf(n_dot_h, roughness);
-                            let g = utils::geometry_function(n_dot_v, n_dot_l, roughness);
-
-                            let diffuse_specular = if n_dot_v == 0.0 {
-                                diffuse
-                            } else {
-                                let specular = ndf * g * f / (4.0 * n_dot_v * n_dot_l);
-                                diffuse + specular
-                            };
-
-                            irradiance += diffuse_specular.component_mul(&radiance) * n_dot_l;
-                        }
-                    }
+                let (origin, direction) = self.converge_eye(
+                    Point3::origin(),
+                    Vector3::from([x * fov, y * fov, -1.0]),
+                    eye_offset,
+                );
+                self.focus_through_lens(origin, direction, lens_point)
+            }
+            Projection::Orthographic {
+                height: ortho_height,
+            } => {
+                let half_height = ortho_height / 2.0;
+
+                let (origin, direction) = self.converge_eye(
+                    Point3::from([x * aspect * half_height, y * half_height, 0.0]),
+                    -Vector3::z(),
+                    eye_offset,
+                );
+                self.focus_through_lens(origin, direction, lens_point)
+            }
+            // Angle from the viewing axis grows linearly with the distance from the center of a circle filling
+            // the shorter side of the image
+            Projection::Fisheye { fov } => {
+                let (x, y) = if width < height {
+                    (x, y / aspect)
+                } else {
+                    (x * aspect, y)
+                };
+                let radius = x.hypot(y);
+                if radius > 1.0 {
+                    return None;
                 }
-            };
-        }
-
-        let (incoming_emissive, ambient_occlusion, illumination_stats) =
-            self.compute_global_illumination(intersection, depth);
-        cast_stats += illumination_stats;
 
-        let mut color_data = ColorData::new(
-            material.emissive
-                + (ambient_light + irradiance + incoming_emissive.component_mul(&diffuse))
-                    * ambient_occlusion,
-            material_color,
-            normal,
-        );
+                let theta = radius * fov.to_radians() / 2.0;
+                let phi = y.atan2(x);
+                let direction = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    -theta.cos(),
+                );
 
-        if let Some(reflection) = reflection {
-            color_data.color = Vector3::new(
-                utils::lerp(color_data.color.x, reflection.color.x, f.x),
-                utils::lerp(color_data.color.y, reflection.color.y, f.y),
-                utils::lerp(color_data.color.z, reflection.color.z, f.z),
-            );
-        }
+                (Point3::from([eye_offset, 0.0, 0.0]), direction)
+            }
+            // Longitude spans the width of the image and latitude its height, with the center looking forward.
+            // Stereo panoramas use omni-directional stereo, placing each eye on a circle which the rays are tangent
+            // to, so the eyes are offset sideways from every horizontal viewing direction.
+            Projection::Equirectangular => {
+                let longitude = x * PI;
+                let latitude = y * FRAC_PI_2;
+                let direction = Vector3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                let origin = Point3::new(longitude.cos(), 0.0, longitude.sin()) * eye_offset;
 
-        if let Some(refraction) = refraction {
-            color_data.color = refraction
-                .compute_color()
-                .lerp(&color_data.color, material.opacity);
-            color_data.normal = refraction.normal;
-        }
+                (origin, direction)
+            }
+        };
 
-        (color_data, cast_stats)
+        let (camera_position, camera_to_world) = self.camera.get_frame_at(time);
+        let direction = (camera_to_world * direction.normalize().to_homogeneous()).xyz();
+        let origin = camera_position + (camera_to_world * origin.coords.to_homogeneous()).xyz();
+
+        Some(Ray {
+            ray_type: RayType::Primary,
+            origin,
+            direction,
+            refractive_index: 1.0,
+            time,
+        })
     }
 
-    #[allow(clippy::option_if_let_else)]
-    fn get_illumination(&self, ray: &Ray) -> (Vector3<f64>, CastStats, bool) {
-        let mut cast_stats = CastStats::zero();
-
-        if ray.get_depth() >= self.render_options.max_depth {
-            return (Vector3::zero(), cast_stats, false);
-        }
-
-        cast_stats.ray_count += 1;
-        if let Some(mut intersection) = self.raycast(ray) {
-            intersection.compute_data(ray);
-
-            let material = intersection.object.get_material();
-            let (emissive, material_stats) = match material {
-                Material::Phong(material) => self.get_emissive_phong(ray, &intersection, material),
-                Material::Physical(material) => {
-                    self.get_emissive_physical(ray, &intersection, material)
+    // Offset from the camera along its x axis of the eye seeing a position on the screen, with the position and
+    // size of the screen area showing that eye
+    fn get_eye_viewport(&self, (x, y): (f64, f64)) -> (f64, (f64, f64), (f64, f64)) {
+        let (width, height) = (f64::from(self.get_width()), f64::from(self.get_height()));
+        let eye_offset = self.camera.interocular_distance / 2.0;
+
+        match self.camera.stereo {
+            StereoLayout::None => (0.0, (x, y), (width, height)),
+            StereoLayout::SideBySide => {
+                let eye_width = width / 2.0;
+                if x < eye_width {
+                    (-eye_offset, (x, y), (eye_width, height))
+                } else {
+                    (eye_offset, (x - eye_width, y), (eye_width, height))
                 }
-            };
-            cast_stats += material_stats;
-
-            (
-                emissive,
-                cast_stats,
-                intersection.distance <= self.render_options.max_occlusion_distance,
-            )
-        } else {
-            (Vector3::zero(), cast_stats, false)
+            }
+            StereoLayout::TopBottom => {
+                let eye_height = height / 2.0;
+                if y < eye_height {
+                    (-eye_offset, (x, y), (width, eye_height))
+                } else {
+                    (eye_offset, (x, y - eye_height), (width, eye_height))
+                }
+            }
         }
     }
 
-    #[allow(clippy::option_if_let_else)]
-    fn get_color(&self, ray: &Ray) -> (ColorData, CastStats) {
-        let mut cast_stats = CastStats::zero();
-
-        if ray.get_depth() >= self.render_options.max_depth {
-            return (ColorData::black(), cast_stats);
+    // Move the origin of a camera space ray to an eye `eye_offset` along the x axis, aiming at the point where the
+    // ray crosses the plane of convergence so that it appears at the depth of the screen
+    fn converge_eye(
+        &self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        eye_offset: f64,
+    ) -> (Point3<f64>, Vector3<f64>) {
+        if eye_offset == 0.0 {
+            return (origin, direction);
         }
 
-        cast_stats.ray_count += 1;
-        if let Some(mut intersection) = self.raycast(ray) {
-            intersection.compute_data(ray);
-
-            let material = intersection.object.get_material();
-            let (color_data, material_stats) = match material {
-                Material::Phong(material) => self.get_color_phong(ray, &intersection, material),
-                Material::Physical(material) => {
-                    self.get_color_physical(ray, &intersection, material)
-                }
-            };
-            cast_stats += material_stats;
+        let convergence_point =
+            origin + direction * (self.camera.convergence_distance / -direction.z);
+        let eye_origin = origin + Vector3::new(eye_offset, 0.0, 0.0);
 
-            (color_data.clamp(), cast_stats)
-        } else {
-            (ColorData::black(), cast_stats)
-        }
+        (eye_origin, convergence_point - eye_origin)
     }
 
-    fn build_camera_rays(&self, x: u32, y: u32) -> Vec<Ray> {
-        assert!(x < self.get_width() && y < self.get_height());
-
-        let samples = self.render_options.samples_per_pixel;
-        let (width, height) = (f64::from(self.get_width()), f64::from(self.get_height()));
-        let aspect = self.get_aspect();
-        let fov = self.compute_screen_to_fov();
-
-        let (x, y) = (f64::from(x), f64::from(y));
-
-        let mut ray_pixel_positions = Vec::with_capacity(samples.into());
-        ray_pixel_positions.push((x + 0.5, y + 0.5));
-
-        let mut rng = rand::thread_rng();
-        for _ in 1..samples {
-            let rx: f64 = rng.gen();
-            let ry: f64 = rng.gen();
-            ray_pixel_positions.push((x + rx, y + ry));
-        }
-
-        ray_pixel_positions
-            .into_iter()
-            .map(|(x, y)| {
-                let (x, y) = (
-                    utils::remap_value(x, (0.0, width), (-1.0, 1.0)),
-                    utils::remap_value(y, (0.0, height), (1.0, -1.0)),
-                );
+    // Move the origin of a camera space ray to a point on the lens, aiming at the point where the ray crosses the
+    // plane of focus
+    fn focus_through_lens(
+        &self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        lens_point: &Point2<f64>,
+    ) -> (Point3<f64>, Vector3<f64>) {
+        let focus_point = origin + direction * (self.camera.focus_distance / -direction.z);
+        let lens_origin = origin + Vector3::new(lens_point.x, lens_point.y, 0.0);
+
+        (lens_origin, focus_point - lens_origin)
+    }
 
-                // Apply fov and scale to aspect ratio
-                let (x, y) = if width < height {
-                    (x * aspect, y)
-                } else {
-                    (x, y / aspect)
-                };
-                let (x, y) = (x * fov, y * fov);
+    fn get_world_to_camera(&self) -> Matrix3<f64> {
+        self.camera
+            .camera_to_world
+            .fixed_slice::<3, 3>(0, 0)
+            .transpose()
+    }
 
-                let direction = Vector3::from([x, y, -1.0]).normalize();
-                let direction = (self.camera.camera_to_world * direction.to_homogeneous()).xyz();
+    // Surface seen through the center of a pixel, for the geometric render passes
+    fn surface_raycast(&self, x: u32, y: u32, cast_stats: &mut CastStats) -> Option<SurfaceData> {
+        let ray = self.build_camera_ray(
+            (f64::from(x) + 0.5, f64::from(y) + 0.5),
+            &Point2::origin(),
+            self.camera.get_mid_shutter_time(),
+        )?;
 
-                Ray {
-                    ray_type: RayType::Primary,
-                    origin: self.camera.position,
-                    direction,
-                    refractive_index: 1.0,
-                }
-            })
-            .collect()
+        cast_stats.ray_count += 1;
+        let mut intersection = self.raycast(&ray)?;
+        intersection.compute_data(&ray);
+
+        let forward = -self.get_world_to_camera().row(2).transpose();
+        let depth = intersection.distance * ray.direction.dot(&forward) / ray.direction.magnitude();
+
+        Some(SurfaceData {
+            depth,
+            normal: intersection.get_normal(),
+            uv: intersection.get_uv(),
+            ids: self.object_ids.get(&intersection),
+        })
     }
 
+    /// # Panics
+    ///
+    /// Will panic if the pixel coordinates are outside of the image
     pub fn screen_raycast(&self, x: u32, y: u32) -> (ColorData, CastStats) {
         let samples = self.render_options.samples_per_pixel;
-        let rays = self.build_camera_rays(x, y);
+        let mut sampler = Sampler::new(u64::from(y) * u64::from(self.get_width()) + u64::from(x));
+        let mut cast_stats = CastStats::zero();
+        let mut rays = self.build_camera_rays(x, y, &mut sampler).into_iter();
 
-        let (color_data, stats) = if samples == 1 {
-            self.get_color(rays.first().unwrap())
-        } else {
-            let (mut color_data, mut cast_stats) = self.get_color(rays.first().unwrap());
+        // Pixels outside of the area covered by the projection are left black
+        let Some(first_ray) = rays.next() else {
+            return (ColorData::black(), cast_stats);
+        };
+
+        let mut color_data =
+            self.integrator
+                .radiance(self, &first_ray, &mut sampler, &mut cast_stats);
 
-            for ray in &rays[1..] {
-                let (data, stats) = self.get_color(ray);
+        if samples > 1 {
+            for ray in rays {
+                let data = self
+                    .integrator
+                    .radiance(self, &ray, &mut sampler, &mut cast_stats);
                 color_data.color += data.color;
                 color_data.albedo += data.albedo;
-                cast_stats += stats;
+                color_data.direct += data.direct;
             }
 
             let inv_samples = 1.0 / f64::from(samples);
             color_data.color *= inv_samples;
             color_data.albedo *= inv_samples;
+            color_data.direct *= inv_samples;
+        }
 
-            (color_data.clamp(), cast_stats)
-        };
-
-        (color_data.gamma_correct(), stats)
+        (color_data, cast_stats)
     }
 
     fn build_progress_bar(&self) -> ProgressBar {
@@ -643,7 +520,10 @@ impl RaytracingScene {
     }
 
     #[cfg(feature = "denoise")]
-    fn denoising_pass(&self, color_data_buffer_lock: &RwLock<Vec<ColorData>>) {
+    fn denoising_pass(
+        &self,
+        color_data_buffer_lock: &RwLock<Vec<ColorData>>,
+    ) -> Result<(), RaytraceError> {
         let width = self.get_width() as usize;
         let height = self.get_height() as usize;
 
@@ -679,14 +559,14 @@ impl RaytracingScene {
         let device = oidn::Device::new();
         let mut filter = oidn::RayTracing::new(&device);
         filter
-            .srgb(true)
+            .hdr(true)
             .image_dimensions(width, height)
             .albedo_normal(&albedo_data, &normal_data)
             .filter(&beauty_image, &mut denoised_output)
-            .expect("error configuring denoising filter");
+            .map_err(|err| RaytraceError::Denoise(format!("{:?}", err)))?;
 
-        if let Err(e) = device.get_error() {
-            panic!("error denoising image: {:#?}", e);
+        if let Err(err) = device.get_error() {
+            return Err(RaytraceError::Denoise(format!("{:?}", err)));
         }
 
         for index in 0..width * height {
@@ -698,28 +578,74 @@ impl RaytracingScene {
                 denoised_output[buffer_index + 2].into(),
             );
         }
+
+        Ok(())
+    }
+
+    // Render the scene to an 8-bit image with the exposure and tone mapping of the render options
+    pub fn raytrace_to_image(
+        &self,
+        use_progress: bool,
+    ) -> Result<(RgbaImage, CastTimings, CastStats), RaytraceError> {
+        let (image, cast_timings, cast_stats) = self.raytrace(use_progress)?;
+        Ok((
+            to_display_image(&image, &self.render_options),
+            cast_timings,
+            cast_stats,
+        ))
+    }
+
+    pub fn raytrace(
+        &self,
+        use_progress: bool,
+    ) -> Result<(Rgb32FImage, CastTimings, CastStats), RaytraceError> {
+        let (passes, cast_timings, cast_stats) =
+            self.render_passes(&[RenderPass::Beauty], use_progress)?;
+        Ok((passes.into_beauty(), cast_timings, cast_stats))
+    }
+
+    // Render the beauty pass along with the passes selected by the render options
+    pub fn raytrace_passes(
+        &self,
+        use_progress: bool,
+    ) -> Result<(RenderPasses, CastTimings, CastStats), RaytraceError> {
+        self.render_passes(&self.render_options.passes, use_progress)
     }
 
-    pub fn raytrace_to_image(&self, use_progress: bool) -> (RgbaImage, CastTimings, CastStats) {
+    // Only denoising can fail, so the result is always `Ok` without it
+    #[cfg_attr(not(feature = "denoise"), allow(clippy::unnecessary_wraps))]
+    fn render_passes(
+        &self,
+        passes: &[RenderPass],
+        use_progress: bool,
+    ) -> Result<(RenderPasses, CastTimings, CastStats), RaytraceError> {
         let width = self.get_width() as usize;
         let height = self.get_height() as usize;
+        let trace_surfaces = passes.iter().any(|pass| pass.is_geometric());
 
         let mut color_data_buffer: Vec<ColorData> = Vec::new();
         for _ in 0..width * height {
             color_data_buffer.push(ColorData::black());
         }
         let color_data_buffer_lock = RwLock::new(color_data_buffer);
+        let surface_buffer_lock = RwLock::new(vec![None; width * height]);
         let cast_stats = CastStats::zero();
         let cast_stats_lock = RwLock::new(cast_stats);
 
         let process_pixel = |&index| {
-            let (color_data, stats) =
-                self.screen_raycast((index % width) as u32, (index / width) as u32);
+            let (x, y) = ((index % width) as u32, (index / width) as u32);
+            let (color_data, mut stats) = self.screen_raycast(x, y);
+            let surface = if trace_surfaces {
+                self.surface_raycast(x, y, &mut stats)
+            } else {
+                None
+            };
             {
                 let mut cast_stats = cast_stats_lock.write().unwrap();
                 *cast_stats += stats;
             }
 
+            surface_buffer_lock.write().unwrap()[index] = surface;
             let mut color_data_buffer = color_data_buffer_lock.write().unwrap();
             color_data_buffer[index] = color_data;
         };
@@ -749,33 +675,27 @@ impl RaytracingScene {
         {
             if !self.render_options.skip_denoise_pass {
                 cast_timings.start_post_processing();
-                self.denoising_pass(&color_data_buffer_lock);
+                self.denoising_pass(&color_data_buffer_lock)?;
                 cast_timings.finish_post_processing();
             }
         }
 
-        let mut image_buffer: Vec<u8> = vec![0; width * height * 4];
-        for &index in &indexes {
-            let color = {
-                let color_data_buffer = color_data_buffer_lock.read().unwrap();
-                color_data_buffer[index].color
-            };
-
-            let buffer_index = index * 4;
-            image_buffer[buffer_index] = (color.x * 255.0) as u8;
-            image_buffer[buffer_index + 1] = (color.y * 255.0) as u8;
-            image_buffer[buffer_index + 2] = (color.z * 255.0) as u8;
-            image_buffer[buffer_index + 3] = 255;
-        }
-
-        let image = RgbaImage::from_raw(width as u32, height as u32, image_buffer)
-            .expect("failed to convert buffer");
+        let render_passes = RenderPasses::new(
+            passes,
+            (self.get_width(), self.get_height()),
+            &color_data_buffer_lock.read().unwrap(),
+            &surface_buffer_lock.read().unwrap(),
+            &self.get_world_to_camera(),
+        );
 
         let cast_stats = *cast_stats_lock.read().unwrap();
-        (image, cast_timings, cast_stats)
+        Ok((render_passes, cast_timings, cast_stats))
     }
 
-    pub fn raytrace_to_buffer(self, use_progress: bool) {
+    /// # Panics
+    ///
+    /// Will panic if the render thread panics while holding the image buffer
+    pub fn raytrace_to_buffer(self, use_progress: bool) -> Result<(), RaytraceError> {
         let width = self.get_width() as usize;
         let height = self.get_height() as usize;
 
@@ -789,8 +709,7 @@ impl RaytracingScene {
                 borderless: true,
                 ..WindowOptions::default()
             },
-        )
-        .unwrap();
+        )?;
 
         let image_buffer: Vec<u32> = vec![0; width * height];
         let image_buffer_lock = Arc::new(RwLock::new(image_buffer));
@@ -817,7 +736,10 @@ impl RaytracingScene {
 
                 {
                     let mut image_buffer = ray_image_buffer_lock.write().unwrap();
-                    image_buffer[index] = utils::to_argb_u32(color_data.color);
+                    image_buffer[index] = utils::to_argb_u32(to_display_color(
+                        color_data.color,
+                        &self.render_options,
+                    ));
                 }
 
                 let mut color_data_buffer = color_data_buffer_lock.write().unwrap();
@@ -847,7 +769,10 @@ impl RaytracingScene {
             {
                 if !self.render_options.skip_denoise_pass {
                     let post_processing_start = Instant::now();
-                    self.denoising_pass(&color_data_buffer_lock);
+                    if let Err(err) = self.denoising_pass(&color_data_buffer_lock) {
+                        eprintln!("{}", err);
+                        return;
+                    }
                     println!(
                         "Took {:?} to run the post processing pass.",
                         post_processing_start.elapsed()
@@ -856,7 +781,10 @@ impl RaytracingScene {
                     let mut image_buffer = ray_image_buffer_lock.write().unwrap();
                     for &index in &indexes {
                         let color_data_buffer = color_data_buffer_lock.write().unwrap();
-                        image_buffer[index] = utils::to_argb_u32(color_data_buffer[index].color);
+                        image_buffer[index] = utils::to_argb_u32(to_display_color(
+                            color_data_buffer[index].color,
+                            &self.render_options,
+                        ));
                     }
                 }
             }
@@ -865,12 +793,326 @@ impl RaytracingScene {
         while window.is_open() && !window.is_key_down(Key::Escape) {
             {
                 let image_buffer = image_buffer_lock.read().unwrap();
-                window
-                    .update_with_buffer(&image_buffer, width, height)
-                    .unwrap();
+                window.update_with_buffer(&image_buffer, width, height)?;
             }
 
             thread::sleep(Duration::from_millis(100));
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::Material;
+    use crate::render::Scene;
+    use more_asserts::{assert_gt, assert_le, assert_lt};
+    use serde_json::json;
+
+    fn build_emissive_scene(exposure: f64) -> RaytracingScene {
+        let scene_json = json!({
+          "width": 4,
+          "height": 4,
+          "skip_denoise_pass": true,
+          "exposure": exposure,
+          "camera": { "position": [0, 0, 5], "target": [0, 0, 0] },
+          "objects": [{
+            "type": "sphere",
+            "radius": 2,
+            "material": { "type": "phong", "color": [1, 1, 1], "emissive": [4, 4, 4] }
+          }]
+        });
+        let scene: Scene = serde_json::from_value(scene_json).unwrap();
+        scene.build_raytracing_scene().unwrap()
+    }
+
+    #[test]
+    fn it_renders_radiance_above_one() {
+        let (image, _, _) = build_emissive_scene(0.0).raytrace(false).unwrap();
+
+        assert!(image.get_pixel(2, 2).0.iter().all(|&c| c > 1.0));
+    }
+
+    #[test]
+    fn it_keeps_highlight_detail_at_negative_exposure() {
+        let scene = build_emissive_scene(-3.0);
+        let (image, _, _) = scene.raytrace_to_image(false).unwrap();
+        let value = f64::from(image.get_pixel(2, 2).0[0]) / 255.0;
+
+        // Radiance clipped to 1 before exposure would display no brighter than this
+        let clipped = to_display_color(Vector3::repeat(1.0), &scene.render_options);
+        assert_lt!(value, 1.0);
+        assert_gt!(value, clipped.x + 0.1);
+    }
+
+    #[test]
+    fn it_focuses_camera_rays_through_a_lens() {
+        let scene_json = json!({
+          "width": 40,
+          "height": 20,
+          "samples_per_pixel": 16,
+          "camera": {
+            "position": [1, 2, 5],
+            "target": [0, 0, 0],
+            "aperture": 0.5,
+            "focus_distance": 3,
+            "aperture_blades": 6
+          }
+        });
+        let scene: Scene = serde_json::from_value(scene_json).unwrap();
+        let scene = scene.build_raytracing_scene().unwrap();
+        let position = Point3::new(1.0, 2.0, 5.0);
+        let forward = (Point3::origin() - position).normalize();
+
+        let pinhole_ray = scene
+            .build_camera_ray((30.5, 5.5), &Point2::origin(), 0.0)
+            .unwrap();
+        let focus_point =
+            pinhole_ray.origin + pinhole_ray.direction * 3.0 / pinhole_ray.direction.dot(&forward);
+
+        let mut sampler = Sampler::new(0);
+        let rays: Vec<Ray> = (0..100)
+            .filter_map(|_| {
+                let lens_point = scene.camera.sample_lens(&mut sampler);
+                scene.build_camera_ray((30.5, 5.5), &lens_point, 0.0)
+            })
+            .collect();
+        assert!(rays.iter().any(|ray| ray.origin != position));
+        for ray in &rays {
+            let offset = ray.origin - position;
+            assert_le!(offset.magnitude(), 0.25 + 1e-9);
+            assert_le!(offset.dot(&forward).abs(), 1e-9);
+
+            // Rays from every point on the lens converge at the focus distance
+            let to_focus = focus_point - ray.origin;
+            let direction = ray.direction.normalize();
+            let miss = (to_focus - direction * to_focus.dot(&direction)).magnitude();
+            assert_le!(miss, 1e-9);
+        }
+    }
+
+    #[test]
+    fn it_builds_camera_rays_for_each_projection() {
+        let build_scene = |projection| {
+            let scene_json = json!({
+              "width": 40,
+              "height": 20,
+              "camera": { "position": [0, 0, 0], "target": [0, 0, -1], "projection": projection }
+            });
+            let scene: Scene = serde_json::from_value(scene_json).unwrap();
+            scene.build_raytracing_scene().unwrap()
+        };
+        let get_direction = |scene: &RaytracingScene, position| {
+            scene
+                .build_camera_ray(position, &Point2::origin(), 0.0)
+                .map(|ray| ray.direction.normalize())
+        };
+        let assert_direction = |direction: Option<Vector3<f64>>, expected: Vector3<f64>| {
+            assert_le!((direction.unwrap() - expected).magnitude(), 1e-9);
+        };
+
+        let orthographic = build_scene(json!({ "type": "orthographic", "height": 4 }));
+        let corner_ray = orthographic
+            .build_camera_ray((0.0, 0.0), &Point2::origin(), 0.0)
+            .unwrap();
+        assert_direction(Some(corner_ray.direction.normalize()), -Vector3::z());
+        assert_le!(
+            (corner_ray.origin - Point3::new(-4.0, 2.0, 0.0)).magnitude(),
+            1e-9
+        );
+
+        let fisheye = build_scene(json!({ "type": "fisheye", "fov": 180 }));
+        assert_direction(get_direction(&fisheye, (20.0, 10.0)), -Vector3::z());
+        assert_direction(get_direction(&fisheye, (30.0, 10.0)), Vector3::x());
+        assert!(get_direction(&fisheye, (0.5, 0.5)).is_none());
+
+        let equirectangular = build_scene(json!({ "type": "equirectangular" }));
+        assert_direction(get_direction(&equirectangular, (20.0, 10.0)), -Vector3::z());
+        assert_direction(get_direction(&equirectangular, (30.0, 10.0)), Vector3::x());
+        assert_direction(get_direction(&equirectangular, (0.0, 10.0)), Vector3::z());
+        assert_direction(get_direction(&equirectangular, (20.0, 0.0)), Vector3::y());
+    }
+
+    #[test]
+    fn it_builds_camera_rays_for_both_eyes() {
+        let build_scene = |camera| {
+            let scene_json = json!({ "width": 40, "height": 20, "camera": camera });
+            let scene: Scene = serde_json::from_value(scene_json).unwrap();
+            scene.build_raytracing_scene().unwrap()
+        };
+
+        let side_by_side = build_scene(json!({
+          "position": [0, 0, 0],
+          "target": [0, 0, -1],
+          "stereo": "side_by_side",
+          "interocular_distance": 0.1,
+          "convergence_distance": 2
+        }));
+        let left = side_by_side
+            .build_camera_ray((5.0, 4.0), &Point2::origin(), 0.0)
+            .unwrap();
+        let right = side_by_side
+            .build_camera_ray((25.0, 4.0), &Point2::origin(), 0.0)
+            .unwrap();
+        assert_eq!(left.origin, Point3::new(-0.05, 0.0, 0.0));
+        assert_eq!(right.origin, Point3::new(0.05, 0.0, 0.0));
+
+        // Both eyes see the same point at the convergence distance
+        let left_point = left.origin + left.direction * (2.0 / -left.direction.z);
+        let right_point = right.origin + right.direction * (2.0 / -right.direction.z);
+        assert_le!((left_point - right_point).magnitude(), 1e-9);
+
+        let panorama = build_scene(json!({
+          "position": [0, 0, 0],
+          "target": [0, 0, -1],
+          "projection": { "type": "equirectangular" },
+          "stereo": "top_bottom",
+          "interocular_distance": 0.1
+        }));
+        for (x, y) in [(5.0, 3.0), (20.0, 5.0), (33.0, 14.0), (12.0, 18.0)] {
+            let ray = panorama
+                .build_camera_ray((x, y), &Point2::origin(), 0.0)
+                .unwrap();
+            let horizontal_direction = Vector3::new(ray.direction.x, 0.0, ray.direction.z);
+
+            assert_le!((ray.origin.coords.magnitude() - 0.05).abs(), 1e-9);
+            assert_le!(ray.origin.coords.dot(&horizontal_direction).abs(), 1e-9);
+            // The left eye is on the top half and sits to the left of the viewing direction
+            let side = ray.origin.coords.cross(&horizontal_direction).y;
+            assert_eq!(side < 0.0, y < 10.0);
+        }
+    }
+
+    #[test]
+    fn it_intersects_objects_along_their_motion() {
+        let scene_json = json!({
+          "width": 40,
+          "height": 20,
+          "camera": {
+            "position": [0, 0, 5],
+            "end_position": [2, 0, 5],
+            "target": [0, 0, 0],
+            "end_target": [2, 0, 0]
+          },
+          "objects": [
+            {
+              "type": "sphere",
+              "radius": 0.5,
+              "transform": [{ "translate": [-2, 0, 0] }],
+              "end_transform": [{ "translate": [2, 0, 0] }]
+            },
+            {
+              "type": "cube",
+              "size": 1,
+              "transform": [{ "translate": [0, 3, 0] }],
+              "end_transform": [{ "rotate": [[0, 0, 1], 90] }, { "translate": [0, 3, 0] }],
+              "children": [
+                { "type": "sphere", "radius": 0.25, "transform": [{ "translate": [2, 0, 0] }] }
+              ]
+            }
+          ]
+        });
+        let scene: Scene = serde_json::from_value(scene_json).unwrap();
+        let scene = scene.build_raytracing_scene().unwrap();
+        let cast = |x: f64, y: f64, time: f64| {
+            let ray = Ray {
+                ray_type: RayType::Primary,
+                origin: Point3::new(x, y, 5.0),
+                direction: -Vector3::z(),
+                refractive_index: 1.0,
+                time,
+            };
+            scene
+                .raycast(&ray)
+                .map(|intersection| intersection.distance)
+        };
+
+        assert!(cast(-2.0, 0.0, 0.0).is_some());
+        assert!(cast(-2.0, 0.0, 1.0).is_none());
+        assert!(cast(2.0, 0.0, 1.0).is_some());
+        assert!(cast(0.0, 0.0, 0.5).is_some());
+
+        // The child follows its parent's rotation from the right of the cube to above it
+        assert!(cast(2.0, 3.0, 0.0).is_some());
+        assert!(cast(2.0, 3.0, 1.0).is_none());
+        assert!(cast(0.0, 5.0, 1.0).is_some());
+
+        // Halfway through its rotation a corner of the cube reaches further out
+        assert!(cast(0.6, 3.0, 0.0).is_none());
+        assert!(cast(0.6, 3.0, 0.5).is_some());
+
+        let start = scene
+            .build_camera_ray((20.0, 10.0), &Point2::origin(), 0.0)
+            .unwrap();
+        let end = scene
+            .build_camera_ray((20.0, 10.0), &Point2::origin(), 1.0)
+            .unwrap();
+        assert_le!(
+            (start.origin - Point3::new(0.0, 0.0, 5.0)).magnitude(),
+            1e-9
+        );
+        assert_le!((end.origin - Point3::new(2.0, 0.0, 5.0)).magnitude(), 1e-9);
+        assert_eq!(end.time, 1.0);
+    }
+
+    #[test]
+    fn it_intersects_instances_of_prototypes() {
+        let scene_json = json!({
+          "passes": ["beauty", "object_id"],
+          "materials": {
+            "red": { "type": "phong", "color": [1, 0, 0] },
+            "blue": { "type": "phong", "color": [0, 0, 1] }
+          },
+          "prototypes": {
+            "box": { "type": "cube", "size": 1, "material": "red" }
+          },
+          "objects": [
+            { "type": "instance", "prototype": "box", "transform": [{ "translate": [-2, 0, 0] }] },
+            {
+              "type": "instance",
+              "prototype": "box",
+              "transform": [{ "scale": [2, 2, 2] }, { "translate": [2, 0, 0] }],
+              "material": "blue"
+            }
+          ]
+        });
+        let scene: Scene = serde_json::from_value(scene_json).unwrap();
+        let scene = scene.build_raytracing_scene().unwrap();
+        assert_eq!(scene.get_num_objects(), 2);
+
+        let cast = |x: f64| {
+            let ray = Ray {
+                ray_type: RayType::Primary,
+                origin: Point3::new(x, 0.25, 5.0),
+                direction: -Vector3::z(),
+                refractive_index: 1.0,
+                time: 0.0,
+            };
+            let mut intersection = scene.raycast(&ray)?;
+            intersection.compute_data(&ray);
+            let Material::Phong(material) = intersection.get_material() else {
+                panic!("expected a phong material");
+            };
+            assert_eq!(intersection.get_normal(), Vector3::z_axis());
+            let ids = scene.object_ids.get(&intersection).unwrap();
+
+            Some((intersection.distance, material.color, ids))
+        };
+
+        let (distance, color, ids) = cast(-2.0).unwrap();
+        assert_le!((distance - 4.5).abs(), 1e-9);
+        assert_eq!(color, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(ids.object, 0);
+
+        let (distance, color, scaled_ids) = cast(2.5).unwrap();
+        assert_le!((distance - 4.0).abs(), 1e-9);
+        assert_eq!(color, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(scaled_ids.object, 1);
+        assert_eq!(scaled_ids.primitive, ids.primitive);
+        assert_ne!(scaled_ids.material, ids.material);
+
+        assert!(cast(0.0).is_none());
     }
 }
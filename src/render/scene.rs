@@ -1,20 +1,33 @@
 use super::raytracing_scene::RaytracingScene;
-use super::{Camera, RenderOptions};
-use crate::core::{KdTreeAccelerator, Texture, Transform};
-use crate::lights::Light;
-use crate::primitives::Object3D;
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::path::Path;
-
-#[derive(Debug, Deserialize)]
+use super::{AssetCache, Camera, Diagnostic, ObjectIdMap, RenderOptions, Validator};
+use crate::core::{AnimatedTransform, KdTreeAccelerator, Material, Texture};
+use crate::lights::{EmissiveLight, Emitter, Environment, Light};
+use crate::primitives::{Object3D, Prototype, RaytracingObject};
+use crate::RaytraceError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 pub struct Scene {
     #[serde(flatten)]
     pub render_options: RenderOptions,
+    #[serde(skip_serializing)]
     loaded: bool,
+    // Scene files relative to the asset base whose lights, objects and materials are merged into this scene
+    include: Vec<String>,
     camera: Camera,
+    // Materials which objects can refer to by name
+    materials: BTreeMap<String, Material>,
+    // Objects which instances can place in the scene by name, built once however many instances use them
+    prototypes: BTreeMap<String, Object3D>,
     lights: Vec<Light>,
+    environment: Option<Environment>,
     objects: Vec<Object3D>,
 
     #[serde(skip)]
@@ -26,8 +39,12 @@ impl Default for Scene {
         Self {
             render_options: RenderOptions::default(),
             loaded: false,
+            include: Vec::new(),
             camera: Camera::default(),
+            materials: BTreeMap::new(),
+            prototypes: BTreeMap::new(),
             lights: Vec::new(),
+            environment: None,
             objects: Vec::new(),
 
             textures: HashMap::new(),
@@ -44,55 +61,345 @@ impl Scene {
         }
     }
 
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::default()
+    }
+
+    pub fn add_material(&mut self, name: &str, material: Material) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+    pub fn add_prototype(&mut self, name: &str, object: Object3D) -> Result<(), RaytraceError> {
+        if self.loaded {
+            return Err(RaytraceError::AssetsLoaded);
+        }
+
+        self.prototypes.insert(name.to_string(), object);
+
+        Ok(())
+    }
+
     pub fn add_light(&mut self, light: Light) {
         self.lights.push(light);
     }
 
-    /// # Panics
-    ///
-    /// Will panic if scene assets have been loaded already
-    pub fn add_object(&mut self, object: Object3D) {
+    pub fn set_environment(&mut self, environment: Environment) -> Result<(), RaytraceError> {
         if self.loaded {
-            panic!("objects cannot be added after scene assets have loaded");
+            return Err(RaytraceError::AssetsLoaded);
+        }
+
+        self.environment = Some(environment);
+
+        Ok(())
+    }
+
+    pub fn add_object(&mut self, object: Object3D) -> Result<(), RaytraceError> {
+        if self.loaded {
+            return Err(RaytraceError::AssetsLoaded);
         }
 
         self.objects.push(object);
+
+        Ok(())
     }
 
-    /// # Panics
-    ///
-    /// Will panic if scene assets have been loaded already
-    pub fn load_assets(&mut self, asset_base: &Path) {
+    // Problems which would make the scene fail to load or render, each with the JSON path of the offending
+    // value, checking that asset files exist relative to `asset_base`
+    pub fn validate(&self, asset_base: &Path) -> Vec<Diagnostic> {
+        // Objects may use materials and prototypes from included scenes, which are only known once the includes are
+        // merged
+        let materials = self.include.is_empty().then_some(&self.materials);
+        let prototypes = self.include.is_empty().then_some(&self.prototypes);
+        let mut validator = Validator::new(asset_base, materials, prototypes);
+        self.render_options.validate(&mut validator);
+        validator.field("include", |validator| {
+            for (index, file) in self.include.iter().enumerate() {
+                validator.index(index, |validator| validator.check_file_at(file));
+            }
+        });
+        validator.field("camera", |validator| self.camera.validate(validator));
+        validator.field("materials", |validator| {
+            for (name, material) in &self.materials {
+                validator.field(name, |validator| material.validate(validator));
+            }
+        });
+        validator.field("prototypes", |validator| {
+            for (name, object) in &self.prototypes {
+                validator.field(name, |validator| {
+                    object.validate(validator);
+                    if object.has_instances() {
+                        validator.report("prototypes cannot contain instances");
+                    }
+                });
+            }
+        });
+        validator.field("lights", |validator| {
+            for (index, light) in self.lights.iter().enumerate() {
+                validator.index(index, |validator| light.validate(validator));
+            }
+        });
+        if let Some(environment) = &self.environment {
+            validator.field("environment", |validator| environment.validate(validator));
+        }
+        validator.field("objects", |validator| {
+            for (index, object) in self.objects.iter().enumerate() {
+                validator.index(index, |validator| object.validate(validator));
+            }
+        });
+
+        validator.into_diagnostics()
+    }
+
+    pub fn load_assets(&mut self, asset_base: &Path) -> Result<(), RaytraceError> {
+        self.load_assets_from_cache(asset_base, &mut AssetCache::default())
+    }
+
+    pub fn load_assets_from_cache(
+        &mut self,
+        asset_base: &Path,
+        assets: &mut AssetCache,
+    ) -> Result<(), RaytraceError> {
         if self.loaded {
-            panic!("assets are already loaded for scene");
+            return Err(RaytraceError::AssetsLoaded);
         }
 
-        for object in &mut self.objects {
-            Object3D::load_assets(object, asset_base, &mut self.textures);
+        self.resolve_includes(asset_base)?;
+        for object in self.objects.iter_mut().chain(self.prototypes.values_mut()) {
+            object.resolve_materials(&self.materials)?;
+        }
+        for object in self.objects.iter_mut().chain(self.prototypes.values_mut()) {
+            Object3D::load_assets(object, asset_base, assets)?;
         }
+        self.import_gltf_cameras_and_lights();
+        if let Some(environment) = &mut self.environment {
+            environment.load_assets(asset_base, assets)?;
+        }
+        self.textures = assets.get_textures().clone();
         self.loaded = true;
+
+        Ok(())
+    }
+
+    // Use the cameras and lights which glTF objects at the top level of the scene import from their files
+    fn import_gltf_cameras_and_lights(&mut self) {
+        let aspect_ratio =
+            f64::from(self.render_options.width) / f64::from(self.render_options.height);
+        for object in &mut self.objects {
+            if let Object3D::Gltf(gltf) = object {
+                if let Some(camera) = gltf.take_camera() {
+                    self.camera = camera.into_camera(&self.camera, aspect_ratio);
+                }
+                self.lights.extend(gltf.take_lights());
+            }
+        }
+    }
+
+    // Merge the lights, objects, materials and prototypes of included scene files into the scene, keeping this
+    // scene's materials and prototypes over included ones with the same name
+    pub fn resolve_includes(&mut self, asset_base: &Path) -> Result<(), RaytraceError> {
+        if self.loaded {
+            return Err(RaytraceError::AssetsLoaded);
+        }
+
+        self.merge_includes(asset_base, &mut HashSet::new())
     }
 
-    pub fn build_raytracing_scene(self) -> RaytracingScene {
+    fn merge_includes(
+        &mut self,
+        asset_base: &Path,
+        included: &mut HashSet<PathBuf>,
+    ) -> Result<(), RaytraceError> {
+        for file in mem::take(&mut self.include) {
+            let path = asset_base.join(file);
+            // Include each file once, so shared includes do not duplicate objects and cycles terminate
+            if !included.insert(path.clone()) {
+                continue;
+            }
+
+            let reader = File::open(&path).map_err(|source| RaytraceError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            let mut scene: Scene =
+                serde_json::from_reader(BufReader::new(reader)).map_err(|source| {
+                    RaytraceError::Include {
+                        path: path.clone(),
+                        source,
+                    }
+                })?;
+            scene.merge_includes(asset_base, included)?;
+
+            self.lights.extend(scene.lights);
+            self.objects.extend(scene.objects);
+            for (name, material) in scene.materials {
+                self.materials.entry(name).or_insert(material);
+            }
+            for (name, object) in scene.prototypes {
+                self.prototypes.entry(name).or_insert(object);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn build_raytracing_scene(self) -> Result<RaytracingScene, RaytraceError> {
         RaytracingScene::from_scene(self)
     }
 }
 
+// Fluent construction of a scene description, which can be written out as a scene file with `serde_json`
+#[derive(Debug, Default)]
+pub struct SceneBuilder {
+    scene: Scene,
+}
+
+impl SceneBuilder {
+    #[must_use]
+    pub fn render_options(mut self, render_options: RenderOptions) -> Self {
+        self.scene.render_options = render_options;
+        self
+    }
+
+    #[must_use]
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.scene.render_options.width = width;
+        self.scene.render_options.height = height;
+        self
+    }
+
+    #[must_use]
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u16) -> Self {
+        self.scene.render_options.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    #[must_use]
+    pub fn camera(mut self, camera: Camera) -> Self {
+        self.scene.camera = camera;
+        self
+    }
+
+    #[must_use]
+    pub fn include(mut self, file: &str) -> Self {
+        self.scene.include.push(file.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn material(mut self, name: &str, material: Material) -> Self {
+        self.scene.add_material(name, material);
+        self
+    }
+
+    #[must_use]
+    pub fn prototype(mut self, name: &str, object: impl Into<Object3D>) -> Self {
+        self.scene
+            .prototypes
+            .insert(name.to_string(), object.into());
+        self
+    }
+
+    #[must_use]
+    pub fn light(mut self, light: impl Into<Light>) -> Self {
+        self.scene.lights.push(light.into());
+        self
+    }
+
+    #[must_use]
+    pub fn environment(mut self, environment: impl Into<Environment>) -> Self {
+        self.scene.environment = Some(environment.into());
+        self
+    }
+
+    #[must_use]
+    pub fn object(mut self, object: impl Into<Object3D>) -> Self {
+        self.scene.objects.push(object.into());
+        self
+    }
+
+    pub fn build(self) -> Scene {
+        self.scene
+    }
+}
+
+// Fail before rendering rather than when a ray first hits the texture
+fn check_textures(
+    object: &dyn RaytracingObject,
+    textures: &HashMap<String, Texture>,
+) -> Result<(), RaytraceError> {
+    for texture_path in object.get_material().get_texture_paths() {
+        if !textures.contains_key(texture_path) {
+            return Err(RaytraceError::MissingTexture(texture_path.clone()));
+        }
+    }
+
+    Ok(())
+}
+
 impl RaytracingScene {
-    fn from_scene(scene: Scene) -> Self {
-        let root_transform = Transform::default();
+    fn from_scene(scene: Scene) -> Result<Self, RaytraceError> {
+        let root_transform = AnimatedTransform::default();
+        // Identifiers are only written by ID passes, so other renders do not gather them
+        let track_ids = scene.render_options.passes.iter().any(|pass| pass.is_id());
+        let mut object_ids = ObjectIdMap::default();
+
+        // Prototypes are flattened in their own space, for instances to move rays into it
+        let mut prototypes = BTreeMap::new();
+        for (name, mut object) in scene.prototypes {
+            if object.has_instances() {
+                return Err(RaytraceError::NestedInstance(name));
+            }
+
+            object.resolve_materials(&scene.materials)?;
+            let prototype_objects = object.flatten_to_world(&root_transform);
+            for primitive in &prototype_objects {
+                check_textures(primitive.as_ref(), &scene.textures)?;
+                // The object index of a prototype's objects is replaced by the index of the instance they are hit
+                // through
+                if track_ids {
+                    object_ids.insert(primitive.as_ref(), 0);
+                }
+            }
+            prototypes.insert(name, Arc::new(Prototype::new(prototype_objects)));
+        }
+
         let mut objects = Vec::new();
-        for object in scene.objects {
-            objects.append(&mut object.flatten_to_world(&root_transform));
+        for (object_index, mut object) in scene.objects.into_iter().enumerate() {
+            object.resolve_materials(&scene.materials)?;
+            object.resolve_prototypes(&prototypes)?;
+            for primitive in object.flatten_to_world(&root_transform) {
+                check_textures(primitive.as_ref(), &scene.textures)?;
+                if track_ids {
+                    object_ids.insert(primitive.as_ref(), object_index);
+                }
+                objects.push(primitive);
+            }
+        }
+
+        let mut lights = scene.lights;
+        let emitters: Vec<Emitter> = objects
+            .iter()
+            .filter_map(|object| Emitter::from_object(object.as_ref()))
+            .collect();
+        if !emitters.is_empty() {
+            lights.push(Light::Emissive(Box::new(EmissiveLight::new(emitters))));
         }
+        if let Some(environment) = scene.environment {
+            if let Some(sun) = environment.get_sun_light() {
+                lights.push(Light::Directional(Box::new(sun)));
+            }
+            lights.push(Light::Environment(Box::new(environment)));
+        }
+
         let object_tree = KdTreeAccelerator::new(objects);
 
-        RaytracingScene::new(
+        Ok(RaytracingScene::new(
             scene.render_options,
             scene.camera.into(),
-            scene.lights,
+            lights,
             scene.textures,
             object_tree,
-        )
+            object_ids,
+        ))
     }
 }
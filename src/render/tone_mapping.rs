@@ -0,0 +1,143 @@
+use nalgebra::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// Operator compressing linear radiance into the [0, 1] range of a display
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToneMapping {
+    // Clamp values outside of [0, 1]
+    #[default]
+    None,
+    Reinhard,
+    // Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+    Aces,
+    // Sigmoid in a log encoded, desaturated space following Troy Sobotka's AgX
+    Agx,
+}
+
+impl FromStr for ToneMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ToneMapping::None),
+            "reinhard" => Ok(ToneMapping::Reinhard),
+            "aces" => Ok(ToneMapping::Aces),
+            "agx" => Ok(ToneMapping::Agx),
+            _ => Err(format!("unknown tone mapping operator \"{s}\"")),
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(self, color: Vector3<f64>) -> Vector3<f64> {
+        let color = color.map(|c| c.max(0.0));
+        let mapped = match self {
+            ToneMapping::None => color,
+            ToneMapping::Reinhard => color.map(|c| c / (1.0 + c)),
+            ToneMapping::Aces => aces(color),
+            ToneMapping::Agx => agx(color),
+        };
+
+        mapped.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+fn aces(color: Vector3<f64>) -> Vector3<f64> {
+    #[rustfmt::skip]
+    let input = Matrix3::new(
+        0.59719, 0.35458, 0.04823,
+        0.07600, 0.90834, 0.01566,
+        0.02840, 0.13383, 0.83777,
+    );
+    #[rustfmt::skip]
+    let output = Matrix3::new(
+        1.60475, -0.53108, -0.07367,
+        -0.10208, 1.10813, -0.00605,
+        -0.00327, -0.07276, 1.07602,
+    );
+
+    let color = (input * color).map(|c| {
+        (c * (c + 0.024_578_6) - 0.000_090_537) / (c * (0.983_729 * c + 0.432_951) + 0.238_081)
+    });
+
+    output * color
+}
+
+fn agx(color: Vector3<f64>) -> Vector3<f64> {
+    const MIN_EV: f64 = -12.473_93;
+    const MAX_EV: f64 = 4.026_069;
+
+    #[rustfmt::skip]
+    let inset = Matrix3::new(
+        0.842_479_062_253_094, 0.078_433_6, 0.079_223_745_147_764_3,
+        0.042_328_242_261_012_3, 0.878_468_636_469_772, 0.079_166_127_460_543_4,
+        0.042_375_654_905_705_1, 0.078_433_6, 0.879_142_973_793_104,
+    );
+    #[rustfmt::skip]
+    let outset = Matrix3::new(
+        1.196_879_005_120_17, -0.098_020_881_140_136_8, -0.099_029_744_079_720_5,
+        -0.052_896_851_757_456_2, 1.151_903_129_904_17, -0.098_961_176_844_843_3,
+        -0.052_971_635_514_443_8, -0.098_043_450_117_124_1, 1.151_073_672_641_16,
+    );
+
+    let color = (inset * color).map(|c| {
+        let encoded =
+            (c.max(f64::MIN_POSITIVE).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+
+        // Polynomial approximation of the default contrast curve
+        let x2 = encoded * encoded;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * encoded + 31.96 * x4 - 6.868 * x2 * encoded
+            + 0.4298 * x2
+            + 0.1191 * encoded
+            - 0.002_32
+    });
+
+    // The curve produces display encoded values, so linearize them before the output transform
+    (outset * color).map(|c| c.max(0.0).powf(2.2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_maps_radiance_into_the_display_range() {
+        let operators = [
+            ToneMapping::None,
+            ToneMapping::Reinhard,
+            ToneMapping::Aces,
+            ToneMapping::Agx,
+        ];
+
+        for operator in operators {
+            let black = operator.apply(Vector3::zeros());
+            let bright = operator.apply(Vector3::repeat(1e4));
+            assert!(black.max() < 1e-2, "{:?} does not keep black", operator);
+            assert!(
+                bright.min() > 0.9,
+                "{:?} does not map highlights to white",
+                operator
+            );
+
+            let mut previous = 0.0;
+            for exponent in -8..8 {
+                let value = operator.apply(Vector3::repeat(2f64.powi(exponent))).x;
+                assert!(value >= previous, "{:?} is not monotonic", operator);
+                assert!(value <= 1.0);
+                previous = value;
+            }
+        }
+
+        assert_eq!(
+            ToneMapping::Reinhard.apply(Vector3::repeat(1.0)),
+            Vector3::repeat(0.5)
+        );
+        assert_eq!(
+            ToneMapping::None.apply(Vector3::new(-1.0, 0.5, 2.0)),
+            Vector3::new(0.0, 0.5, 1.0)
+        );
+    }
+}
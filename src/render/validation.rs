@@ -0,0 +1,164 @@
+use crate::core::{Material, Transform};
+use crate::primitives::Object3D;
+use nalgebra::{Unit, Vector3};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+// Problem with a scene found before rendering, at a JSON path such as `objects[2].material.color`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+// Collects the diagnostics of a scene while walking its description, tracking the JSON path of the value
+// being checked
+pub(crate) struct Validator<'a> {
+    asset_base: &'a Path,
+    // Materials objects may refer to by name, or `None` if they are not all known before includes are merged
+    materials: Option<&'a BTreeMap<String, Material>>,
+    // Prototypes instances may refer to by name, or `None` if they are not all known before includes are merged
+    prototypes: Option<&'a BTreeMap<String, Object3D>>,
+    path: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(
+        asset_base: &'a Path,
+        materials: Option<&'a BTreeMap<String, Material>>,
+        prototypes: Option<&'a BTreeMap<String, Object3D>>,
+    ) -> Self {
+        Self {
+            asset_base,
+            materials,
+            prototypes,
+            path: String::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    pub fn has_material(&self, name: &str) -> bool {
+        self.materials
+            .is_none_or(|materials| materials.contains_key(name))
+    }
+
+    pub fn has_prototype(&self, name: &str) -> bool {
+        self.prototypes
+            .is_none_or(|prototypes| prototypes.contains_key(name))
+    }
+
+    fn nested(&mut self, segment: &str, validate: impl FnOnce(&mut Self)) {
+        let length = self.path.len();
+        self.path.push_str(segment);
+        validate(self);
+        self.path.truncate(length);
+    }
+
+    pub fn field(&mut self, key: &str, validate: impl FnOnce(&mut Self)) {
+        let segment = if self.path.is_empty() {
+            key.to_string()
+        } else {
+            format!(".{key}")
+        };
+        self.nested(&segment, validate);
+    }
+
+    pub fn index(&mut self, index: usize, validate: impl FnOnce(&mut Self)) {
+        self.nested(&format!("[{index}]"), validate);
+    }
+
+    // Report a problem with the value at the current path
+    pub fn report(&mut self, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            path: self.path.clone(),
+            message: message.into(),
+        });
+    }
+
+    pub fn check(&mut self, key: &str, is_valid: bool, message: &str) {
+        if !is_valid {
+            self.field(key, |validator| validator.report(message));
+        }
+    }
+
+    pub fn check_finite(&mut self, key: &str, value: f64) {
+        self.check(key, value.is_finite(), "must be a finite number");
+    }
+
+    pub fn check_positive(&mut self, key: &str, value: f64) {
+        self.check(key, value > 0.0 && value.is_finite(), "must be positive");
+    }
+
+    pub fn check_non_negative(&mut self, key: &str, value: f64) {
+        self.check(
+            key,
+            value >= 0.0 && value.is_finite(),
+            "must not be negative",
+        );
+    }
+
+    pub fn check_range(&mut self, key: &str, value: f64, range: RangeInclusive<f64>) {
+        if !range.contains(&value) {
+            let message = format!("must be between {} and {}", range.start(), range.end());
+            self.field(key, |validator| validator.report(message));
+        }
+    }
+
+    pub fn check_color(&mut self, key: &str, color: &Vector3<f64>) {
+        self.check(
+            key,
+            color.iter().all(|c| c.is_finite() && *c >= 0.0),
+            "must be a color with finite, non-negative components",
+        );
+    }
+
+    pub fn check_direction(&mut self, key: &str, direction: &Unit<Vector3<f64>>) {
+        let length = direction.as_ref().magnitude();
+        self.check(
+            key,
+            length.is_finite() && length > f64::EPSILON,
+            "must have a non-zero length",
+        );
+    }
+
+    pub fn check_transform(&mut self, key: &str, transform: &Transform) {
+        self.check(key, transform.is_invertible(), "must be invertible");
+    }
+
+    // Transforms of an object, which must both be invertible for rays to be moved into its space
+    pub fn check_transforms(&mut self, transform: &Transform, end_transform: Option<&Transform>) {
+        self.check_transform("transform", transform);
+        if let Some(end_transform) = end_transform {
+            self.check_transform("end_transform", end_transform);
+        }
+    }
+
+    pub fn check_file(&mut self, key: &str, file: &str) {
+        self.field(key, |validator| validator.check_file_at(file));
+    }
+
+    // Check that the file at the current path exists relative to the asset base
+    pub fn check_file_at(&mut self, file: &str) {
+        let path = self.asset_base.join(file);
+        if !path.is_file() {
+            self.report(format!("file \"{}\" does not exist", path.display()));
+        }
+    }
+}
@@ -29,8 +29,12 @@ fn main() {
         output_filename.set_extension("png");
 
         let mut scene: Scene = serde_json::from_reader(scene_file).expect("failed to parse scene");
-        scene.load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")));
-        let scene = scene.build_raytracing_scene();
+        scene
+            .load_assets(scene_path.parent().unwrap_or_else(|| Path::new("")))
+            .expect("failed to load assets");
+        let scene = scene
+            .build_raytracing_scene()
+            .expect("failed to build scene");
 
         let mut duration_sum = Duration::ZERO;
         let mut ray_count_sum = 0;
@@ -44,7 +48,9 @@ fn main() {
             print!("\u{2514} Iteration {}: tracing...", i + 1);
             io::stdout().flush().unwrap();
 
-            let (image, cast_timings, stats) = scene.raytrace_to_image(false);
+            let (image, cast_timings, stats) = scene
+                .raytrace_to_image(false)
+                .expect("failed to render scene");
             let mut iteration_duration = cast_timings.ray_casting_duration;
             if let Some(post_processing_duration) = cast_timings.post_processing_duration {
                 iteration_duration += post_processing_duration;
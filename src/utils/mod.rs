@@ -5,9 +5,12 @@ mod sampling;
 use nalgebra::Vector3;
 use num_traits::Float;
 
-pub use physical_material_equations::{fresnel, geometry_function, ndf};
+pub use physical_material_equations::{fresnel, geometry_function, ndf, smith_masking};
 pub use rays::{reflect, refract};
-pub use sampling::{cosine_sample_hemisphere, uniform_sample_cone};
+pub use sampling::{
+    concentric_sample_disk, cosine_sample_hemisphere, ggx_sample_visible_microfacet,
+    power_heuristic, sample_regular_polygon, uniform_sample_sphere, uniform_sample_spherical_cap,
+};
 
 const ALPHA_BIT_MASK: u32 = 255 << 24;
 
@@ -18,14 +21,35 @@ pub fn to_argb_u32(rgb: Vector3<f64>) -> u32 {
     ALPHA_BIT_MASK | r << 16 | g << 8 | b
 }
 
-pub fn gamma_correct(color: Vector3<f64>, gamma: f64) -> Vector3<f64> {
-    color.map(|c| c.powf(1.0 / gamma))
+// Encode a linear color in [0, 1] with the sRGB transfer function
+pub fn srgb_oetf(color: Vector3<f64>) -> Vector3<f64> {
+    color.map(|c| {
+        if c <= 0.003_130_8 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+// Relative luminance of a linear Rec. 709 color
+pub fn luminance(color: Vector3<f64>) -> f64 {
+    color.dot(&Vector3::new(0.2126, 0.7152, 0.0722))
 }
 
 pub fn lerp<F: Float>(x0: F, x1: F, t: F) -> F {
     x0 - x0 * t + x1 * t
 }
 
+// Hermite interpolation between 0 and 1 as `x` moves from `edge0` to `edge1`
+pub fn smoothstep<F: Float>(edge0: F, edge1: F, x: F) -> F {
+    let t = ((x - edge0) / (edge1 - edge0)).max(F::zero()).min(F::one());
+    let two = F::one() + F::one();
+    let three = two + F::one();
+
+    t * t * (three - two * t)
+}
+
 pub fn remap_value<F: Float>(num: F, domain: (F, F), range: (F, F)) -> F {
     assert!(domain.0 < domain.1, "domain values must be of the form (min, max) - range values can be swapped for this behavior");
 
@@ -63,6 +87,19 @@ mod test {
         assert_eq!(to_argb_u32(Vector3::from([1.0, 0.0, 1.0])), color);
     }
 
+    #[test]
+    fn it_encodes_srgb() {
+        let encoded = srgb_oetf(Vector3::new(0.0, 0.001, 1.0));
+        assert_eq!(encoded.x, 0.0);
+        assert!((encoded.y - 0.01292).abs() < 1e-10);
+        assert!((encoded.z - 1.0).abs() < 1e-10);
+
+        // The linear segment and power curve meet at the threshold
+        let below = srgb_oetf(Vector3::repeat(0.003_130_8 - 1e-9)).x;
+        let above = srgb_oetf(Vector3::repeat(0.003_130_8 + 1e-9)).x;
+        assert!((below - above).abs() < 1e-6);
+    }
+
     #[test]
     fn it_maps_numbers() {
         assert_eq!(remap_value(1.0, (0.0, 1.0), (0.0, 5.0)), 5.0);
@@ -73,6 +110,16 @@ mod test {
         assert_eq!(remap_value(2.0, (0.0, 1.0), (0.0, 10.0)), 20.0);
     }
 
+    #[test]
+    fn it_interpolates_smoothly() {
+        assert_eq!(smoothstep(0.0, 1.0, -1.0), 0.0);
+        assert_eq!(smoothstep(0.0, 1.0, 0.0), 0.0);
+        assert_eq!(smoothstep(0.0, 1.0, 0.5), 0.5);
+        assert_eq!(smoothstep(0.0, 1.0, 1.0), 1.0);
+        assert_eq!(smoothstep(0.0, 1.0, 2.0), 1.0);
+        assert_eq!(smoothstep(2.0, 4.0, 3.0), 0.5);
+    }
+
     #[test]
     fn it_solves_quadratic_eqs() {
         assert_eq!(quadratic(1.0, 2.0, 1.0), Some((-1.0, -1.0)));
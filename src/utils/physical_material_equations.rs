@@ -24,6 +24,14 @@ pub fn geometry_function(n_dot_v: f64, n_dot_l: f64, roughness: f64) -> f64 {
     ggx1 * ggx2
 }
 
+// Smith's masking function for the Trowbridge-Reitz GGX distribution, matching visible normal sampling
+pub fn smith_masking(n_dot_v: f64, roughness: f64) -> f64 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+
+    2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt())
+}
+
 // Fresnel-Schlick equation
 pub fn fresnel(n_dot_v: f64, base_reflectivity: Vector3<f64>) -> Vector3<f64> {
     base_reflectivity + (Vector3::repeat(1.0) - base_reflectivity) * (1.0 - n_dot_v).powf(5.0)
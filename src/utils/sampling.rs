@@ -3,15 +3,16 @@ use rand::Rng;
 use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, TAU};
 use std::f64::EPSILON;
 
-fn concentric_sample_disk() -> Point2<f64> {
-    let rnd: Vector2<f64> = 2.0 * Vector2::new_random() - Vector2::from([1.0, 1.0]);
+// Sample the unit disk using Shirley's concentric mapping
+pub fn concentric_sample_disk<R: Rng>(rng: &mut R) -> Point2<f64> {
+    let rnd: Vector2<f64> = 2.0 * Vector2::new(rng.gen(), rng.gen()) - Vector2::from([1.0, 1.0]);
 
     if rnd.x == 0.0 && rnd.y == 0.0 {
         return Point2::origin();
     }
 
     let (r, theta) = if rnd.x.abs() > rnd.y.abs() {
-        (rnd.x, FRAC_PI_2 * (rnd.y / rnd.x))
+        (rnd.x, FRAC_PI_4 * (rnd.y / rnd.x))
     } else {
         (rnd.y, FRAC_PI_2 - FRAC_PI_4 * (rnd.x / rnd.y))
     };
@@ -19,56 +20,135 @@ fn concentric_sample_disk() -> Point2<f64> {
     r * Point2::from([theta.cos(), theta.sin()])
 }
 
-// Sample a hemisphere with a cosine weight in the direction of the given direction using Malley's method
-pub fn cosine_sample_hemisphere(direction: &Unit<Vector3<f64>>) -> Unit<Vector3<f64>> {
-    let p = concentric_sample_disk();
-    let p = Point3::from([p.x, p.y, (1.0 - p.x * p.x - p.y * p.y).sqrt()]);
+// Uniformly sample a regular polygon inscribed in the unit circle, with its first vertex `rotation` radians from
+// the x axis
+pub fn sample_regular_polygon<R: Rng>(sides: u8, rotation: f64, rng: &mut R) -> Point2<f64> {
+    let sector_angle = TAU / f64::from(sides);
+    let start_angle = rotation + sector_angle * f64::from(rng.gen_range(0..sides));
+    let end_angle = start_angle + sector_angle;
+
+    // Sample the triangle between the center and one edge of the polygon
+    let (mut u, mut v): (f64, f64) = (rng.gen(), rng.gen());
+    if u + v > 1.0 {
+        u = 1.0 - u;
+        v = 1.0 - v;
+    }
 
-    let w = direction.into_inner();
-    let u = if w.x.abs() > EPSILON {
+    Point2::from([
+        u * start_angle.cos() + v * end_angle.cos(),
+        u * start_angle.sin() + v * end_angle.sin(),
+    ])
+}
+
+// Build two unit vectors which form an orthonormal basis with the given direction
+fn orthonormal_basis(direction: &Unit<Vector3<f64>>) -> (Vector3<f64>, Vector3<f64>) {
+    let u = if direction.x.abs() > EPSILON {
         direction.cross(&Vector3::y_axis())
     } else {
         direction.cross(&Vector3::x_axis())
     };
+    let u = u.normalize();
     let v = direction.cross(&u);
 
-    Unit::new_normalize(u * p.x + v * p.y + w * p.z)
+    (u, v)
 }
 
-// Sample a cone in the direction of the given direction
-pub fn uniform_sample_cone(direction: &Unit<Vector3<f64>>, max_angle: f64) -> Unit<Vector3<f64>> {
-    debug_assert!((0.0..=FRAC_PI_2).contains(&max_angle));
-
-    if max_angle < EPSILON {
-        return *direction;
-    }
+// Sample a hemisphere with a cosine weight in the direction of the given direction using Malley's method
+pub fn cosine_sample_hemisphere<R: Rng>(
+    direction: &Unit<Vector3<f64>>,
+    rng: &mut R,
+) -> Unit<Vector3<f64>> {
+    let p = concentric_sample_disk(rng);
+    let p = Point3::from([p.x, p.y, (1.0 - p.x * p.x - p.y * p.y).max(0.0).sqrt()]);
+
+    let (u, v) = orthonormal_basis(direction);
+    let w = direction.into_inner();
 
-    let mut rng = rand::thread_rng();
+    Unit::new_normalize(u * p.x + v * p.y + w * p.z)
+}
 
-    let theta = (rng.gen::<f64>()).acos();
-    let theta = theta * max_angle / FRAC_PI_2;
-    let z = theta.cos();
-    let radius = theta.sin();
+// Sample a microfacet normal around the given normal from the distribution of GGX normals visible from `view_dir`
+pub fn ggx_sample_visible_microfacet<R: Rng>(
+    normal: &Unit<Vector3<f64>>,
+    view_dir: &Unit<Vector3<f64>>,
+    roughness: f64,
+    rng: &mut R,
+) -> Unit<Vector3<f64>> {
+    let alpha = roughness * roughness;
+
+    // Transform the view direction to the local frame and stretch it to the hemisphere configuration
+    let (u, v) = orthonormal_basis(normal);
+    let w = normal.into_inner();
+    let local_view = Vector3::new(
+        alpha * view_dir.dot(&u),
+        alpha * view_dir.dot(&v),
+        view_dir.dot(&w).max(0.0),
+    )
+    .normalize();
+
+    let len_squared = local_view.x * local_view.x + local_view.y * local_view.y;
+    let t1 = if len_squared > 0.0 {
+        Vector3::new(-local_view.y, local_view.x, 0.0) / len_squared.sqrt()
+    } else {
+        Vector3::x()
+    };
+    let t2 = local_view.cross(&t1);
 
+    // Sample the projected area of the visible hemisphere
+    let radius = rng.gen::<f64>().sqrt();
     let phi = rng.gen::<f64>() * TAU;
+    let p1 = radius * phi.cos();
+    let p2 = radius * phi.sin();
+    let blend = 0.5 * (1.0 + local_view.z);
+    let p2 = (1.0 - blend) * (1.0 - p1 * p1).max(0.0).sqrt() + blend * p2;
+    let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    let hemisphere_normal = t1 * p1 + t2 * p2 + local_view * p3;
+
+    // Unstretch back to the ellipsoid configuration
+    Unit::new_normalize(
+        u * alpha * hemisphere_normal.x
+            + v * alpha * hemisphere_normal.y
+            + w * hemisphere_normal.z.max(f64::EPSILON),
+    )
+}
 
-    let u = direction.cross(&Vector3::z_axis());
-    let mag = u.magnitude();
-    if mag < EPSILON {
-        return Unit::new_normalize(Vector3::new(
-            radius * phi.cos(),
-            radius * phi.sin(),
-            direction.z.signum() * z,
-        ));
-    }
+// Sample the directions within a cone uniformly by solid angle, given the cosine of the cone's half angle
+pub fn uniform_sample_spherical_cap<R: Rng>(
+    direction: &Unit<Vector3<f64>>,
+    cos_max_angle: f64,
+    rng: &mut R,
+) -> Unit<Vector3<f64>> {
+    let z = 1.0 - rng.gen::<f64>() * (1.0 - cos_max_angle);
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = rng.gen::<f64>() * TAU;
 
+    let (u, v) = orthonormal_basis(direction);
     let w = direction.into_inner();
-    let u = u.normalize();
-    let v = direction.cross(&u).normalize();
 
     Unit::new_normalize(u * radius * phi.cos() + v * radius * phi.sin() + w * z)
 }
 
+// Sample a direction uniformly over the unit sphere
+pub fn uniform_sample_sphere<R: Rng>(rng: &mut R) -> Unit<Vector3<f64>> {
+    let z = 1.0 - 2.0 * rng.gen::<f64>();
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let phi = rng.gen::<f64>() * TAU;
+
+    Unit::new_normalize(Vector3::new(radius * phi.cos(), radius * phi.sin(), z))
+}
+
+// Veach's power heuristic (beta = 2) for weighting a sample from `f` against one from `g`
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -78,9 +158,11 @@ mod test {
 
     #[test]
     fn it_samples_a_hemisphere() {
+        let mut rng = rand::thread_rng();
+
         for _ in 0..10_000 {
             let vec: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
-            let sampled = cosine_sample_hemisphere(&vec);
+            let sampled = cosine_sample_hemisphere(&vec, &mut rng);
             let dot = sampled.dot(&vec);
 
             assert_le!(dot.min(1.0).acos(), FRAC_PI_2 + PRECISION);
@@ -88,103 +170,91 @@ mod test {
     }
 
     #[test]
-    fn it_samples_a_cone() {
+    fn it_samples_a_visible_microfacet_normal() {
         let mut rng = rand::thread_rng();
 
         for _ in 0..10_000 {
-            let direction: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
-            let max_angle = rng.gen::<f64>() * FRAC_PI_2;
-            let sampled = uniform_sample_cone(&direction, max_angle);
-            let dot = sampled.dot(&direction);
+            let normal: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
+            let view_dir = cosine_sample_hemisphere(&normal, &mut rng);
+            let sampled = ggx_sample_visible_microfacet(&normal, &view_dir, rng.gen(), &mut rng);
 
-            assert_le!(dot.min(1.0).acos(), max_angle + PRECISION);
+            assert_le!(sampled.dot(&normal).min(1.0).acos(), FRAC_PI_2 + PRECISION);
+            assert_le!(-PRECISION, sampled.dot(&view_dir));
         }
     }
 
     #[test]
-    fn it_samples_a_cone_z_direction() {
+    fn it_samples_a_spherical_cap() {
         let mut rng = rand::thread_rng();
 
-        // +z, random max angle
-        let direction = Vector3::z_axis();
-        for _ in 0..10_000 {
-            let max_angle = rng.gen::<f64>() * FRAC_PI_2;
-            let sampled = uniform_sample_cone(&direction, max_angle);
-            let dot = sampled.dot(&direction);
-
-            assert_le!(dot.min(1.0).acos(), max_angle + PRECISION);
-        }
-
-        // -z, random max angle
-        let direction = -Vector3::z_axis();
         for _ in 0..10_000 {
-            let max_angle = rng.gen::<f64>() * FRAC_PI_2;
-            let sampled = uniform_sample_cone(&direction, max_angle);
-            let dot = sampled.dot(&direction);
+            let direction: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
+            let cos_max_angle = rng.gen::<f64>() * 2.0 - 1.0;
+            let sampled = uniform_sample_spherical_cap(&direction, cos_max_angle, &mut rng);
 
-            assert_le!(dot.min(1.0).acos(), max_angle + PRECISION);
+            assert_le!(cos_max_angle, sampled.dot(&direction) + PRECISION);
         }
     }
 
     #[test]
-    fn it_samples_a_cone_angle_edges() {
-        // random direction, 0 max angle
-        let zero_angle = 0.0;
-        for _ in 0..10_000 {
-            let direction: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
-            let sampled = uniform_sample_cone(&direction, zero_angle);
-            let dot = sampled.dot(&direction);
+    fn it_samples_a_disk() {
+        let mut rng = rand::thread_rng();
 
-            assert_le!(dot.min(1.0).acos(), zero_angle + PRECISION);
-        }
+        let mut horizontal_samples = 0;
 
-        // random direction, PI/2 max angle
         for _ in 0..10_000 {
-            let direction: Unit<Vector3<f64>> = Unit::new_normalize(Vector3::new_random());
-            let sampled = uniform_sample_cone(&direction, FRAC_PI_2);
-            let dot = sampled.dot(&direction);
+            let sampled = concentric_sample_disk(&mut rng);
+            assert_le!(sampled.coords.magnitude(), 1.0 + PRECISION);
 
-            assert_le!(dot.min(1.0).acos(), FRAC_PI_2 + PRECISION);
+            if sampled.x.abs() > sampled.y.abs() {
+                horizontal_samples += 1;
+            }
         }
+
+        // Samples are spread evenly between the left and right wedges and the top and bottom wedges
+        assert_le!((horizontal_samples - 5_000_i32).abs(), 300);
     }
 
     #[test]
-    fn it_samples_a_cone_angle_edges_z_direction() {
-        let zero_angle = 0.0;
+    fn it_samples_a_regular_polygon() {
+        let mut rng = rand::thread_rng();
 
-        let positive_z = Vector3::z_axis();
-        let negative_z = -Vector3::z_axis();
+        let rotation = 0.3;
 
-        // +z, 0 max angle
-        for _ in 0..10_000 {
-            let sampled = uniform_sample_cone(&positive_z, zero_angle);
-            let dot = sampled.dot(&positive_z);
+        for sides in 3..8 {
+            let sector_angle = TAU / f64::from(sides);
+            let apothem = (sector_angle / 2.0).cos();
 
-            assert_le!(dot.min(1.0).acos(), zero_angle + PRECISION);
-        }
+            for _ in 0..1_000 {
+                let sampled = sample_regular_polygon(sides, rotation, &mut rng);
 
-        // -z, 0 max angle
-        for _ in 0..10_000 {
-            let sampled = uniform_sample_cone(&negative_z, zero_angle);
-            let dot = sampled.dot(&negative_z);
+                // Project onto the normal of the edge bounding the sample's sector
+                let angle = (sampled.y.atan2(sampled.x) - rotation).rem_euclid(TAU);
+                let edge_angle = rotation + ((angle / sector_angle).floor() + 0.5) * sector_angle;
+                let distance = sampled.x * edge_angle.cos() + sampled.y * edge_angle.sin();
 
-            assert_le!(dot.min(1.0).acos(), zero_angle + PRECISION);
+                assert_le!(distance, apothem + PRECISION);
+            }
         }
+    }
 
-        // +z, PI/2 max angle
-        for _ in 0..10_000 {
-            let sampled = uniform_sample_cone(&positive_z, FRAC_PI_2);
-            let dot = sampled.dot(&positive_z);
-
-            assert_le!(dot.min(1.0).acos(), FRAC_PI_2 + PRECISION);
-        }
+    #[test]
+    fn it_samples_a_sphere() {
+        let mut rng = rand::thread_rng();
 
-        // -z, PI/2 max angle
         for _ in 0..10_000 {
-            let sampled = uniform_sample_cone(&negative_z, FRAC_PI_2);
-            let dot = sampled.dot(&negative_z);
+            let sampled = uniform_sample_sphere(&mut rng);
 
-            assert_le!(dot.min(1.0).acos(), FRAC_PI_2 + PRECISION);
+            assert_le!((sampled.magnitude() - 1.0).abs(), PRECISION);
         }
     }
+
+    #[test]
+    fn it_weights_samples_with_the_power_heuristic() {
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 1.0), 0.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert_eq!(power_heuristic(3.0, 1.0), 0.9);
+    }
 }